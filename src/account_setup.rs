@@ -0,0 +1,102 @@
+//! Leverage, margin mode and position mode checks.
+//!
+//! The bot sizes positions assuming a known position mode (one-way, or hedge
+//! when `HEDGE_MODE` is on) and a known leverage, so it sets both explicitly
+//! instead of inheriting whatever was last chosen in the exchange UI. A symbol
+//! is only traded once its settings have been read back and match what was
+//! requested.
+
+use crate::bybit_api::BybitClient;
+use crate::config::{HEDGE_MODE, MARGIN_MODE, MAX_EFFECTIVE_LEVERAGE, TARGET_LEVERAGE};
+
+/// Leverage to configure for a symbol: the target, capped by our own
+/// effective-leverage limit and the instrument maximum. Whole numbers only.
+pub fn leverage_for(max_leverage: f64) -> f64 {
+    TARGET_LEVERAGE
+        .min(MAX_EFFECTIVE_LEVERAGE)
+        .min(max_leverage)
+        .max(1.0)
+        .floor()
+}
+
+/// Startup check of the account-wide margin mode; switches it if needed.
+pub async fn configure_account(bybit: &BybitClient) -> Result<(), String> {
+    let mode = bybit
+        .get_margin_mode()
+        .await
+        .map_err(|e| format!("margin mode query failed: {}", e))?;
+    if mode == MARGIN_MODE {
+        log::info!("Margin mode confirmed: {}", mode);
+        return Ok(());
+    }
+
+    log::warn!("Margin mode is {} — switching to {}", mode, MARGIN_MODE);
+    bybit
+        .set_margin_mode(MARGIN_MODE)
+        .await
+        .map_err(|e| format!("switch to {} failed: {}", MARGIN_MODE, e))?;
+
+    let mode = bybit
+        .get_margin_mode()
+        .await
+        .map_err(|e| format!("margin mode re-check failed: {}", e))?;
+    if mode != MARGIN_MODE {
+        return Err(format!("margin mode still {} after switching to {}", mode, MARGIN_MODE));
+    }
+    log::info!("Margin mode confirmed: {}", mode);
+    Ok(())
+}
+
+/// Puts a symbol in the configured position mode at the configured leverage
+/// and reads the settings back. Returns the confirmed leverage.
+pub async fn prepare_symbol(bybit: &BybitClient, symbol: &str) -> Result<f64, String> {
+    let max = bybit
+        .get_max_leverage(symbol)
+        .await
+        .map_err(|e| format!("instrument info failed: {}", e))?;
+    let leverage = leverage_for(max);
+
+    let current = bybit
+        .get_symbol_settings(symbol)
+        .await
+        .map_err(|e| format!("position settings query failed: {}", e))?;
+
+    if current.hedge_mode != HEDGE_MODE {
+        log::warn!("[{}] Position mode is {} — switching to {}",
+            symbol, mode_label(current.hedge_mode), mode_label(HEDGE_MODE));
+        bybit
+            .switch_position_mode(symbol, HEDGE_MODE)
+            .await
+            .map_err(|e| format!("switch to {} failed: {}", mode_label(HEDGE_MODE), e))?;
+    }
+    if current.leverage != leverage {
+        bybit
+            .set_leverage(symbol, leverage)
+            .await
+            .map_err(|e| format!("set leverage {}× failed: {}", leverage, e))?;
+    }
+
+    // Read back: never trust the write alone
+    let confirmed = bybit
+        .get_symbol_settings(symbol)
+        .await
+        .map_err(|e| format!("position settings re-check failed: {}", e))?;
+    if confirmed.hedge_mode != HEDGE_MODE {
+        return Err(format!("still in {} mode", mode_label(confirmed.hedge_mode)));
+    }
+    if confirmed.leverage != leverage {
+        return Err(format!(
+            "leverage is {}× after setting {}× (instrument max {}×)",
+            confirmed.leverage, leverage, max
+        ));
+    }
+    log::info!(
+        "[{}] Account settings confirmed: {}, {}× (max {}×){}",
+        symbol, mode_label(HEDGE_MODE), leverage, max, if confirmed.isolated { ", isolated" } else { "" }
+    );
+    Ok(leverage)
+}
+
+fn mode_label(hedge: bool) -> &'static str {
+    if hedge { "hedge" } else { "one-way" }
+}
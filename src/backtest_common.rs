@@ -0,0 +1,304 @@
+//! Pieces shared by the backtest, optimize, convert and download binaries
+//! (`#[path]` include — the live bot does not use it): timeframe parsing,
+//! the cost model (fees, slippage, funding), the entry confirmation filters,
+//! candle loading from CSV or the binary store, instrument specs, a seeded
+//! RNG and date formatting.
+//!
+//! Both simulators must charge exactly the same costs and filter entries the
+//! same way, so everything that feeds a signal, a fill price or a PnL lives
+//! here rather than in either binary.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::{candle_store, data_quality};
+
+// ── Timeframes ────────────────────────────────────────────────────────────────
+
+/// "15m" → 900 000, "4H" → 14 400 000, "1D" → 86 400 000.
+pub fn timeframe_ms(tf: &str) -> Option<i64> {
+    let (n, unit) = tf.split_at(tf.len().checked_sub(1)?);
+    let n: i64 = n.parse().ok().filter(|n| *n > 0)?;
+    match unit {
+        "m"       => Some(n * 60_000),
+        "h" | "H" => Some(n * 3_600_000),
+        "d" | "D" => Some(n * 86_400_000),
+        _         => None,
+    }
+}
+
+/// clap value parser for `timeframe_ms` strings.
+pub fn parse_timeframe(tf: &str) -> Result<String, String> {
+    timeframe_ms(tf).map(|_| tf.to_string()).ok_or_else(|| format!("timeframe inválido: {} (ej. 15m, 4H, 1D)", tf))
+}
+
+// ── Cost model ────────────────────────────────────────────────────────────────
+// Market entries, stop-market SL and time stops pay taker + slippage; the TP
+// is a reduce-only limit: maker, no slippage. Funding every 8h from
+// {data-dir}/{SYMBOL}_funding.csv (download bin); none is charged without it.
+// Fees and slippage come from --taker-fee / --maker-fee / --slippage.
+
+pub const TAKER_FEE: f64 = 0.00055;
+pub const MAKER_FEE: f64 = 0.0002;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Slippage {
+    None,
+    FixedTicks(f64), // fixed ticks per fill
+    AtrFrac(f64),    // fraction of ATR, rounded to ticks (min. 1 tick)
+}
+pub const SLIPPAGE: Slippage = Slippage::FixedTicks(2.0);
+
+impl std::fmt::Display for Slippage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Slippage::None          => write!(f, "none"),
+            Slippage::FixedTicks(n) => write!(f, "ticks:{}", n),
+            Slippage::AtrFrac(k)    => write!(f, "atr:{}", k),
+        }
+    }
+}
+
+/// clap value parser for `--slippage`: `none`, `ticks:N` or `atr:K`.
+pub fn parse_slippage(s: &str) -> Result<Slippage, String> {
+    let err = || format!("slippage inválido: {} (none, ticks:N o atr:K)", s);
+    if s.eq_ignore_ascii_case("none") {
+        return Ok(Slippage::None);
+    }
+    let (kind, value) = s.split_once(':').ok_or_else(err)?;
+    let value: f64 = value.trim().parse().map_err(|_| err())?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(err());
+    }
+    match kind.trim().to_ascii_lowercase().as_str() {
+        "ticks" => Ok(Slippage::FixedTicks(value)),
+        "atr"   => Ok(Slippage::AtrFrac(value)),
+        _       => Err(err()),
+    }
+}
+
+pub struct Costs {
+    pub tick:      f64,
+    pub funding:   Vec<(i64, f64)>, // (timestamp_ms, rate), sorted
+    pub taker_fee: f64,             // fraction of notional
+    pub maker_fee: f64,
+    pub slippage:  Slippage,
+}
+
+impl Costs {
+    /// Adverse slippage per fill, in price.
+    #[inline]
+    pub fn slippage(&self, atr: f64) -> f64 {
+        match self.slippage {
+            Slippage::None          => 0.0,
+            Slippage::FixedTicks(n) => n * self.tick,
+            Slippage::AtrFrac(k)    => ((k * atr / self.tick).round() * self.tick).max(self.tick),
+        }
+    }
+}
+
+/// `{SYMBOL}_funding.csv` as `(timestamp_ms, rate)`, sorted. Empty if the
+/// file does not exist.
+pub fn load_funding(path: &Path) -> Vec<(i64, f64)> {
+    let Ok(file) = File::open(path) else { return Vec::new() };
+    let mut out: Vec<(i64, f64)> = BufReader::new(file).lines().skip(1)
+        .map_while(|l| l.ok())
+        .filter_map(|line| {
+            let f: Vec<&str> = line.split(',').collect();
+            Some((f.first()?.parse().ok()?, f.get(2)?.parse().ok()?))
+        })
+        .collect();
+    out.sort_by_key(|r| r.0);
+    out
+}
+
+/// Sum of the funding rates settled in (from_ms, to_ms].
+#[inline]
+pub fn funding_rate_sum(funding: &[(i64, f64)], from_ms: i64, to_ms: i64) -> f64 {
+    let a = funding.partition_point(|r| r.0 <= from_ms);
+    let b = funding.partition_point(|r| r.0 <= to_ms);
+    funding[a..b.max(a)].iter().map(|r| r.1).sum()
+}
+
+// ── Confirmation filters ──────────────────────────────────────────────────────
+// The live bot's entry filters (fvg_detector: close vs the BB middle, SMA(20)
+// bias with a threshold, break of structure over N bars), evaluated on the one
+// series the simulators run on instead of on the live entry / bias / structure
+// timeframes. 0 / None = filter off.
+
+/// Whether candle `i` passes the active filters for a long (`is_long`) or short
+/// entry. Without enough history for an active filter, it does not.
+pub fn confirmed(
+    candles: &[Candle], i: usize, is_long: bool, bb_period: usize, bias_threshold: Option<f64>, bos_window: usize,
+) -> bool {
+    let close = candles[i].close;
+    let sma = |period: usize| -> Option<f64> {
+        if period == 0 || i + 1 < period { return None; }
+        Some(candles[i + 1 - period..=i].iter().map(|c| c.close).sum::<f64>() / period as f64)
+    };
+
+    if bb_period > 0 {
+        let Some(mid) = sma(bb_period) else { return false };
+        if is_long && close <= mid || !is_long && close >= mid { return false; }
+    }
+    if let Some(t) = bias_threshold {
+        let Some(sma20) = sma(20) else { return false };
+        if is_long && close <= sma20 * (1.0 + t) || !is_long && close >= sma20 * (1.0 - t) { return false; }
+    }
+    if bos_window > 0 {
+        if i < bos_window { return false; }
+        let window = &candles[i - bos_window..i];
+        let broken = if is_long {
+            close > window.iter().map(|c| c.high).fold(f64::NEG_INFINITY, f64::max)
+        } else {
+            close < window.iter().map(|c| c.low).fold(f64::INFINITY, f64::min)
+        };
+        if !broken { return false; }
+    }
+    true
+}
+
+// ── Instruments ───────────────────────────────────────────────────────────────
+
+/// Exchange specs of one symbol, from `/v5/market/instruments-info`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InstrumentSpec {
+    pub tick_size: f64,
+}
+
+/// `{data-dir}/instruments.json`, written by the download bin.
+pub fn instruments_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("instruments.json")
+}
+
+pub fn load_instruments(data_dir: &Path) -> std::io::Result<BTreeMap<String, InstrumentSpec>> {
+    let text = std::fs::read_to_string(instruments_path(data_dir))?;
+    serde_json::from_str(&text).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Writes the specs atomically (tmp file + rename).
+pub fn save_instruments(data_dir: &Path, specs: &BTreeMap<String, InstrumentSpec>) -> std::io::Result<()> {
+    let path = instruments_path(data_dir);
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(specs).map_err(std::io::Error::other)?)?;
+    std::fs::rename(&tmp, &path)
+}
+
+/// Instrument specs for the simulators; exits with a hint to run `download`
+/// when the file is missing or unreadable, since ticks drive slippage.
+pub fn require_instruments(data_dir: &Path) -> BTreeMap<String, InstrumentSpec> {
+    load_instruments(data_dir).unwrap_or_else(|e| {
+        eprintln!("  ✖  {:?}: {} — ejecuta `download` para guardar los instrumentos",
+                  instruments_path(data_dir), e);
+        std::process::exit(1);
+    })
+}
+
+// ── Candles: CSV or binary store ──────────────────────────────────────────────
+
+#[derive(Clone, Debug)]
+pub struct Candle { pub ts_ms: i64, pub open: f64, pub high: f64, pub low: f64, pub close: f64, pub volume: f64 }
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Store { Auto, Csv, Bin }
+
+/// Candles of {SYMBOL}_{tf} with open time in [from_ms, to_ms), validated
+/// against `bar_ms` (see data_quality): sorted, without duplicates or
+/// unreadable rows. Only the requested range of the binary store is decoded;
+/// `Store::Auto` falls back to the CSV when it is newer than the store.
+/// None if there is no file; data errors end the process unless
+/// `allow_dirty`, gaps and zero volume are only reported.
+pub fn load_candles(
+    data_dir: &Path, symbol: &str, tf: &str, bar_ms: i64, (from_ms, to_ms): (i64, i64), store: Store, allow_dirty: bool,
+) -> Option<Vec<Candle>> {
+    let bin = candle_store::store_path(data_dir, symbol, tf);
+    let csv = data_dir.join(format!("{}_{}.csv", symbol, tf));
+    // A CSV written after the store (download that could not rebuild it,
+    // manual edits) means the .bin no longer matches its source.
+    let modified = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    let stale = bin.exists() && matches!((modified(&csv), modified(&bin)), (Some(c), Some(b)) if c > b);
+    let use_bin = match store {
+        Store::Bin  => true,
+        Store::Csv  => false,
+        Store::Auto => bin.exists() && !stale,
+    };
+    if stale {
+        let name = bin.file_name().unwrap_or_default().to_string_lossy();
+        if use_bin {
+            println!("    ⚠  {} es anterior al CSV: regenera con `convert`", name);
+        } else if store == Store::Auto {
+            println!("    ⚠  {} es anterior al CSV: se lee el CSV (regenera con `convert`)", name);
+        }
+    }
+    let path = if use_bin { bin } else { csv };
+    if !path.exists() { return None; }
+
+    let (bars, quality) = if use_bin {
+        let file = candle_store::CandleFile::open(&path)
+            .unwrap_or_else(|e| panic!("no se pudo leer {:?}: {}", path, e));
+        if file.bar_ms != bar_ms {
+            eprintln!("  ✖  {:?} es de velas de {} ms, no {}", path, file.bar_ms, tf);
+            std::process::exit(1);
+        }
+        let bars = file.bars(file.range(from_ms, to_ms));
+        let issues = data_quality::validate(&bars, bar_ms);
+        (bars, data_quality::Quality { rows: file.rows, issues })
+    } else {
+        let (mut bars, quality) = data_quality::check_csv(&path, bar_ms)
+            .unwrap_or_else(|e| panic!("no se pudo leer {:?}: {}", path, e));
+        bars.retain(|b| b.ts_ms >= from_ms && b.ts_ms < to_ms);
+        (bars, quality)
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    for line in quality.summary() {
+        println!("    ⚠  {}: {}", name, line);
+    }
+    if quality.errors() > 0 && !allow_dirty {
+        eprintln!("  ✖  {} no pasa la validación: repara con `download --repair` o usa --allow-dirty-data", name);
+        std::process::exit(1);
+    }
+    Some(bars.into_iter()
+        .map(|b| Candle { ts_ms: b.ts_ms, open: b.open, high: b.high, low: b.low, close: b.close, volume: b.volume })
+        .collect())
+}
+
+// ── Misc ──────────────────────────────────────────────────────────────────────
+
+/// SplitMix64: the same seed gives the same samples, so two runs draw
+/// exactly the same simulations / configurations.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    pub fn next_f64(&mut self) -> f64 { self.next_u64() as f64 / (u64::MAX as f64 + 1.0) }
+    pub fn below(&mut self, n: usize) -> usize { (self.next_f64() * n as f64) as usize }
+}
+
+/// "YYYY-MM-DD HH:MM" (UTC).
+pub fn ms_to_date(ms: i64) -> String {
+    let s = ms / 1000;
+    let (hh, mm) = ((s / 3600) % 24, (s / 60) % 60);
+    let mut days = s / 86400;
+    let mut y = 1970i32;
+    loop {
+        let dy = if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) { 366 } else { 365 };
+        if days < dy { break; }
+        days -= dy; y += 1;
+    }
+    let leap = y % 4 == 0 && (y % 100 != 0 || y % 400 == 0);
+    let dm = if leap { [31,29,31,30,31,30,31,31,30,31,30,31] }
+             else    { [31,28,31,30,31,30,31,31,30,31,30,31] };
+    let mut mo = 1;
+    for d in &dm { if days < *d { break; } days -= *d; mo += 1; }
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", y, mo, days + 1, hh, mm)
+}
@@ -1,19 +1,196 @@
-/// FVG Backtester — lee data/*.csv, simula la estrategia vela a vela
-/// Run: cargo run --bin backtest --release
-use std::collections::HashMap;
+/// FVG Backtester — lee {data-dir}/{SYMBOL}_{timeframe}.csv, simula la estrategia vela a vela
+/// Run: cargo run --bin backtest --release -- [opciones]   (--help para la lista)
+use chrono::NaiveDate;
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-// ── Constantes ────────────────────────────────────────────────────────────────
+#[allow(dead_code)] // parte de la API solo la usan download / convert
+#[path = "../candle_store.rs"]
+mod candle_store;
+#[allow(dead_code)] // parte de la API solo la usa el bin download
+#[path = "../data_quality.rs"]
+mod data_quality;
+#[allow(dead_code)] // parte de la API solo la usan optimize / convert / download
+#[path = "../backtest_common.rs"]
+mod backtest_common;
+
+use backtest_common::{
+    confirmed, funding_rate_sum, load_candles, load_funding, ms_to_date, parse_slippage, parse_timeframe, timeframe_ms, Candle,
+    Costs, Rng, Slippage, Store, MAKER_FEE, SLIPPAGE, TAKER_FEE,
+};
+
+// ── Valores por defecto (sobrescribibles por CLI) ─────────────────────────────
 const SYMBOLS:            &[&str] = &["BTCUSDT","ETHUSDT","BNBUSDT","XRPUSDT","SOLUSDT"];
 const INITIAL_BALANCE:    f64   = 10_000.0;
 const MAX_RISK_PCT:       f64   = 0.03;
 const MAX_DAILY_LOSS_PCT: f64   = 0.05;
-const EQUITY_FLOOR_PCT:   f64   = 0.90;
+const MAX_TOTAL_LOSS_PCT: f64   = 0.10;  // equity floor = 90 % del balance inicial
+const MAX_OPEN_POSITIONS: usize = 2;     // igual que config.rs (solo modo portfolio)
 const ATR_PERIOD:         usize = 14;
 const VOL_AVG_PERIOD:     usize = 20;
 
+// ── Modo ──────────────────────────────────────────────────────────────────────
+// PerSymbol: cada símbolo con su propio balance inicial, resultados concatenados.
+// Portfolio: una cuenta compartida — ver backtest_portfolio.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Mode { PerSymbol, Portfolio }
+
+// ── CLI ───────────────────────────────────────────────────────────────────────
+#[derive(Parser)]
+#[command(name = "backtest", about = "FVG backtester sobre velas históricas en CSV")]
+struct Cli {
+    /// Símbolos, separados por coma
+    #[arg(long, value_delimiter = ',', default_values_t = SYMBOLS.iter().map(|s| s.to_string()))]
+    symbols: Vec<String>,
+    /// Timeframe principal: sufijo del CSV ({SYMBOL}_{tf}.csv) — 15m, 1H, 4H, 1D…
+    #[arg(long, default_value = "4H", value_parser = parse_timeframe)]
+    timeframe: String,
+    /// Timeframe intradía para velas que tocan SL y TP ("none" = solo la principal)
+    #[arg(long, default_value = "5m", value_parser = parse_ltf)]
+    ltf: String,
+    /// Quién va primero si ni el --ltf resuelve una vela que toca SL y TP
+    #[arg(long, value_enum, default_value_t = AmbiguityPolicy::Pessimistic)]
+    ambiguity_policy: AmbiguityPolicy,
+    /// Primera fecha simulada (YYYY-MM-DD, UTC)
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Última fecha simulada, inclusive (YYYY-MM-DD, UTC)
+    #[arg(long)]
+    to: Option<NaiveDate>,
+    /// Directorio con los CSV [por defecto: data/ del repo]
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+    /// Directorio de salida (trade log, equity, reportes) [por defecto: data-dir]
+    #[arg(long)]
+    out_dir: Option<PathBuf>,
+    /// CSV de parámetros por símbolo con el formato de optimized_params.csv;
+    /// los símbolos que no aparezcan usan los parámetros internos
+    #[arg(long)]
+    params: Option<PathBuf>,
+    /// Cuenta por símbolo o compartida (--mode portfolio)
+    #[arg(long, value_enum, default_value_t = Mode::PerSymbol)]
+    mode: Mode,
+    /// Balance inicial (USDT)
+    #[arg(long, default_value_t = INITIAL_BALANCE)]
+    balance: f64,
+    /// Riesgo por trade, % del balance
+    #[arg(long, default_value_t = MAX_RISK_PCT * 100.0)]
+    risk_pct: f64,
+    /// Pérdida diaria máxima, % del balance
+    #[arg(long, default_value_t = MAX_DAILY_LOSS_PCT * 100.0)]
+    daily_loss_pct: f64,
+    /// Pérdida total máxima, % del balance inicial (equity floor = 100 − valor)
+    #[arg(long, default_value_t = MAX_TOTAL_LOSS_PCT * 100.0)]
+    max_loss_pct: f64,
+    /// Comisión taker (entradas a mercado, SL, time stop), % del nocional
+    #[arg(long, default_value_t = TAKER_FEE * 100.0)]
+    taker_fee: f64,
+    /// Comisión maker (TP límite reduce-only), % del nocional
+    #[arg(long, default_value_t = MAKER_FEE * 100.0)]
+    maker_fee: f64,
+    /// Slippage por fill taker: none, ticks:N o atr:K (fracción del ATR)
+    #[arg(long, default_value_t = SLIPPAGE, value_parser = parse_slippage)]
+    slippage: Slippage,
+    /// Máximo de posiciones abiertas a la vez (modo portfolio)
+    #[arg(long, default_value_t = MAX_OPEN_POSITIONS)]
+    max_positions: usize,
+    /// Formato de las velas: auto = {SYMBOL}_{tf}.bin si existe (bin convert), si no el CSV
+    #[arg(long, value_enum, default_value_t = Store::Auto)]
+    store: Store,
+    /// Correr aunque los datos tengan errores de validación (duplicados, OHLC incoherente…)
+    #[arg(long)]
+    allow_dirty_data: bool,
+    /// Caminos Monte Carlo sobre los retornos por trade (0 = desactivado)
+    #[arg(long, default_value_t = 5_000)]
+    mc_runs: usize,
+    /// Backtests con parámetros perturbados ±MC_PARAM_JITTER (0 = desactivado)
+    #[arg(long, default_value_t = 20)]
+    mc_param_runs: usize,
+    /// Remuestreo de los retornos: reordenar o con reemplazo
+    #[arg(long, value_enum, default_value_t = McMethod::Bootstrap)]
+    mc_method: McMethod,
+}
+
+/// Configuración de la ejecución, derivada de Cli (fracciones, no %).
+struct Settings {
+    mode:               Mode,
+    initial_balance:    f64,
+    max_risk_pct:       f64,
+    max_daily_loss_pct: f64,
+    equity_floor_pct:   f64,
+    max_positions:      usize,
+    taker_fee:          f64,
+    maker_fee:          f64,
+    slippage:           Slippage,
+    timeframe:          String,
+    bar_ms:             i64,            // duración de la vela principal
+    ltf:                Option<String>, // None = sin resolución intradía
+    ambiguity_policy:   AmbiguityPolicy,
+    mc_method:          McMethod,
+    mc_runs:            usize,          // 0 = sin Monte Carlo de trades
+    mc_param_runs:      usize,          // 0 = sin perturbar parámetros
+}
+
+impl Settings {
+    fn from_cli(cli: &Cli) -> Self {
+        Settings {
+            mode:               cli.mode,
+            initial_balance:    cli.balance,
+            max_risk_pct:       cli.risk_pct / 100.0,
+            max_daily_loss_pct: cli.daily_loss_pct / 100.0,
+            equity_floor_pct:   1.0 - cli.max_loss_pct / 100.0,
+            max_positions:      cli.max_positions,
+            taker_fee:          cli.taker_fee / 100.0,
+            maker_fee:          cli.maker_fee / 100.0,
+            slippage:           cli.slippage,
+            timeframe:          cli.timeframe.clone(),
+            bar_ms:             timeframe_ms(&cli.timeframe).unwrap(),
+            ltf:                (!cli.ltf.eq_ignore_ascii_case("none")).then(|| cli.ltf.clone()),
+            ambiguity_policy:   cli.ambiguity_policy,
+            mc_method:          cli.mc_method,
+            mc_runs:            cli.mc_runs,
+            mc_param_runs:      cli.mc_param_runs,
+        }
+    }
+}
+
+fn parse_ltf(tf: &str) -> Result<String, String> {
+    if tf.eq_ignore_ascii_case("none") { Ok(tf.to_string()) } else { parse_timeframe(tf) }
+}
+
+// ── Ambigüedad intrabar ───────────────────────────────────────────────────────
+// Cuando una vela toca SL y TP, se reproduce su recorrido con velas
+// {data-dir}/{SYMBOL}_{ltf}.csv (5m o 1m, --ltf). Si no hay datos, o la vela
+// intradía también toca ambos, decide --ambiguity-policy.
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum AmbiguityPolicy {
+    Pessimistic,   // SL primero
+    Optimistic,    // TP primero
+    OpenProximity, // el nivel más cercano a la apertura de la vela, primero
+}
+
+// ── Monte Carlo ───────────────────────────────────────────────────────────────
+// Reordena (Shuffle) o remuestrea con reemplazo (Bootstrap) los retornos por
+// trade sobre los mismos días de salida (--mc-runs), y repite el backtest con
+// parámetros perturbados ±MC_PARAM_JITTER (--mc-param-runs). Límites: pérdida
+// diaria y equity floor de Settings (reglas de prop firm).
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum McMethod { Shuffle, Bootstrap }
+const MC_SEED:         u64 = 42;
+const MC_PARAM_JITTER: f64 = 0.10; // ±10 % uniforme por parámetro
+
+#[derive(Clone, Copy, Default)]
+struct AmbiguityStats {
+    bars:         usize, // velas 4H que tocaron SL y TP
+    resolved_ltf: usize, // resueltas con velas intradía
+    fallback:     usize, // resueltas por --ambiguity-policy
+}
+
 // ── Parámetros optimizados por símbolo (resultado del grid search) ─────────────
 struct SymbolParams {
     min_gap_pct:  f64,
@@ -21,24 +198,81 @@ struct SymbolParams {
     fvg_lookback: usize,
     sl_atr_mult:  f64,
     tp_mult:      f64,
-    time_stop:    usize, // candles (4H c/u)
+    time_stop:    usize, // velas del timeframe principal
+    // Filtros de confirmación (backtest_common::confirmed); 0 / None = sin filtro
+    bb_period:      usize,
+    bias_threshold: Option<f64>,
+    bos_window:     usize,
 }
 
 fn symbol_params(symbol: &str) -> SymbolParams {
-    match symbol {
-        "BTCUSDT" => SymbolParams { min_gap_pct: 0.001, min_vol_mult: 1.5, fvg_lookback: 12, sl_atr_mult: 0.5, tp_mult: 5.0, time_stop:  7 },
-        "ETHUSDT" => SymbolParams { min_gap_pct: 0.001, min_vol_mult: 1.5, fvg_lookback:  8, sl_atr_mult: 1.0, tp_mult: 3.0, time_stop:  7 },
-        "BNBUSDT" => SymbolParams { min_gap_pct: 0.003, min_vol_mult: 1.0, fvg_lookback: 12, sl_atr_mult: 2.0, tp_mult: 2.5, time_stop: 35 },
-        "XRPUSDT" => SymbolParams { min_gap_pct: 0.008, min_vol_mult: 1.0, fvg_lookback:  8, sl_atr_mult: 2.0, tp_mult: 1.5, time_stop: 14 },
-        "SOLUSDT" => SymbolParams { min_gap_pct: 0.008, min_vol_mult: 1.2, fvg_lookback: 12, sl_atr_mult: 1.5, tp_mult: 4.0, time_stop:  7 },
-        _         => SymbolParams { min_gap_pct: 0.003, min_vol_mult: 1.2, fvg_lookback:  8, sl_atr_mult: 1.0, tp_mult: 2.0, time_stop:  7 },
+    let (min_gap_pct, min_vol_mult, fvg_lookback, sl_atr_mult, tp_mult, time_stop) = match symbol {
+        //              gap%   vol×  LB  SL×  TP×  stop
+        "BTCUSDT" => (0.001, 1.5, 12, 0.5, 5.0,  7),
+        "ETHUSDT" => (0.001, 1.5,  8, 1.0, 3.0,  7),
+        "BNBUSDT" => (0.003, 1.0, 12, 2.0, 2.5, 35),
+        "XRPUSDT" => (0.008, 1.0,  8, 2.0, 1.5, 14),
+        "SOLUSDT" => (0.008, 1.2, 12, 1.5, 4.0,  7),
+        _         => (0.003, 1.2,  8, 1.0, 2.0,  7),
+    };
+    SymbolParams {
+        min_gap_pct, min_vol_mult, fvg_lookback, sl_atr_mult, tp_mult, time_stop,
+        bb_period: 0, bias_threshold: None, bos_window: 0,
     }
 }
 
-// ── Tipos ─────────────────────────────────────────────────────────────────────
-#[derive(Clone, Debug)]
-struct Candle { ts_ms: i64, open: f64, high: f64, low: f64, close: f64, volume: f64 }
+/// Lee --params: CSV con cabecera, formato de optimized_params.csv (columnas
+/// symbol, min_gap, min_vol_mult, lookback, sl_atr_mult, tp_mult, time_stop;
+/// bb_period, bias_threshold y bos_window son opcionales, vacío = sin filtro;
+/// el resto se ignora). El error indica archivo, línea y columna.
+fn load_params(path: &Path) -> Result<HashMap<String, SymbolParams>, String> {
+    let file = File::open(path).map_err(|e| format!("{:?}: {}", path, e))?;
+    let mut lines = BufReader::new(file).lines();
+    let header: Vec<String> = match lines.next() {
+        Some(line) => line.map_err(|e| format!("{:?}: {}", path, e))?
+            .split(',').map(|h| h.trim().to_string()).collect(),
+        None => return Err(format!("{:?}: archivo vacío", path)),
+    };
+    let col = |name: &'static str| header.iter().position(|h| h == name)
+        .map(|i| (i, name))
+        .ok_or_else(|| format!("{:?}:1: falta la columna {}", path, name));
+    let (sym, gap, vol, lb, sl, tp, ts) = (col("symbol")?, col("min_gap")?, col("min_vol_mult")?,
+        col("lookback")?, col("sl_atr_mult")?, col("tp_mult")?, col("time_stop")?);
+    let optional = |name: &'static str| header.iter().position(|h| h == name).map(|i| (i, name));
+    let (bb, bias, bos) = (optional("bb_period"), optional("bias_threshold"), optional("bos_window"));
 
+    fn field<T: std::str::FromStr>(f: &[&str], (i, name): (usize, &str), path: &Path, n: usize) -> Result<T, String> {
+        let v = f.get(i).ok_or_else(|| format!("{:?}:{}: falta el valor de la columna {}", path, n, name))?;
+        v.parse().map_err(|_| format!("{:?}:{}: columna {}: valor inválido {:?}", path, n, name, v))
+    }
+    fn filter<T: std::str::FromStr>(f: &[&str], col: Option<(usize, &str)>, path: &Path, n: usize) -> Result<Option<T>, String> {
+        match col {
+            Some(c @ (i, _)) if f.get(i).is_some_and(|v| !v.is_empty()) => field(f, c, path, n).map(Some),
+            _ => Ok(None),
+        }
+    }
+    let mut out = HashMap::new();
+    for (n, line) in lines.enumerate() {
+        let n = n + 2; // 1-based, tras la cabecera
+        let line = line.map_err(|e| format!("{:?}:{}: {}", path, n, e))?;
+        if line.trim().is_empty() { continue; }
+        let f: Vec<&str> = line.split(',').map(str::trim).collect();
+        out.insert(field(&f, sym, path, n)?, SymbolParams {
+            min_gap_pct:  field(&f, gap, path, n)?,
+            min_vol_mult: field(&f, vol, path, n)?,
+            fvg_lookback: field(&f, lb, path, n)?,
+            sl_atr_mult:  field(&f, sl, path, n)?,
+            tp_mult:      field(&f, tp, path, n)?,
+            time_stop:    field(&f, ts, path, n)?,
+            bb_period:      filter(&f, bb, path, n)?.unwrap_or(0),
+            bias_threshold: filter(&f, bias, path, n)?,
+            bos_window:     filter(&f, bos, path, n)?.unwrap_or(0),
+        });
+    }
+    Ok(out)
+}
+
+// ── Tipos ─────────────────────────────────────────────────────────────────────
 #[derive(Clone, Debug, PartialEq)]
 enum Side { Long, Short }
 
@@ -47,33 +281,42 @@ struct Trade {
     symbol: String, side: Side,
     entry_ts: i64, exit_ts: i64,
     entry: f64, exit: f64, qty: f64, sl: f64, tp1: f64,
-    pnl: f64, pnl_pct: f64, reason: String,
+    gross_pnl: f64, fees: f64, slippage: f64, funding: f64,
+    pnl: f64, pnl_pct: f64, reason: String, // pnl = neto
 }
 
 struct Position {
-    side: Side, entry: f64, sl: f64, tp1: f64,
-    qty: f64, entry_candle: usize,
-}
-
-// ── CSV loader ────────────────────────────────────────────────────────────────
-fn load_csv(path: &Path) -> Vec<Candle> {
-    let mut out = Vec::with_capacity(9000);
-    for (i, line) in BufReader::new(File::open(path).expect("CSV not found")).lines().enumerate() {
-        let line = line.unwrap();
-        if i == 0 { continue; }
-        let f: Vec<&str> = line.split(',').collect();
-        if f.len() < 7 { continue; }
-        out.push(Candle {
-            ts_ms:  f[0].parse().unwrap_or(0),
-            open:   f[2].parse().unwrap_or(0.0),
-            high:   f[3].parse().unwrap_or(0.0),
-            low:    f[4].parse().unwrap_or(0.0),
-            close:  f[5].parse().unwrap_or(0.0),
-            volume: f[6].parse().unwrap_or(0.0),
-        });
+    side: Side, entry: f64, fill: f64, sl: f64, tp1: f64,
+    qty: f64, entry_candle: usize, entry_fee: f64,
+}
+
+/// Decide si el SL se tocó antes que el TP dentro de `bar` (que toca ambos).
+fn sl_first(bar: &Candle, cfg: &Settings, side: &Side, sl: f64, tp: f64, ltf: &[Candle], stats: &mut AmbiguityStats) -> bool {
+    stats.bars += 1;
+    let hits = |c: &Candle| match side {
+        Side::Long  => (c.low <= sl, c.high >= tp),
+        Side::Short => (c.high >= sl, c.low <= tp),
+    };
+
+    // Recorrido intradía de la vela: [ts, ts + bar_ms)
+    let a = ltf.partition_point(|c| c.ts_ms < bar.ts_ms);
+    let b = ltf.partition_point(|c| c.ts_ms < bar.ts_ms + cfg.bar_ms);
+    let mut decider = bar;
+    for c in &ltf[a..b] {
+        match hits(c) {
+            (true, false) => { stats.resolved_ltf += 1; return true; }
+            (false, true) => { stats.resolved_ltf += 1; return false; }
+            (true, true)  => { decider = c; break; } // sigue ambiguo a menor escala
+            (false, false) => {}
+        }
+    }
+
+    stats.fallback += 1;
+    match cfg.ambiguity_policy {
+        AmbiguityPolicy::Pessimistic   => true,
+        AmbiguityPolicy::Optimistic    => false,
+        AmbiguityPolicy::OpenProximity => (decider.open - sl).abs() <= (decider.open - tp).abs(),
     }
-    out.sort_by_key(|c| c.ts_ms);
-    out
 }
 
 // ── Indicadores ───────────────────────────────────────────────────────────────
@@ -127,6 +370,7 @@ fn find_signal(candles: &[Candle], i: usize, p: &SymbolParams) -> Option<Fvg> {
                 if retested
                     && current.close > zh
                     && current.volume > avg_vol * p.min_vol_mult
+                    && confirmed(candles, i, true, p.bb_period, p.bias_threshold, p.bos_window)
                 {
                     return Some(Fvg { side: Side::Long, zone_low: zl, zone_high: zh });
                 }
@@ -147,6 +391,7 @@ fn find_signal(candles: &[Candle], i: usize, p: &SymbolParams) -> Option<Fvg> {
                 if retested
                     && current.close < zl
                     && current.volume > avg_vol * p.min_vol_mult
+                    && confirmed(candles, i, false, p.bb_period, p.bias_threshold, p.bos_window)
                 {
                     return Some(Fvg { side: Side::Short, zone_low: zl, zone_high: zh });
                 }
@@ -156,11 +401,118 @@ fn find_signal(candles: &[Candle], i: usize, p: &SymbolParams) -> Option<Fvg> {
     None
 }
 
+// ── Ejecución de una vela (común a modo símbolo y modo portfolio) ─────────────
+/// Cierra `pos` si la vela `i` toca SL/TP o vence el time stop.
+/// `pnl_pct` se calcula sobre `balance` antes de aplicar el trade.
+#[allow(clippy::too_many_arguments)]
+fn check_exit(
+    symbol: &str, candles: &[Candle], i: usize, pos: &Position, p: &SymbolParams,
+    costs: &Costs, ltf: &[Candle], ambiguity: &mut AmbiguityStats, balance: f64, cfg: &Settings,
+) -> Option<Trade> {
+    let candle  = &candles[i];
+    let cur_atr = calc_atr(&candles[..=i], ATR_PERIOD);
+
+    let sl_hit = match pos.side {
+        Side::Long  => candle.low  <= pos.sl,
+        Side::Short => candle.high >= pos.sl,
+    };
+    let tp_hit = match pos.side {
+        Side::Long  => candle.high >= pos.tp1,
+        Side::Short => candle.low  <= pos.tp1,
+    };
+    let time_stop = (i - pos.entry_candle) >= p.time_stop;
+
+    let sl_exit = sl_hit
+        && (!tp_hit || sl_first(candle, cfg, &pos.side, pos.sl, pos.tp1, ltf, ambiguity));
+
+    let (close_price, reason) = if sl_exit {
+        (pos.sl, "SL")
+    } else if tp_hit {
+        (pos.tp1, "TP1")
+    } else if time_stop {
+        (candle.close, "TimeStop")
+    } else {
+        return None;
+    };
+
+    let mult = match pos.side { Side::Long => 1.0, Side::Short => -1.0 };
+    // SL / time stop salen a mercado: slippage en contra + taker. TP: maker.
+    let (exit_fill, exit_rate) = if reason == "TP1" {
+        (close_price, costs.maker_fee)
+    } else {
+        (close_price - costs.slippage(cur_atr) * mult, costs.taker_fee)
+    };
+    let exit_fee = exit_fill * pos.qty * exit_rate;
+    let entry_close_ms = candles[pos.entry_candle].ts_ms + cfg.bar_ms;
+    let funding = funding_rate_sum(&costs.funding, entry_close_ms, candle.ts_ms + cfg.bar_ms)
+        * pos.entry * pos.qty * mult; // largos pagan funding positivo
+
+    let gross_pnl = (close_price - pos.entry) * pos.qty * mult;
+    let fill_pnl  = (exit_fill - pos.fill) * pos.qty * mult;
+    let fees      = pos.entry_fee + exit_fee;
+    let pnl       = fill_pnl - fees - funding;
+
+    Some(Trade {
+        symbol: symbol.to_string(), side: pos.side.clone(),
+        entry_ts: candles[pos.entry_candle].ts_ms, exit_ts: candle.ts_ms,
+        entry: pos.fill, exit: exit_fill, qty: pos.qty,
+        sl: pos.sl, tp1: pos.tp1,
+        gross_pnl, fees, slippage: gross_pnl - fill_pnl, funding,
+        pnl, pnl_pct: pnl / balance * 100.0, reason: reason.to_string(),
+    })
+}
+
+/// Abre posición si hay señal al cierre de la vela `i`. El riesgo se limita a
+/// max_risk_pct de `balance` y al presupuesto diario restante (`daily_pnl`).
+fn check_entry(
+    candles: &[Candle], i: usize, p: &SymbolParams, costs: &Costs, balance: f64, daily_pnl: f64,
+    cfg: &Settings,
+) -> Option<Position> {
+    let cur_atr = calc_atr(&candles[..=i], ATR_PERIOD);
+    if cur_atr == 0.0 { return None; }
+
+    let fvg   = find_signal(candles, i, p)?;
+    let entry = candles[i].close;
+
+    let sl = match fvg.side {
+        Side::Long  => fvg.zone_low  - cur_atr * p.sl_atr_mult,
+        Side::Short => fvg.zone_high + cur_atr * p.sl_atr_mult,
+    };
+
+    let risk_unit = (entry - sl).abs();
+    if risk_unit <= 0.0 || risk_unit > entry * 0.10 { return None; }
+
+    let tp1 = match fvg.side {
+        Side::Long  => entry + risk_unit * p.tp_mult,
+        Side::Short => entry - risk_unit * p.tp_mult,
+    };
+
+    let max_risk = balance * cfg.max_risk_pct;
+    let budget   = (balance * cfg.max_daily_loss_pct + daily_pnl).max(0.0);
+    let risk     = max_risk.min(budget);
+    if risk <= 0.0 { return None; }
+
+    let qty = (risk / risk_unit).floor();
+    if qty <= 0.0 { return None; }
+
+    // Entrada a mercado al cierre: slippage en contra + taker
+    let fill = match fvg.side {
+        Side::Long  => entry + costs.slippage(cur_atr),
+        Side::Short => entry - costs.slippage(cur_atr),
+    };
+    Some(Position {
+        side: fvg.side, entry, fill, sl, tp1, qty, entry_candle: i,
+        entry_fee: fill * qty * costs.taker_fee,
+    })
+}
+
 // ── Backtest por símbolo ──────────────────────────────────────────────────────
-fn backtest_symbol(symbol: &str, candles: &[Candle]) -> Vec<Trade> {
-    let p = symbol_params(symbol);
+fn backtest_symbol(
+    symbol: &str, candles: &[Candle], costs: &Costs, ltf: &[Candle], p: &SymbolParams, cfg: &Settings,
+) -> (Vec<Trade>, AmbiguityStats) {
     let mut trades: Vec<Trade> = Vec::new();
-    let mut balance  = INITIAL_BALANCE;
+    let mut ambiguity = AmbiguityStats::default();
+    let mut balance  = cfg.initial_balance;
     let mut position: Option<Position> = None;
 
     let mut current_day: i64 = -1;
@@ -170,108 +522,170 @@ fn backtest_symbol(symbol: &str, candles: &[Candle]) -> Vec<Trade> {
     let min_i = ATR_PERIOD + VOL_AVG_PERIOD + 3;
 
     for i in min_i..candles.len() {
-        let candle = &candles[i];
-
         // ── Reset diario ──────────────────────────────────────────────────────
-        let day = candle.ts_ms / 86_400_000;
+        let day = candles[i].ts_ms / 86_400_000;
         if day != current_day {
             current_day = day;
             daily_pnl = 0.0;
-            trading_on = balance >= INITIAL_BALANCE * EQUITY_FLOOR_PCT;
+            trading_on = balance >= cfg.initial_balance * cfg.equity_floor_pct;
         }
 
-        let cur_atr = calc_atr(&candles[..=i], ATR_PERIOD);
-
         // ── Gestión de posición abierta ───────────────────────────────────────
         if let Some(ref pos) = position {
-            let sl_hit = match pos.side {
-                Side::Long  => candle.low  <= pos.sl,
-                Side::Short => candle.high >= pos.sl,
-            };
-            let tp_hit = match pos.side {
-                Side::Long  => candle.high >= pos.tp1,
-                Side::Short => candle.low  <= pos.tp1,
-            };
-            let time_stop = (i - pos.entry_candle) >= p.time_stop;
-
-            let (close_price, reason) = if sl_hit {
-                (pos.sl, "SL")
-            } else if tp_hit {
-                (pos.tp1, "TP1")
-            } else if time_stop {
-                (candle.close, "TimeStop")
-            } else {
-                continue;
-            };
-
-            let mult = match pos.side { Side::Long => 1.0, Side::Short => -1.0 };
-            let pnl  = (close_price - pos.entry) * pos.qty * mult;
-            let pnl_pct = pnl / balance * 100.0;
-            balance   += pnl;
-            daily_pnl += pnl;
-
-            trades.push(Trade {
-                symbol: symbol.to_string(), side: pos.side.clone(),
-                entry_ts: candles[pos.entry_candle].ts_ms, exit_ts: candle.ts_ms,
-                entry: pos.entry, exit: close_price, qty: pos.qty,
-                sl: pos.sl, tp1: pos.tp1, pnl, pnl_pct, reason: reason.to_string(),
-            });
+            let Some(trade) = check_exit(symbol, candles, i, pos, p, costs, ltf, &mut ambiguity, balance, cfg)
+            else { continue };
+            balance   += trade.pnl;
+            daily_pnl += trade.pnl;
+            trades.push(trade);
             position = None;
 
-            if daily_pnl < -(balance.max(INITIAL_BALANCE) * MAX_DAILY_LOSS_PCT) {
+            if daily_pnl < -(balance.max(cfg.initial_balance) * cfg.max_daily_loss_pct) {
                 trading_on = false;
             }
             continue;
         }
 
-        if !trading_on || cur_atr == 0.0 { continue; }
+        if !trading_on { continue; }
 
         // ── Búsqueda de señal ─────────────────────────────────────────────────
-        if let Some(fvg) = find_signal(candles, i, &p) {
-            let entry = candle.close;
+        position = check_entry(candles, i, p, costs, balance, daily_pnl, cfg);
+    }
 
-            let sl = match fvg.side {
-                Side::Long  => fvg.zone_low  - cur_atr * p.sl_atr_mult,
-                Side::Short => fvg.zone_high + cur_atr * p.sl_atr_mult,
-            };
+    (trades, ambiguity)
+}
+
+// ── Backtest de portfolio ─────────────────────────────────────────────────────
+// Una sola línea temporal con todas las velas de todos los símbolos: balance
+// compartido (con compounding), max_positions global, pérdida diaria y
+// equity floor comunes. En cada timestamp se procesan primero las salidas y
+// después las entradas, en el orden de --symbols.
+struct SymbolData {
+    symbol:  String,
+    candles: Vec<Candle>,
+    costs:   Costs,
+    ltf:     Vec<Candle>,
+}
 
-            let risk_unit = (entry - sl).abs();
-            if risk_unit <= 0.0 || risk_unit > entry * 0.10 { continue; }
+struct EquityPoint { ts_ms: i64, balance: f64, equity: f64, open: usize }
+
+fn backtest_portfolio(
+    data: &[SymbolData], params: &[SymbolParams], cfg: &Settings,
+) -> (Vec<Trade>, AmbiguityStats, Vec<EquityPoint>) {
+    let mut timeline: Vec<i64> = data.iter().flat_map(|d| d.candles.iter().map(|c| c.ts_ms)).collect();
+    timeline.sort_unstable();
+    timeline.dedup();
+
+    let mut trades: Vec<Trade> = Vec::new();
+    let mut ambiguity = AmbiguityStats::default();
+    let mut curve: Vec<EquityPoint> = Vec::with_capacity(timeline.len());
+    let mut cursor: Vec<usize> = vec![0; data.len()];
+    let mut positions: Vec<Option<Position>> = (0..data.len()).map(|_| None).collect();
 
-            let tp1 = match fvg.side {
-                Side::Long  => entry + risk_unit * p.tp_mult,
-                Side::Short => entry - risk_unit * p.tp_mult,
-            };
+    let mut balance     = cfg.initial_balance;
+    let mut current_day = -1i64;
+    let mut daily_pnl   = 0.0_f64;
+    let mut trading_on  = true;
+    let min_i = ATR_PERIOD + VOL_AVG_PERIOD + 3;
+
+    for &ts in &timeline {
+        let day = ts / 86_400_000;
+        if day != current_day {
+            current_day = day;
+            daily_pnl = 0.0;
+            trading_on = balance >= cfg.initial_balance * cfg.equity_floor_pct;
+        }
 
-            let max_risk = balance * MAX_RISK_PCT;
-            let budget   = (balance * MAX_DAILY_LOSS_PCT + daily_pnl).max(0.0);
-            let risk     = max_risk.min(budget);
-            if risk <= 0.0 { continue; }
+        // Símbolos con vela en este timestamp → índice de la vela
+        let active: Vec<(usize, usize)> = (0..data.len())
+            .filter(|&s| data[s].candles.get(cursor[s]).is_some_and(|c| c.ts_ms == ts))
+            .map(|s| (s, cursor[s]))
+            .collect();
 
-            let qty = (risk / risk_unit).floor();
-            if qty <= 0.0 { continue; }
+        // ── Salidas ───────────────────────────────────────────────────────────
+        let mut exited: Vec<usize> = Vec::new();
+        for &(s, i) in &active {
+            let Some(pos) = positions[s].as_ref() else { continue };
+            let d = &data[s];
+            if let Some(trade) = check_exit(&d.symbol, &d.candles, i, pos, &params[s], &d.costs, &d.ltf, &mut ambiguity, balance, cfg) {
+                balance   += trade.pnl;
+                daily_pnl += trade.pnl;
+                trades.push(trade);
+                positions[s] = None;
+                exited.push(s);
+                if daily_pnl < -(balance.max(cfg.initial_balance) * cfg.max_daily_loss_pct) {
+                    trading_on = false;
+                }
+            }
+        }
 
-            position = Some(Position {
-                side: fvg.side, entry, sl, tp1, qty, entry_candle: i,
-            });
+        // ── Entradas ──────────────────────────────────────────────────────────
+        if trading_on {
+            for &(s, i) in &active {
+                if i < min_i || positions[s].is_some() || exited.contains(&s) { continue; }
+                if positions.iter().flatten().count() >= cfg.max_positions { break; }
+                // El riesgo ya abierto consume presupuesto diario compartido
+                let open_risk: f64 = positions.iter().flatten()
+                    .map(|pos| (pos.fill - pos.sl).abs() * pos.qty)
+                    .sum();
+                let d = &data[s];
+                positions[s] = check_entry(&d.candles, i, &params[s], &d.costs, balance, daily_pnl - open_risk, cfg);
+            }
         }
+
+        for &(s, _) in &active { cursor[s] += 1; }
+
+        // ── Equity mark-to-market (último cierre de cada símbolo) ─────────────
+        let unrealized: f64 = positions.iter().enumerate()
+            .filter_map(|(s, pos)| {
+                let pos   = pos.as_ref()?;
+                let close = data[s].candles[cursor[s] - 1].close;
+                let mult  = match pos.side { Side::Long => 1.0, Side::Short => -1.0 };
+                Some((close - pos.fill) * pos.qty * mult)
+            })
+            .sum();
+        curve.push(EquityPoint {
+            ts_ms: ts, balance, equity: balance + unrealized,
+            open: positions.iter().flatten().count(),
+        });
+    }
+
+    (trades, ambiguity, curve)
+}
+
+fn save_equity_curve(curve: &[EquityPoint], path: &Path) {
+    let mut f = File::create(path).expect("no se pudo crear equity curve");
+    writeln!(f, "timestamp_ms,datetime_utc,balance,equity,open_positions").unwrap();
+    for e in curve {
+        writeln!(f, "{},{},{:.2},{:.2},{}", e.ts_ms, ms_to_date(e.ts_ms), e.balance, e.equity, e.open).unwrap();
     }
+}
 
-    trades
+/// Máximo drawdown (%) de la curva de equity mark-to-market.
+fn curve_max_drawdown(curve: &[EquityPoint], initial: f64) -> f64 {
+    let mut peak = initial;
+    let mut max_dd = 0.0_f64;
+    for e in curve {
+        peak = peak.max(e.equity);
+        max_dd = max_dd.max((peak - e.equity) / peak * 100.0);
+    }
+    max_dd
 }
 
 // ── Estadísticas ──────────────────────────────────────────────────────────────
+#[derive(Serialize)]
 struct Stats {
     symbol: String, trades: usize, wins: usize, losses: usize,
     win_rate: f64, total_pnl: f64, total_pnl_pct: f64,
+    gross_pnl: f64, fees: f64, slippage: f64, funding: f64,
     avg_win: f64, avg_loss: f64, profit_factor: f64,
     max_drawdown: f64, best: f64, worst: f64,
 }
 
-fn compute_stats(symbol: &str, trades: &[Trade]) -> Stats {
+fn compute_stats(symbol: &str, trades: &[Trade], initial: f64) -> Stats {
     if trades.is_empty() {
         return Stats { symbol: symbol.to_string(), trades: 0, wins: 0, losses: 0,
             win_rate: 0.0, total_pnl: 0.0, total_pnl_pct: 0.0,
+            gross_pnl: 0.0, fees: 0.0, slippage: 0.0, funding: 0.0,
             avg_win: 0.0, avg_loss: 0.0, profit_factor: 0.0,
             max_drawdown: 0.0, best: 0.0, worst: 0.0 };
     }
@@ -281,8 +695,8 @@ fn compute_stats(symbol: &str, trades: &[Trade]) -> Stats {
     let gross_loss: f64 = losses.iter().sum();
     let total_pnl: f64  = trades.iter().map(|t| t.pnl).sum();
 
-    let mut bal = INITIAL_BALANCE;
-    let mut peak = INITIAL_BALANCE;
+    let mut bal = initial;
+    let mut peak = initial;
     let mut max_dd = 0.0_f64;
     for t in trades {
         bal += t.pnl;
@@ -295,7 +709,11 @@ fn compute_stats(symbol: &str, trades: &[Trade]) -> Stats {
         symbol: symbol.to_string(),
         trades: trades.len(), wins: wins.len(), losses: losses.len(),
         win_rate: wins.len() as f64 / trades.len() as f64 * 100.0,
-        total_pnl, total_pnl_pct: total_pnl / INITIAL_BALANCE * 100.0,
+        total_pnl, total_pnl_pct: total_pnl / initial * 100.0,
+        gross_pnl: trades.iter().map(|t| t.gross_pnl).sum(),
+        fees:      trades.iter().map(|t| t.fees).sum(),
+        slippage:  trades.iter().map(|t| t.slippage).sum(),
+        funding:   trades.iter().map(|t| t.funding).sum(),
         avg_win:  if wins.is_empty()   { 0.0 } else { gross_win  / wins.len() as f64 },
         avg_loss: if losses.is_empty() { 0.0 } else { gross_loss / losses.len() as f64 },
         profit_factor: if gross_loss == 0.0 { f64::INFINITY } else { gross_win / gross_loss },
@@ -315,7 +733,9 @@ fn print_stats(s: &Stats) {
     println!("  ├─────────────────────────────────────────────┤");
     println!("  │  Trades         {:>6}   ({} W / {} L)", s.trades, s.wins, s.losses);
     println!("  │  Win Rate       {:>6.1}%", s.win_rate);
-    println!("  │  Total PnL      {:>+9.2} USDT  ({:+.1}%)", s.total_pnl, s.total_pnl_pct);
+    println!("  │  Gross PnL      {:>+9.2} USDT", s.gross_pnl);
+    println!("  │    − Fees       {:>9.2}   Slip {:.2}   Funding {:+.2}", s.fees, s.slippage, s.funding);
+    println!("  │  Net PnL        {:>+9.2} USDT  ({:+.1}%)", s.total_pnl, s.total_pnl_pct);
     println!("  │  Avg Win        {:>+9.2} USDT", s.avg_win);
     println!("  │  Avg Loss       {:>+9.2} USDT", -s.avg_loss);
     println!("  │  Profit Factor  {:>9.2}", s.profit_factor);
@@ -325,18 +745,20 @@ fn print_stats(s: &Stats) {
     println!("  └─────────────────────────────────────────────┘");
 }
 
-fn print_global(all_trades: &[Trade]) {
-    let s = compute_stats("ALL", all_trades);
+fn print_global(all_trades: &[Trade], pairs: usize, initial: f64) {
+    let s = compute_stats("ALL", all_trades, initial);
     let verdict = if s.win_rate >= 55.0 && s.profit_factor >= 1.5 { "✅ APTO PARA LIVE" }
                   else if s.win_rate >= 50.0 { "⚠️  REVISAR PARAMETROS" }
                   else { "❌ NO INICIAR LIVE" };
     println!();
     println!("  ╔══════════════════════════════════════════════════╗");
-    println!("  ║  RESULTADO GLOBAL — {} PARES  {}  ║", SYMBOLS.len(), verdict);
+    println!("  ║  RESULTADO GLOBAL — {} PARES  {}  ║", pairs, verdict);
     println!("  ╠══════════════════════════════════════════════════╣");
     println!("  ║  Trades         {:>6}   ({} W / {} L)", s.trades, s.wins, s.losses);
     println!("  ║  Win Rate       {:>6.1}%", s.win_rate);
-    println!("  ║  Total PnL      {:>+9.2} USDT  ({:+.1}%)", s.total_pnl, s.total_pnl_pct);
+    println!("  ║  Gross PnL      {:>+9.2} USDT", s.gross_pnl);
+    println!("  ║    − Fees       {:>9.2}   Slip {:.2}   Funding {:+.2}", s.fees, s.slippage, s.funding);
+    println!("  ║  Net PnL        {:>+9.2} USDT  ({:+.1}%)", s.total_pnl, s.total_pnl_pct);
     println!("  ║  Avg Win        {:>+9.2} USDT", s.avg_win);
     println!("  ║  Avg Loss       {:>+9.2} USDT", -s.avg_loss);
     println!("  ║  Profit Factor  {:>9.2}", s.profit_factor);
@@ -360,67 +782,568 @@ fn print_global(all_trades: &[Trade]) {
     }
 }
 
+fn print_ambiguity(a: &AmbiguityStats, ltf: Option<&str>, policy: AmbiguityPolicy) {
+    if a.bars == 0 { return; }
+    let ltf = ltf.unwrap_or("sin datos");
+    println!("    Ambiguas {:>4}  →  {} resueltas con {}  |  {} por política {:?}",
+             a.bars, a.resolved_ltf, ltf, a.fallback, policy);
+}
+
+// ── Monte Carlo ───────────────────────────────────────────────────────────────
+struct McPath { final_equity: f64, max_dd: f64, daily_breach: bool, total_breach: bool }
+
+/// Aplica `returns` (fracción del balance) con compounding; `days[k]` es el
+/// día de salida del trade k.
+fn replay(returns: &[f64], days: &[i64], cfg: &Settings) -> McPath {
+    let mut equity    = cfg.initial_balance;
+    let mut peak      = cfg.initial_balance;
+    let mut day_start = cfg.initial_balance;
+    let mut cur_day   = -1i64;
+    let mut path = McPath { final_equity: 0.0, max_dd: 0.0, daily_breach: false, total_breach: false };
+    for (r, &d) in returns.iter().zip(days) {
+        if d != cur_day { cur_day = d; day_start = equity; }
+        equity *= 1.0 + r;
+        peak = peak.max(equity);
+        path.max_dd = path.max_dd.max((peak - equity) / peak * 100.0);
+        if equity - day_start < -(day_start.max(cfg.initial_balance) * cfg.max_daily_loss_pct) {
+            path.daily_breach = true;
+        }
+        if equity < cfg.initial_balance * cfg.equity_floor_pct { path.total_breach = true; }
+    }
+    path.final_equity = equity;
+    path
+}
+
+/// (retornos, días) en orden de salida.
+fn trade_returns(trades: &[Trade]) -> (Vec<f64>, Vec<i64>) {
+    let mut sorted: Vec<&Trade> = trades.iter().collect();
+    sorted.sort_by_key(|t| t.exit_ts);
+    (sorted.iter().map(|t| t.pnl_pct / 100.0).collect(),
+     sorted.iter().map(|t| t.exit_ts / 86_400_000).collect())
+}
+
+fn monte_carlo(trades: &[Trade], rng: &mut Rng, cfg: &Settings) -> Vec<McPath> {
+    let (returns, days) = trade_returns(trades);
+    if returns.is_empty() { return Vec::new(); }
+    let mut sample = returns.clone();
+    (0..cfg.mc_runs).map(|_| {
+        match cfg.mc_method {
+            McMethod::Shuffle => {
+                for k in (1..sample.len()).rev() { sample.swap(k, rng.below(k + 1)); }
+            }
+            McMethod::Bootstrap => {
+                for r in sample.iter_mut() { *r = returns[rng.below(returns.len())]; }
+            }
+        }
+        replay(&sample, &days, cfg)
+    }).collect()
+}
+
+fn perturb(p: &SymbolParams, rng: &mut Rng) -> SymbolParams {
+    let mut jitter = |v: f64| v * (1.0 + MC_PARAM_JITTER * (2.0 * rng.next_f64() - 1.0));
+    SymbolParams {
+        min_gap_pct:  jitter(p.min_gap_pct),
+        min_vol_mult: jitter(p.min_vol_mult),
+        fvg_lookback: (jitter(p.fvg_lookback as f64).round() as usize).max(1),
+        sl_atr_mult:  jitter(p.sl_atr_mult),
+        tp_mult:      jitter(p.tp_mult),
+        time_stop:    (jitter(p.time_stop as f64).round() as usize).max(1),
+        bb_period:      p.bb_period,
+        bias_threshold: p.bias_threshold,
+        bos_window:     p.bos_window,
+    }
+}
+
+/// Repite el backtest (según cfg.mode) con `base` perturbados; un camino por
+/// ejecución, en el orden real de los trades.
+fn param_monte_carlo(data: &[SymbolData], base: &[SymbolParams], rng: &mut Rng, cfg: &Settings) -> Vec<McPath> {
+    (0..cfg.mc_param_runs).map(|k| {
+        eprint!("\r    perturbación {}/{}   ", k + 1, cfg.mc_param_runs);
+        let params: Vec<SymbolParams> = base.iter().map(|p| perturb(p, rng)).collect();
+        let trades: Vec<Trade> = match cfg.mode {
+            Mode::PerSymbol => data.iter().zip(&params)
+                .flat_map(|(d, p)| backtest_symbol(&d.symbol, &d.candles, &d.costs, &d.ltf, p, cfg).0)
+                .collect(),
+            Mode::Portfolio => backtest_portfolio(data, &params, cfg).0,
+        };
+        let (returns, days) = trade_returns(&trades);
+        replay(&returns, &days, cfg)
+    }).collect()
+}
+
+fn percentile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() { return 0.0; }
+    sorted[((sorted.len() - 1) as f64 * q).round() as usize]
+}
+
+const MC_QUANTILES: [f64; 5] = [0.05, 0.25, 0.50, 0.75, 0.95];
+
+struct McSummary {
+    label:        String,
+    runs:         usize,
+    final_equity: [f64; 5], // MC_QUANTILES
+    max_dd:       [f64; 5],
+    p_daily:      f64,      // % de caminos que rompen el límite diario
+    p_total:      f64,
+    p_any:        f64,
+}
+
+fn summarize(label: &str, paths: &[McPath]) -> McSummary {
+    let mut eq: Vec<f64> = paths.iter().map(|p| p.final_equity).collect();
+    let mut dd: Vec<f64> = paths.iter().map(|p| p.max_dd).collect();
+    eq.sort_by(f64::total_cmp);
+    dd.sort_by(f64::total_cmp);
+    let pct = |f: &dyn Fn(&McPath) -> bool| {
+        paths.iter().filter(|p| f(p)).count() as f64 / paths.len().max(1) as f64 * 100.0
+    };
+    McSummary {
+        label: label.to_string(), runs: paths.len(),
+        final_equity: MC_QUANTILES.map(|q| percentile(&eq, q)),
+        max_dd:       MC_QUANTILES.map(|q| percentile(&dd, q)),
+        p_daily: pct(&|p| p.daily_breach),
+        p_total: pct(&|p| p.total_breach),
+        p_any:   pct(&|p| p.daily_breach || p.total_breach),
+    }
+}
+
+fn print_montecarlo(realized: &McPath, summaries: &[McSummary], cfg: &Settings) {
+    println!();
+    println!("  Monte Carlo  —  real: equity {:.0}  DD {:.1}%  límite diario {}  límite total {}",
+             realized.final_equity, realized.max_dd,
+             if realized.daily_breach { "ROTO" } else { "ok" },
+             if realized.total_breach { "ROTO" } else { "ok" });
+    for s in summaries {
+        println!();
+        println!("    {} ({} caminos)          P5        P25       P50       P75       P95", s.label, s.runs);
+        println!("      Equity final   {}", s.final_equity.iter().map(|v| format!("{:>9.0}", v)).collect::<Vec<_>>().join(" "));
+        println!("      Max DD %       {}", s.max_dd.iter().map(|v| format!("{:>9.1}", v)).collect::<Vec<_>>().join(" "));
+        println!("      P(rompe diario {:.0}%) {:>5.1}%   P(rompe total {:.0}%) {:>5.1}%   P(alguno) {:>5.1}%",
+                 cfg.max_daily_loss_pct * 100.0, s.p_daily, (1.0 - cfg.equity_floor_pct) * 100.0, s.p_total, s.p_any);
+    }
+}
+
+fn save_montecarlo(summaries: &[McSummary], path: &Path) {
+    let mut f = File::create(path).expect("no se pudo crear montecarlo");
+    writeln!(f, "simulation,runs,metric,p5,p25,p50,p75,p95").unwrap();
+    for s in summaries {
+        for (metric, vals) in [("final_equity", &s.final_equity), ("max_drawdown_pct", &s.max_dd)] {
+            writeln!(f, "{},{},{},{}", s.label, s.runs, metric,
+                     vals.iter().map(|v| format!("{:.2}", v)).collect::<Vec<_>>().join(",")).unwrap();
+        }
+        writeln!(f, "{},{},p_daily_breach_pct,{:.2},,,,", s.label, s.runs, s.p_daily).unwrap();
+        writeln!(f, "{},{},p_total_breach_pct,{:.2},,,,", s.label, s.runs, s.p_total).unwrap();
+        writeln!(f, "{},{},p_any_breach_pct,{:.2},,,,", s.label, s.runs, s.p_any).unwrap();
+    }
+}
+
+// ── Reporte JSON / HTML ───────────────────────────────────────────────────────
+// Curva de equity: mark-to-market en modo portfolio; en modo por símbolo,
+// equity realizada al cierre de cada trade. Ratios anualizados sobre
+// retornos diarios (365 días: cripto opera todos los días).
+const CONSISTENCY_MAX_DAY_PCT: f64 = 40.0; // prop firm: ningún día > 40 % del beneficio total
+
+#[derive(Serialize)]
+struct CurvePoint { ts_ms: i64, date: String, equity: f64, drawdown_pct: f64 }
+
+#[derive(Serialize)]
+struct MonthReturn { month: String, return_pct: f64, pnl: f64 }
+
+#[derive(Serialize)]
+struct DayPnl { date: String, pnl: f64, trades: usize }
+
+#[derive(Serialize)]
+struct Bucket { from: f64, to: f64, days: usize }
+
+#[derive(Serialize)]
+struct Report {
+    mode:                  String,
+    timeframe:             String,
+    initial_balance:       f64,
+    final_equity:          f64,
+    return_pct:            f64,
+    cagr_pct:              f64,
+    max_drawdown_pct:      f64,
+    sharpe:                f64,
+    sortino:               f64,
+    calmar:                f64,
+    expectancy_r:          f64, // PnL neto medio en múltiplos del riesgo inicial
+    longest_losing_streak: usize,
+    avg_holding_hours:     f64,
+    best_day_share_pct:    f64, // mejor día / beneficio neto total
+    consistency_ok:        bool,
+    summary:               Stats,
+    per_symbol:            Vec<Stats>,
+    monthly_returns:       Vec<MonthReturn>,
+    daily_pnl:             Vec<DayPnl>,
+    daily_pnl_histogram:   Vec<Bucket>,
+    equity_curve:          Vec<CurvePoint>,
+}
+
+fn build_report(symbols: &[&str], trades: &[Trade], mtm: Option<&[EquityPoint]>, cfg: &Settings) -> Report {
+    let initial = cfg.initial_balance;
+    let mut sorted: Vec<&Trade> = trades.iter().collect();
+    sorted.sort_by_key(|t| t.exit_ts);
+
+    // Curva (ts, equity)
+    let points: Vec<(i64, f64)> = match mtm {
+        Some(curve) => curve.iter().map(|e| (e.ts_ms, e.equity)).collect(),
+        None => {
+            let mut eq = initial;
+            sorted.iter().map(|t| { eq += t.pnl; (t.exit_ts, eq) }).collect()
+        }
+    };
+    let mut peak = initial;
+    let equity_curve: Vec<CurvePoint> = points.iter().map(|&(ts, eq)| {
+        peak = peak.max(eq);
+        CurvePoint { ts_ms: ts, date: ms_to_date(ts), equity: eq, drawdown_pct: (peak - eq) / peak * 100.0 }
+    }).collect();
+    let max_dd = equity_curve.iter().map(|p| p.drawdown_pct).fold(0.0, f64::max);
+    let final_equity = points.last().map_or(initial, |p| p.1);
+
+    // Equity al cierre de cada día / mes (días sin puntos repiten el anterior)
+    let mut day_close: BTreeMap<i64, f64> = BTreeMap::new();
+    for &(ts, eq) in &points { day_close.insert(ts / 86_400_000, eq); }
+    let mut daily_ret: Vec<f64> = Vec::new();
+    if let (Some((&first, _)), Some((&last, _))) = (day_close.first_key_value(), day_close.last_key_value()) {
+        let mut prev = initial;
+        for d in first..=last {
+            let eq = day_close.get(&d).copied().unwrap_or(prev);
+            daily_ret.push(eq / prev - 1.0);
+            prev = eq;
+        }
+    }
+    let n = daily_ret.len().max(1) as f64;
+    let mean = daily_ret.iter().sum::<f64>() / n;
+    let std  = (daily_ret.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n).sqrt();
+    let down = (daily_ret.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / n).sqrt();
+    let sharpe  = if std  > 0.0 { mean / std  * 365f64.sqrt() } else { 0.0 };
+    let sortino = if down > 0.0 { mean / down * 365f64.sqrt() } else { 0.0 };
+    let years   = daily_ret.len() as f64 / 365.0;
+    let cagr    = if years > 0.0 && final_equity > 0.0 {
+        ((final_equity / initial).powf(1.0 / years) - 1.0) * 100.0
+    } else { 0.0 };
+    let calmar  = if max_dd > 0.0 { cagr / max_dd } else { 0.0 };
+
+    let mut month_close: BTreeMap<String, f64> = BTreeMap::new();
+    for &(ts, eq) in &points { month_close.insert(ms_to_date(ts)[..7].to_string(), eq); }
+    let mut prev = initial;
+    let monthly_returns: Vec<MonthReturn> = month_close.into_iter().map(|(month, eq)| {
+        let m = MonthReturn { month, return_pct: (eq / prev - 1.0) * 100.0, pnl: eq - prev };
+        prev = eq;
+        m
+    }).collect();
+
+    // PnL realizado por día de salida
+    let mut by_day: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for t in &sorted {
+        let e = by_day.entry(t.exit_ts / 86_400_000).or_insert((0.0, 0));
+        e.0 += t.pnl; e.1 += 1;
+    }
+    let daily_pnl: Vec<DayPnl> = by_day.iter()
+        .map(|(&d, &(pnl, trades))| DayPnl { date: ms_to_date(d * 86_400_000)[..10].to_string(), pnl, trades })
+        .collect();
+    let net: f64 = trades.iter().map(|t| t.pnl).sum();
+    let best_day = daily_pnl.iter().map(|d| d.pnl).fold(0.0, f64::max);
+    let best_day_share = if net > 0.0 { best_day / net * 100.0 } else { 0.0 };
+
+    let lo = daily_pnl.iter().map(|d| d.pnl).fold(0.0, f64::min);
+    let hi = daily_pnl.iter().map(|d| d.pnl).fold(0.0, f64::max);
+    let width = ((hi - lo) / 20.0).max(1.0);
+    let mut daily_pnl_histogram: Vec<Bucket> = (0..20)
+        .map(|k| Bucket { from: lo + width * k as f64, to: lo + width * (k + 1) as f64, days: 0 })
+        .collect();
+    for d in &daily_pnl {
+        let k = (((d.pnl - lo) / width) as usize).min(19);
+        daily_pnl_histogram[k].days += 1;
+    }
+
+    // R, rachas y duración
+    let r_mult: Vec<f64> = sorted.iter().filter_map(|t| {
+        let risk = (t.entry - t.sl).abs() * t.qty;
+        (risk > 0.0).then(|| t.pnl / risk)
+    }).collect();
+    let expectancy_r = if r_mult.is_empty() { 0.0 } else { r_mult.iter().sum::<f64>() / r_mult.len() as f64 };
+    let (mut streak, mut longest) = (0usize, 0usize);
+    for t in &sorted {
+        if t.pnl <= 0.0 { streak += 1; longest = longest.max(streak); } else { streak = 0; }
+    }
+    let avg_holding_hours = if trades.is_empty() { 0.0 } else {
+        trades.iter().map(|t| (t.exit_ts - t.entry_ts) as f64 / 3_600_000.0).sum::<f64>() / trades.len() as f64
+    };
+
+    Report {
+        mode: format!("{:?}", cfg.mode),
+        timeframe: cfg.timeframe.clone(),
+        initial_balance: initial,
+        final_equity,
+        return_pct: (final_equity / initial - 1.0) * 100.0,
+        cagr_pct: cagr, max_drawdown_pct: max_dd, sharpe, sortino, calmar, expectancy_r,
+        longest_losing_streak: longest, avg_holding_hours,
+        best_day_share_pct: best_day_share,
+        consistency_ok: net > 0.0 && best_day_share <= CONSISTENCY_MAX_DAY_PCT,
+        summary: compute_stats("ALL", trades, initial),
+        per_symbol: symbols.iter().map(|&s| {
+            let t: Vec<Trade> = trades.iter().filter(|t| t.symbol == s).cloned().collect();
+            compute_stats(s, &t, initial)
+        }).collect(),
+        monthly_returns, daily_pnl, daily_pnl_histogram, equity_curve,
+    }
+}
+
+/// Polilínea SVG de `values` escalada a `w`×`h` (máx. ~1500 puntos).
+fn svg_polyline(values: &[f64], w: f64, h: f64, lo: f64, hi: f64) -> String {
+    let step = (values.len() / 1500).max(1);
+    let span = (hi - lo).max(f64::EPSILON);
+    let last = (values.len().max(2) - 1) as f64;
+    values.iter().enumerate().step_by(step)
+        .map(|(k, v)| format!("{:.1},{:.1}", k as f64 / last * w, h - (v - lo) / span * h))
+        .collect::<Vec<_>>().join(" ")
+}
+
+fn render_html(r: &Report) -> String {
+    let (w, h) = (960.0, 260.0);
+    let equity: Vec<f64> = r.equity_curve.iter().map(|p| p.equity).collect();
+    let dd: Vec<f64> = r.equity_curve.iter().map(|p| -p.drawdown_pct).collect();
+    let eq_lo = equity.iter().copied().fold(r.initial_balance, f64::min);
+    let eq_hi = equity.iter().copied().fold(r.initial_balance, f64::max);
+    let dd_lo = dd.iter().copied().fold(0.0, f64::min);
+    let first = r.equity_curve.first().map_or(String::new(), |p| p.date[..10].to_string());
+    let last  = r.equity_curve.last().map_or(String::new(), |p| p.date[..10].to_string());
+
+    let metric = |k: &str, v: String| format!("<tr><th>{}</th><td>{}</td></tr>", k, v);
+    let metrics = [
+        metric("Modo", format!("{} — velas {}", r.mode, r.timeframe)),
+        metric("Equity final", format!("{:.2} USDT ({:+.2}%)", r.final_equity, r.return_pct)),
+        metric("CAGR", format!("{:.2}%", r.cagr_pct)),
+        metric("Max drawdown", format!("{:.2}%", r.max_drawdown_pct)),
+        metric("Sharpe / Sortino / Calmar", format!("{:.2} / {:.2} / {:.2}", r.sharpe, r.sortino, r.calmar)),
+        metric("Trades", format!("{} ({} W / {} L)", r.summary.trades, r.summary.wins, r.summary.losses)),
+        metric("Win rate / Profit factor", format!("{:.1}% / {:.2}", r.summary.win_rate, r.summary.profit_factor)),
+        metric("Expectancy", format!("{:+.3} R", r.expectancy_r)),
+        metric("Racha perdedora máx.", r.longest_losing_streak.to_string()),
+        metric("Duración media", format!("{:.1} h", r.avg_holding_hours)),
+        metric("Mejor día / beneficio total", format!("{:.1}% {}", r.best_day_share_pct,
+               if r.consistency_ok { "✅ ≤ 40%" } else { "❌ regla 40%" })),
+    ].concat();
+
+    let symbols: String = r.per_symbol.iter().map(|s| format!(
+        "<tr><td>{}</td><td>{}</td><td>{:.1}%</td><td>{:.2}</td><td>{:+.2}</td><td>{:.1}%</td></tr>",
+        s.symbol, s.trades, s.win_rate, s.profit_factor, s.total_pnl, s.max_drawdown)).collect();
+
+    // Tabla año × mes
+    let mut years: BTreeMap<&str, [Option<f64>; 12]> = BTreeMap::new();
+    for m in &r.monthly_returns {
+        let month: usize = m.month[5..7].parse().unwrap_or(1);
+        years.entry(&m.month[..4]).or_insert([None; 12])[month - 1] = Some(m.return_pct);
+    }
+    let monthly: String = years.iter().map(|(y, months)| {
+        let cells: String = months.iter().map(|m| match m {
+            Some(v) => format!("<td class=\"{}\">{:+.1}</td>", if *v >= 0.0 { "pos" } else { "neg" }, v),
+            None    => "<td></td>".to_string(),
+        }).collect();
+        format!("<tr><th>{}</th>{}</tr>", y, cells)
+    }).collect();
+
+    let max_days = r.daily_pnl_histogram.iter().map(|b| b.days).max().unwrap_or(1).max(1) as f64;
+    let bar_w = w / r.daily_pnl_histogram.len().max(1) as f64;
+    let bars: String = r.daily_pnl_histogram.iter().enumerate().map(|(k, b)| {
+        let bh = b.days as f64 / max_days * (h - 20.0);
+        format!("<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" class=\"{}\"><title>{:.0} … {:.0}: {} días</title></rect>",
+                k as f64 * bar_w + 1.0, h - bh, bar_w - 2.0, bh,
+                if b.to <= 0.0 { "neg" } else { "pos" }, b.from, b.to, b.days)
+    }).collect();
+
+    format!(r##"<!DOCTYPE html>
+<html lang="es"><head><meta charset="utf-8"><title>FVG Backtest</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; margin-bottom: 1.5em; }}
+th, td {{ padding: 4px 10px; border-bottom: 1px solid #ddd; text-align: right; }}
+th {{ text-align: left; }}
+td.pos, rect.pos {{ color: #11772d; fill: #2ca02c; }}
+td.neg, rect.neg {{ color: #b00020; fill: #d62728; }}
+svg {{ background: #fafafa; border: 1px solid #ddd; }}
+</style></head><body>
+<h1>FVG Backtest</h1>
+<p>{first} → {last}</p>
+<table>{metrics}</table>
+<h2>Equity</h2>
+<svg width="{w}" height="{h}"><polyline fill="none" stroke="#1f77b4" stroke-width="1.5" points="{eq_line}"/></svg>
+<p>{eq_lo:.0} – {eq_hi:.0} USDT</p>
+<h2>Drawdown</h2>
+<svg width="{w}" height="{h}"><polyline fill="none" stroke="#d62728" stroke-width="1.5" points="{dd_line}"/></svg>
+<p>0 – {dd_lo:.1}%</p>
+<h2>Retornos mensuales (%)</h2>
+<table><tr><th></th><th>Ene</th><th>Feb</th><th>Mar</th><th>Abr</th><th>May</th><th>Jun</th><th>Jul</th><th>Ago</th><th>Sep</th><th>Oct</th><th>Nov</th><th>Dic</th></tr>{monthly}</table>
+<h2>Distribución de PnL diario</h2>
+<svg width="{w}" height="{h}">{bars}</svg>
+<h2>Por símbolo</h2>
+<table><tr><th>Símbolo</th><th>Trades</th><th>WR</th><th>PF</th><th>Neto</th><th>Max DD</th></tr>{symbols}</table>
+</body></html>
+"##,
+        eq_line = svg_polyline(&equity, w, h, eq_lo, eq_hi),
+        dd_line = svg_polyline(&dd, w, h, dd_lo, 0.0),
+    )
+}
+
 // ── Trade log CSV ─────────────────────────────────────────────────────────────
 fn save_trades(trades: &[Trade], path: &Path) {
     let mut f = File::create(path).expect("no se pudo crear trade log");
-    writeln!(f, "symbol,side,entry_date,exit_date,entry,exit,qty,sl,tp1,pnl,pnl_pct,reason").unwrap();
+    writeln!(f, "symbol,side,entry_date,exit_date,entry,exit,qty,sl,tp1,\
+                 gross_pnl,fees,slippage,funding,pnl,pnl_pct,reason").unwrap();
     for t in trades {
         let side = match t.side { Side::Long => "Long", Side::Short => "Short" };
-        writeln!(f, "{},{},{},{},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{}",
+        writeln!(f, "{},{},{},{},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4},{}",
             t.symbol, side, ms_to_date(t.entry_ts), ms_to_date(t.exit_ts),
-            t.entry, t.exit, t.qty, t.sl, t.tp1, t.pnl, t.pnl_pct, t.reason
+            t.entry, t.exit, t.qty, t.sl, t.tp1,
+            t.gross_pnl, t.fees, t.slippage, t.funding, t.pnl, t.pnl_pct, t.reason
         ).unwrap();
     }
 }
 
-fn ms_to_date(ms: i64) -> String {
-    let s = ms / 1000;
-    let (hh, mm) = ((s / 3600) % 24, (s / 60) % 60);
-    let mut days = s / 86400;
-    let mut y = 1970i32;
-    loop {
-        let dy = if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) { 366 } else { 365 };
-        if days < dy { break; }
-        days -= dy; y += 1;
-    }
-    let leap = y % 4 == 0 && (y % 100 != 0 || y % 400 == 0);
-    let dm = if leap { [31,29,31,30,31,30,31,31,30,31,30,31] }
-             else    { [31,28,31,30,31,30,31,31,30,31,30,31] };
-    let mut mo = 1;
-    for d in &dm { if days < *d { break; } days -= *d; mo += 1; }
-    format!("{:04}-{:02}-{:02} {:02}:{:02}", y, mo, days + 1, hh, mm)
-}
-
 // ── Main ──────────────────────────────────────────────────────────────────────
 fn main() {
-    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("data");
+    let cli = Cli::parse();
+    let cfg = Settings::from_cli(&cli);
+    let data_dir = cli.data_dir.clone().unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("data"));
+    let out_dir  = cli.out_dir.clone().unwrap_or_else(|| data_dir.clone());
+    std::fs::create_dir_all(&out_dir).expect("no se pudo crear el directorio de salida");
+    let mut file_params = match cli.params.as_deref().map(load_params).transpose() {
+        Ok(params) => params.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("  ✖  {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Rango de fechas [from 00:00, to 24:00) UTC
+    let from_ms = cli.from.map_or(i64::MIN, |d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis());
+    let to_ms   = cli.to.map_or(i64::MAX, |d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis() + 86_400_000);
 
     println!("\n╔═══════════════════════════════════════════════════════╗");
-    println!("║          FVG BACKTESTER  —  4 años  —  velas 4H      ║");
+    println!("║          FVG BACKTESTER  —  velas {:<4}                ║", cfg.timeframe);
     println!("║  Capital: ${}   Riesgo: {}%   Max DD diario: {}%   ║",
-             INITIAL_BALANCE as u32, (MAX_RISK_PCT*100.0) as u32,
-             (MAX_DAILY_LOSS_PCT*100.0) as u32);
+             cfg.initial_balance as u32, cfg.max_risk_pct * 100.0,
+             cfg.max_daily_loss_pct * 100.0);
+    println!("║  Costes: taker {:.3}%  maker {:.3}%  + funding        ║",
+             cfg.taker_fee * 100.0, cfg.maker_fee * 100.0);
+    println!("║  Slippage: {:<43}║", cfg.slippage.to_string());
     println!("╚═══════════════════════════════════════════════════════╝");
+    if cli.from.is_some() || cli.to.is_some() {
+        println!("  Rango: {} → {}",
+                 cli.from.map_or("inicio".to_string(), |d| d.to_string()),
+                 cli.to.map_or("fin".to_string(), |d| d.to_string()));
+    }
 
-    let mut all_trades: Vec<Trade> = Vec::new();
+    let instruments = backtest_common::require_instruments(&data_dir);
+    let mut data: Vec<SymbolData> = Vec::new();
+    let mut base: Vec<SymbolParams> = Vec::new();
+    for symbol in &cli.symbols {
+        let Some(tick) = instruments.get(symbol).map(|i| i.tick_size) else {
+            eprintln!("  ⚠  {} no está en {:?} — ejecuta `download` para este símbolo",
+                      symbol, backtest_common::instruments_path(&data_dir));
+            continue;
+        };
+        let Some(candles) = load_candles(&data_dir, symbol, &cfg.timeframe, cfg.bar_ms, (from_ms, to_ms),
+                                         cli.store, cli.allow_dirty_data) else {
+            eprintln!("  ⚠  No existe: {:?}", data_dir.join(format!("{}_{}.csv", symbol, cfg.timeframe)));
+            continue;
+        };
+        println!("  {} … {} velas", symbol, candles.len());
 
-    for &symbol in SYMBOLS {
-        let csv = data_dir.join(format!("{}_4H.csv", symbol));
-        if !csv.exists() { eprintln!("  ⚠  No existe: {:?}", csv); continue; }
+        let funding = load_funding(&data_dir.join(format!("{}_funding.csv", symbol)));
+        if funding.is_empty() {
+            println!("    (sin {}_funding.csv — funding no incluido)", symbol);
+        }
+        let costs = Costs { tick, funding, taker_fee: cfg.taker_fee, maker_fee: cfg.maker_fee, slippage: cfg.slippage };
+
+        let ltf = match &cfg.ltf {
+            Some(suffix) => load_candles(&data_dir, symbol, suffix, timeframe_ms(suffix).unwrap(), (i64::MIN, i64::MAX),
+                                         cli.store, cli.allow_dirty_data).unwrap_or_default(),
+            None => Vec::new(),
+        };
+        base.push(file_params.remove(symbol).unwrap_or_else(|| {
+            if cli.params.is_some() { println!("    (sin parámetros en --params — se usan los internos)"); }
+            symbol_params(symbol)
+        }));
+        data.push(SymbolData { symbol: symbol.clone(), candles, costs, ltf });
+    }
+
+    let mut all_trades: Vec<Trade> = Vec::new();
+    let mut ambiguity = AmbiguityStats::default();
+    let mut mtm_curve: Option<Vec<EquityPoint>> = None;
 
-        print!("  {} … cargando", symbol);
-        let candles = load_csv(&csv);
-        println!(" {} velas  →  ejecutando …", candles.len());
+    match cfg.mode {
+        Mode::PerSymbol => {
+            for (d, p) in data.iter().zip(&base) {
+                let (trades, amb) = backtest_symbol(&d.symbol, &d.candles, &d.costs, &d.ltf, p, &cfg);
+                print_stats(&compute_stats(&d.symbol, &trades, cfg.initial_balance));
+                print_ambiguity(&amb, cfg.ltf.as_deref().filter(|_| !d.ltf.is_empty()), cfg.ambiguity_policy);
+                ambiguity.bars         += amb.bars;
+                ambiguity.resolved_ltf += amb.resolved_ltf;
+                ambiguity.fallback     += amb.fallback;
+                all_trades.extend(trades);
+            }
+        }
+        Mode::Portfolio => {
+            println!("\n  Modo portfolio: balance compartido, máx {} posiciones abiertas", cfg.max_positions);
+            let (trades, amb, curve) = backtest_portfolio(&data, &base, &cfg);
+            ambiguity = amb;
+            // Desglose por símbolo (pnl_pct relativo al balance compartido)
+            for d in &data {
+                let sym: Vec<Trade> = trades.iter().filter(|t| t.symbol == d.symbol).cloned().collect();
+                print_stats(&compute_stats(&d.symbol, &sym, cfg.initial_balance));
+            }
+            all_trades = trades;
 
-        let trades = backtest_symbol(symbol, &candles);
-        let stats  = compute_stats(symbol, &trades);
-        print_stats(&stats);
-        all_trades.extend(trades);
+            let final_equity = curve.last().map_or(cfg.initial_balance, |e| e.equity);
+            let max_open = curve.iter().map(|e| e.open).max().unwrap_or(0);
+            println!();
+            println!("  Equity final {:.2} USDT  ({:+.1}%)   DD máx (mark-to-market) {:.1}%   Máx abiertas {}",
+                     final_equity, (final_equity / cfg.initial_balance - 1.0) * 100.0,
+                     curve_max_drawdown(&curve, cfg.initial_balance), max_open);
+            let eq = out_dir.join("backtest_equity.csv");
+            save_equity_curve(&curve, &eq);
+            println!("  📈 Equity curve guardada: {:?}", eq);
+            mtm_curve = Some(curve);
+        }
     }
 
-    print_global(&all_trades);
+    print_global(&all_trades, data.len(), cfg.initial_balance);
+    println!();
+    println!("  Velas ambiguas (SL y TP en la misma vela {}):", cfg.timeframe);
+    print_ambiguity(&ambiguity, cfg.ltf.as_deref(), cfg.ambiguity_policy);
 
-    let log = data_dir.join("backtest_trades.csv");
+    let log = out_dir.join("backtest_trades.csv");
     save_trades(&all_trades, &log);
-    println!("\n  📄 Trade log guardado: {:?}\n", log);
+    println!("\n  📄 Trade log guardado: {:?}", log);
+
+    let symbols: Vec<&str> = data.iter().map(|d| d.symbol.as_str()).collect();
+    let report = build_report(&symbols, &all_trades, mtm_curve.as_deref(), &cfg);
+    let json = out_dir.join("backtest_report.json");
+    let html = out_dir.join("backtest_report.html");
+    std::fs::write(&json, serde_json::to_string_pretty(&report).unwrap()).expect("no se pudo crear reporte JSON");
+    std::fs::write(&html, render_html(&report)).expect("no se pudo crear reporte HTML");
+    println!("  📊 Reporte: {:?} / {:?}", json, html);
+
+    // ── Monte Carlo ───────────────────────────────────────────────────────────
+    if !all_trades.is_empty() && (cfg.mc_runs > 0 || cfg.mc_param_runs > 0) {
+        let mut rng = Rng(MC_SEED);
+        let (returns, days) = trade_returns(&all_trades);
+        let realized = replay(&returns, &days, &cfg);
+        let mut summaries = Vec::new();
+        if cfg.mc_runs > 0 {
+            summaries.push(summarize(&format!("{:?}", cfg.mc_method), &monte_carlo(&all_trades, &mut rng, &cfg)));
+        }
+        if cfg.mc_param_runs > 0 {
+            let paths = param_monte_carlo(&data, &base, &mut rng, &cfg);
+            eprintln!();
+            summaries.push(summarize(&format!("Parámetros ±{:.0}%", MC_PARAM_JITTER * 100.0), &paths));
+        }
+        print_montecarlo(&realized, &summaries, &cfg);
+        let mc = out_dir.join("backtest_montecarlo.csv");
+        save_montecarlo(&summaries, &mc);
+        println!("\n  🎲 Monte Carlo guardado: {:?}", mc);
+    }
+    println!();
 }
@@ -0,0 +1,143 @@
+/// Conversión entre los CSV de velas y el store binario columnar (candle_store)
+///
+///   to-bin   {SYMBOL}_{tf}.csv → {SYMBOL}_{tf}.bin   (+ candles.index.json)
+///   to-csv   {SYMBOL}_{tf}.bin → {SYMBOL}_{tf}.csv
+///
+/// El CSV se valida antes de convertir (data_quality): con errores no se
+/// escribe el .bin salvo --allow-dirty-data. backtest / optimize leen el .bin
+/// cuando existe (--store auto).
+/// Run: cargo run --bin convert --release -- to-bin [opciones]   (--help para la lista)
+#[allow(dead_code)]
+#[path = "../candle_store.rs"]
+mod candle_store;
+#[allow(dead_code)]
+#[path = "../data_quality.rs"]
+mod data_quality;
+#[allow(dead_code)]
+#[path = "../backtest_common.rs"]
+mod backtest_common;
+
+use backtest_common::{parse_timeframe, timeframe_ms};
+use chrono::DateTime;
+use clap::{Args, Parser, Subcommand};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const CANDLE_HEADER: &str = "timestamp_ms,datetime_utc,open,high,low,close,volume,turnover";
+
+// ── CLI ───────────────────────────────────────────────────────────────────────
+#[derive(Parser)]
+#[command(name = "convert", about = "Convierte velas entre CSV y el store binario")]
+struct Cli {
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// CSV → binario
+    ToBin(Sel),
+    /// Binario → CSV
+    ToCsv(Sel),
+}
+
+#[derive(Args)]
+struct Sel {
+    /// Símbolos, separados por coma [por defecto: todos los del data-dir]
+    #[arg(long, value_delimiter = ',')]
+    symbols: Vec<String>,
+    /// Timeframes, separados por coma
+    #[arg(long, value_delimiter = ',', default_value = "4H", value_parser = parse_timeframe)]
+    timeframes: Vec<String>,
+    /// Directorio de datos [por defecto: data/ del repo]
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+    /// Convertir aunque el CSV tenga errores de validación (se escriben las filas legibles)
+    #[arg(long)]
+    allow_dirty_data: bool,
+}
+
+/// Símbolos con fichero `{SYMBOL}_{tf}.{ext}` en `dir`, ordenados.
+fn discover(dir: &Path, tf: &str, ext: &str) -> Vec<String> {
+    let suffix = format!("_{}.{}", tf, ext);
+    let mut symbols: Vec<String> = std::fs::read_dir(dir).into_iter().flatten()
+        .filter_map(|e| e.ok()?.file_name().to_str()?.strip_suffix(&suffix).map(str::to_string))
+        .collect();
+    symbols.sort();
+    symbols
+}
+
+// ── Conversión ────────────────────────────────────────────────────────────────
+fn to_bin(dir: &Path, symbol: &str, tf: &str, bar_ms: i64, allow_dirty: bool) -> bool {
+    let csv = dir.join(format!("{}_{}.csv", symbol, tf));
+    let (bars, quality) = match data_quality::check_csv(&csv, bar_ms) {
+        Ok(r)  => r,
+        Err(e) => { eprintln!("  ⚠  {:?}: {}", csv, e); return false; }
+    };
+    for line in quality.summary() {
+        println!("  {:<10} {:>4}  ⚠  {}", symbol, tf, line);
+    }
+    if quality.errors() > 0 && !allow_dirty {
+        eprintln!("  ✖  {:<10} {:>4}  no se convierte: repara con `download --repair` o usa --allow-dirty-data", symbol, tf);
+        return false;
+    }
+    let bin = candle_store::store_path(dir, symbol, tf);
+    candle_store::write(&bin, bar_ms, &bars)
+        .and_then(|_| candle_store::update_index(dir, symbol, tf))
+        .unwrap_or_else(|e| panic!("no se pudo escribir {:?}: {}", bin, e));
+    let (csv_len, bin_len) = (file_len(&csv), file_len(&bin));
+    println!("  {:<10} {:>4}  {:>7} velas   {:>8.1} MB → {:>7.1} MB",
+             symbol, tf, bars.len(), csv_len as f64 / 1e6, bin_len as f64 / 1e6);
+    true
+}
+
+fn to_csv(dir: &Path, symbol: &str, tf: &str) -> bool {
+    let bin = candle_store::store_path(dir, symbol, tf);
+    let file = match candle_store::CandleFile::open(&bin) {
+        Ok(f)  => f,
+        Err(e) => { eprintln!("  ⚠  {:?}: {}", bin, e); return false; }
+    };
+    let csv = dir.join(format!("{}_{}.csv", symbol, tf));
+    let tmp = csv.with_extension("csv.tmp");
+    let mut w = BufWriter::new(File::create(&tmp).expect("no se pudo crear CSV"));
+    writeln!(w, "{}", CANDLE_HEADER).unwrap();
+    for b in file.bars(0..file.rows) {
+        let dt = DateTime::from_timestamp_millis(b.ts_ms).map_or(String::new(), |d| d.format("%Y-%m-%d %H:%M").to_string());
+        // turnover no se guarda en el binario: columna vacía, como en download
+        writeln!(w, "{},{},{},{},{},{},{},", b.ts_ms, dt, b.open, b.high, b.low, b.close, b.volume).unwrap();
+    }
+    drop(w);
+    std::fs::rename(&tmp, &csv).expect("no se pudo reemplazar CSV");
+    println!("  {:<10} {:>4}  {:>7} velas → {:?}", symbol, tf, file.rows, csv);
+    true
+}
+
+fn file_len(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |m| m.len())
+}
+
+// ── Main ──────────────────────────────────────────────────────────────────────
+fn main() {
+    let cli = Cli::parse();
+    let (sel, ext) = match &cli.cmd {
+        Cmd::ToBin(s) => (s, "csv"),
+        Cmd::ToCsv(s) => (s, "bin"),
+    };
+    let dir = sel.data_dir.clone().unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("data"));
+
+    let (mut ok, mut failed) = (0, 0);
+    for tf in &sel.timeframes {
+        let bar_ms = timeframe_ms(tf).unwrap();
+        let symbols = if sel.symbols.is_empty() { discover(&dir, tf, ext) } else { sel.symbols.clone() };
+        for symbol in &symbols {
+            let done = match cli.cmd {
+                Cmd::ToBin(_) => to_bin(&dir, symbol, tf, bar_ms, sel.allow_dirty_data),
+                Cmd::ToCsv(_) => to_csv(&dir, symbol, tf),
+            };
+            if done { ok += 1 } else { failed += 1 }
+        }
+    }
+    println!("\n✅ {} ficheros convertidos, {} con error.\n", ok, failed);
+    if failed > 0 { std::process::exit(1); }
+}
@@ -0,0 +1,429 @@
+/// Descarga de históricos de Bybit (API pública, sin auth) → {data-dir}/
+///
+///   {SYMBOL}_{tf}.csv            velas (mismo formato que leen backtest / optimize)
+///   {SYMBOL}_funding.csv         funding rates (cada 8h)
+///   *.manifest.json              rango descargado, nº de filas, última actualización
+///   instruments.json             tick size de cada símbolo (lo usan backtest / optimize)
+///
+/// Incremental: si el CSV ya existe solo se piden las velas anteriores a la
+/// primera (si --days / --from piden más historia) y posteriores a la última.
+/// Tras cada actualización el CSV de velas se valida (data_quality); con
+/// --repair se vuelven a pedir los huecos y las velas anómalas, salvo las que
+/// el manifest marca como irreparables. Si ya existe el store binario
+/// ({SYMBOL}_{tf}.bin, bin convert) se regenera con el CSV.
+/// Run: cargo run --bin download --release -- [opciones]   (--help para la lista)
+// Módulos del bot: solo se usan las llamadas públicas de market data.
+#[allow(dead_code)]
+#[path = "../backtest_common.rs"]
+mod backtest_common;
+#[allow(dead_code)]
+#[path = "../bybit_api.rs"]
+mod bybit_api;
+#[allow(dead_code)]
+#[path = "../candle_store.rs"]
+mod candle_store;
+#[allow(dead_code)]
+#[path = "../config.rs"]
+mod config;
+#[allow(dead_code)]
+#[path = "../data_quality.rs"]
+mod data_quality;
+#[allow(dead_code)]
+#[path = "../types.rs"]
+mod types;
+
+use bybit_api::BybitClient;
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::Parser;
+use data_quality::Quality;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+// ── Valores por defecto (sobrescribibles por CLI) ─────────────────────────────
+const SYMBOLS: &[&str] = &["BTCUSDT","ETHUSDT","BNBUSDT","XRPUSDT","SOLUSDT"];
+const HISTORY_DAYS: i64 = 4 * 365;
+const CANDLE_HEADER:  &str = "timestamp_ms,datetime_utc,open,high,low,close,volume,turnover";
+const FUNDING_HEADER: &str = "timestamp_ms,datetime_utc,funding_rate";
+
+// ── CLI ───────────────────────────────────────────────────────────────────────
+#[derive(Parser)]
+#[command(name = "download", about = "Descarga incremental de velas y funding de Bybit")]
+struct Cli {
+    /// Símbolos, separados por coma
+    #[arg(long, value_delimiter = ',', default_values_t = SYMBOLS.iter().map(|s| s.to_string()))]
+    symbols: Vec<String>,
+    /// Timeframes, separados por coma — 1m, 5m, 15m, 1H, 4H, 1D…
+    #[arg(long, value_delimiter = ',', default_value = "4H", value_parser = parse_timeframe)]
+    timeframes: Vec<String>,
+    /// Días de historia hacia atrás desde hoy
+    #[arg(long, default_value_t = HISTORY_DAYS)]
+    days: i64,
+    /// Primera fecha (YYYY-MM-DD, UTC); tiene prioridad sobre --days
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Directorio de destino [por defecto: data/ del repo]
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+    /// No descargar el historial de funding
+    #[arg(long)]
+    no_funding: bool,
+    /// Volver a pedir los huecos y las velas anómalas que encuentre la validación
+    #[arg(long)]
+    repair: bool,
+    /// No ampliar el rango: solo validar (y reparar con --repair) los CSV existentes
+    #[arg(long)]
+    no_update: bool,
+}
+
+/// Timeframe de los CSV ("4H") → (intervalo de Bybit, duración en ms).
+fn bybit_interval(tf: &str) -> Option<(&'static str, i64)> {
+    Some(match tf {
+        "1m"  => ("1",   60_000),
+        "3m"  => ("3",   3 * 60_000),
+        "5m"  => ("5",   5 * 60_000),
+        "15m" => ("15",  15 * 60_000),
+        "30m" => ("30",  30 * 60_000),
+        "1H"  => ("60",  3_600_000),
+        "2H"  => ("120", 2 * 3_600_000),
+        "4H"  => ("240", 4 * 3_600_000),
+        "6H"  => ("360", 6 * 3_600_000),
+        "12H" => ("720", 12 * 3_600_000),
+        "1D"  => ("D",   86_400_000),
+        _     => return None,
+    })
+}
+
+fn parse_timeframe(tf: &str) -> Result<String, String> {
+    bybit_interval(tf).map(|_| tf.to_string())
+        .ok_or_else(|| format!("timeframe no soportado por Bybit: {} (1m 3m 5m 15m 30m 1H 2H 4H 6H 12H 1D)", tf))
+}
+
+// ── Manifest ──────────────────────────────────────────────────────────────────
+/// Sidecar de cada CSV: qué rango contiene y cuándo se actualizó.
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    symbol:     String,
+    series:     String, // timeframe ("4H") o "funding"
+    interval:   String, // intervalo de Bybit ("240"); vacío para funding
+    source:     String,
+    first_ts:   i64,
+    last_ts:    i64,
+    first_utc:  String,
+    last_utc:   String,
+    rows:       usize,
+    updated_at: String,
+    /// Resumen de la última validación (vacío = serie limpia); no aplica a funding
+    #[serde(default)]
+    quality:    Vec<String>,
+    /// Rangos [desde, hasta] (open time, ms) que --repair volvió a pedir y el
+    /// exchange devolvió igual: huecos reales, velas de volumen 0. No se
+    /// vuelven a pedir.
+    #[serde(default)]
+    unrepairable: Vec<(i64, i64)>,
+}
+
+fn manifest_path(csv: &Path) -> PathBuf {
+    csv.with_extension("manifest.json")
+}
+
+fn load_manifest(csv: &Path) -> Option<Manifest> {
+    serde_json::from_str(&std::fs::read_to_string(manifest_path(csv)).ok()?).ok()
+}
+
+fn save_manifest(
+    csv: &Path, symbol: &str, series: &str, interval: &str, quality: Option<&Quality>, unrepairable: &[(i64, i64)],
+) {
+    let Some((first_ts, last_ts, rows)) = csv_range(csv) else { return };
+    let m = Manifest {
+        symbol: symbol.to_string(), series: series.to_string(), interval: interval.to_string(),
+        source: "bybit v5 linear".to_string(),
+        first_ts, last_ts, first_utc: ms_to_date(first_ts), last_utc: ms_to_date(last_ts), rows,
+        updated_at: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        quality: quality.map(Quality::summary).unwrap_or_default(),
+        unrepairable: unrepairable.to_vec(),
+    };
+    std::fs::write(manifest_path(csv), serde_json::to_string_pretty(&m).unwrap())
+        .expect("no se pudo escribir el manifest");
+}
+
+// ── CSV ───────────────────────────────────────────────────────────────────────
+/// (primer ts, último ts, filas) de un CSV con cabecera y timestamp_ms en la
+/// primera columna. None si no existe o no tiene filas.
+fn csv_range(path: &Path) -> Option<(i64, i64, usize)> {
+    let file = File::open(path).ok()?;
+    let mut range: Option<(i64, i64, usize)> = None;
+    for line in BufReader::new(file).lines().skip(1).map_while(|l| l.ok()) {
+        let Some(ts) = line.split(',').next().and_then(|f| f.parse::<i64>().ok()) else { continue };
+        range = Some(match range {
+            None            => (ts, ts, 1),
+            Some((a, b, n)) => (a.min(ts), b.max(ts), n + 1),
+        });
+    }
+    range
+}
+
+fn ms_to_date(ms: i64) -> String {
+    DateTime::from_timestamp_millis(ms).map_or(String::new(), |d| d.format("%Y-%m-%d %H:%M").to_string())
+}
+
+fn candle_row(c: &types::Candle) -> String {
+    // turnover no lo devuelve fetch_klines: columna vacía (nadie la lee)
+    format!("{},{},{},{},{},{},{},", c.timestamp, ms_to_date(c.timestamp), c.open, c.high, c.low, c.close, c.volume)
+}
+
+fn funding_row(r: &(i64, f64)) -> String {
+    format!("{},{},{}", r.0, ms_to_date(r.0), r.1)
+}
+
+/// Completa el CSV: `head` (anteriores a la primera fila existente) obliga a
+/// reescribir el fichero; `tail` (posteriores a la última) se añade al final.
+fn merge_rows(path: &Path, header: &str, first_ts: Option<i64>, head: &[String], tail: &[String]) {
+    if head.is_empty() && tail.is_empty() { return; }
+    if first_ts.is_none() || !head.is_empty() {
+        let existing: Vec<String> = File::open(path).ok()
+            .map(|f| BufReader::new(f).lines().skip(1).map_while(|l| l.ok()).collect())
+            .unwrap_or_default();
+        let tmp = path.with_extension("csv.tmp");
+        let mut f = File::create(&tmp).expect("no se pudo crear CSV");
+        writeln!(f, "{}", header).unwrap();
+        for line in head.iter().chain(&existing).chain(tail) {
+            writeln!(f, "{}", line).unwrap();
+        }
+        drop(f);
+        std::fs::rename(&tmp, path).expect("no se pudo reemplazar CSV");
+    } else if !tail.is_empty() {
+        let mut f = OpenOptions::new().append(true).open(path).expect("no se pudo abrir CSV");
+        for line in tail {
+            writeln!(f, "{}", line).unwrap();
+        }
+    }
+}
+
+/// Rangos [desde, hasta] a volver a pedir: huecos y velas sueltas anómalas,
+/// fusionando las que quedan contiguas.
+fn repair_ranges(q: &Quality, bar_ms: i64) -> Vec<(i64, i64)> {
+    let mut ranges: Vec<(i64, i64)> = q.gaps()
+        .chain(q.issues.iter().filter_map(|i| i.bar_ts()).map(|ts| (ts, ts)))
+        .collect();
+    ranges.sort_unstable();
+    let mut merged: Vec<(i64, i64)> = Vec::new();
+    for (a, b) in ranges {
+        match merged.last_mut() {
+            Some(last) if a <= last.1 + bar_ms => last.1 = last.1.max(b),
+            _ => merged.push((a, b)),
+        }
+    }
+    merged
+}
+
+/// Reescribe el CSV ordenado y sin duplicados (gana la primera aparición),
+/// descartando filas ilegibles o desalineadas y sustituyendo por `fresh` las
+/// velas re-descargadas.
+fn rewrite_candles(path: &Path, bar_ms: i64, fresh: &[types::Candle]) {
+    let mut rows: BTreeMap<i64, String> = BTreeMap::new();
+    if let Ok(f) = File::open(path) {
+        for line in BufReader::new(f).lines().skip(1).map_while(|l| l.ok()) {
+            let Some(bar) = data_quality::parse_row(&line) else { continue };
+            if bar.ts_ms.rem_euclid(bar_ms) == 0 {
+                rows.entry(bar.ts_ms).or_insert(line);
+            }
+        }
+    }
+    for c in fresh {
+        rows.insert(c.timestamp, candle_row(c));
+    }
+    let tmp = path.with_extension("csv.tmp");
+    let mut f = File::create(&tmp).expect("no se pudo crear CSV");
+    writeln!(f, "{}", CANDLE_HEADER).unwrap();
+    for line in rows.values() {
+        writeln!(f, "{}", line).unwrap();
+    }
+    drop(f);
+    std::fs::rename(&tmp, path).expect("no se pudo reemplazar CSV");
+}
+
+fn print_quality(symbol: &str, tf: &str, q: &Quality) {
+    for line in q.summary() {
+        println!("  {:<10} {:>4}  ⚠  {}", symbol, tf, line);
+    }
+}
+
+// ── Descarga ──────────────────────────────────────────────────────────────────
+/// Velas cerradas de `symbol` en [start_ms, ahora) que falten en el CSV.
+/// Un rango que falla a medias se descarta entero: se pagina hacia atrás, y
+/// las páginas sueltas dejarían un hueco que csv_range no vuelve a pedir.
+async fn update_candles(client: &BybitClient, data_dir: &Path, symbol: &str, tf: &str, start_ms: i64) {
+    let (interval, bar_ms) = bybit_interval(tf).unwrap();
+    let path = data_dir.join(format!("{}_{}.csv", symbol, tf));
+    let now  = Utc::now().timestamp_millis();
+    let last_closed = (now / bar_ms - 1) * bar_ms; // open time de la última vela cerrada
+    let start = start_ms / bar_ms * bar_ms;
+    let existing = csv_range(&path);
+
+    let mut head: Vec<types::Candle> = Vec::new();
+    let mut tail: Vec<types::Candle> = Vec::new();
+    let result = async {
+        match existing {
+            Some((first, last, _)) => {
+                if start < first {
+                    head = client.fetch_klines_range(symbol, interval, start, first - 1).await?;
+                }
+                if last < last_closed {
+                    tail = client.fetch_klines_range(symbol, interval, last + 1, last_closed).await?;
+                }
+            }
+            None => tail = client.fetch_klines_range(symbol, interval, start, last_closed).await?,
+        }
+        Ok::<(), bybit_api::BybitError>(())
+    }.await;
+    if let Err(e) = result {
+        eprintln!("  ⚠  {} {}: {} — se guardan los rangos completos; el fallido se descarga de nuevo en la próxima ejecución", symbol, tf, e);
+    }
+
+    let head: Vec<String> = head.iter().map(candle_row).collect();
+    let tail: Vec<String> = tail.iter().map(candle_row).collect();
+    merge_rows(&path, CANDLE_HEADER, existing.map(|r| r.0), &head, &tail);
+
+    match csv_range(&path) {
+        Some((first, last, rows)) => println!("  {:<10} {:>4}  +{:>6} velas   {} → {}   ({} en total)",
+            symbol, tf, head.len() + tail.len(), ms_to_date(first), ms_to_date(last), rows),
+        None => println!("  {:<10} {:>4}  sin datos", symbol, tf),
+    }
+}
+
+/// Valida el CSV de velas y, con `repair`, vuelve a pedir lo que falte o esté
+/// mal y lo reescribe limpio. Lo que siga igual tras pedirlo se anota en el
+/// manifest como irreparable y las siguientes reparaciones lo saltan.
+async fn check_candles(client: &BybitClient, data_dir: &Path, symbol: &str, tf: &str, repair: bool) {
+    let (interval, bar_ms) = bybit_interval(tf).unwrap();
+    let path = data_dir.join(format!("{}_{}.csv", symbol, tf));
+    if !path.exists() { return; }
+    let check = |path: &Path| data_quality::check_csv(path, bar_ms).expect("no se pudo leer CSV");
+
+    let (mut bars, mut quality) = check(&path);
+    if !quality.is_clean() {
+        print_quality(symbol, tf, &quality);
+    }
+    let mut unrepairable = load_manifest(&path).map(|m| m.unrepairable).unwrap_or_default();
+    let within = |list: &[(i64, i64)], (a, b): (i64, i64)| list.iter().any(|&(x, y)| x <= a && b <= y);
+    if repair && !quality.is_clean() {
+        let (skipped, ranges): (Vec<_>, Vec<_>) = repair_ranges(&quality, bar_ms)
+            .into_iter().partition(|&r| within(&unrepairable, r));
+        if !skipped.is_empty() {
+            println!("  {:<10} {:>4}  {} rangos irreparables según el manifest, no se piden", symbol, tf, skipped.len());
+        }
+        let mut fresh: Vec<types::Candle> = Vec::new();
+        let mut fetched: Vec<(i64, i64)> = Vec::new();
+        for (from, to) in ranges {
+            match client.fetch_klines_range(symbol, interval, from, to).await {
+                Ok(c)  => { fresh.extend(c); fetched.push((from, to)); }
+                Err(e) => eprintln!("  ⚠  {} {}: {} → {}: {}", symbol, tf, ms_to_date(from), ms_to_date(to), e),
+            }
+        }
+        rewrite_candles(&path, bar_ms, &fresh);
+        (bars, quality) = check(&path);
+        // Lo pedido con éxito que sigue mal es como lo tiene el exchange
+        let known: Vec<(i64, i64)> = unrepairable.iter().chain(&fetched).copied().collect();
+        unrepairable = repair_ranges(&quality, bar_ms).into_iter().filter(|&r| within(&known, r)).collect();
+        println!("  {:<10} {:>4}  reparado: {} velas re-descargadas, {}",
+                 symbol, tf, fresh.len(),
+                 if quality.is_clean() { "serie limpia".to_string() } else { format!("{} incidencias pendientes", quality.issues.len()) });
+        print_quality(symbol, tf, &quality);
+    }
+    save_manifest(&path, symbol, tf, interval, Some(&quality), &unrepairable);
+
+    let bin = candle_store::store_path(data_dir, symbol, tf);
+    if bin.exists() {
+        if quality.errors() > 0 {
+            eprintln!("  ⚠  {} {}: {:?} no se actualiza (CSV con errores)", symbol, tf, bin);
+        } else {
+            candle_store::write(&bin, bar_ms, &bars)
+                .and_then(|_| candle_store::update_index(data_dir, symbol, tf))
+                .unwrap_or_else(|e| panic!("no se pudo escribir {:?}: {}", bin, e));
+        }
+    }
+}
+
+async fn update_funding(client: &BybitClient, data_dir: &Path, symbol: &str, start_ms: i64) {
+    let path = data_dir.join(format!("{}_funding.csv", symbol));
+    let now  = Utc::now().timestamp_millis();
+    let existing = csv_range(&path);
+
+    let mut head: Vec<(i64, f64)> = Vec::new();
+    let mut tail: Vec<(i64, f64)> = Vec::new();
+    let result = async {
+        match existing {
+            Some((first, last, _)) => {
+                if start_ms < first {
+                    head = client.fetch_funding_history(symbol, start_ms, first - 1).await?;
+                }
+                tail = client.fetch_funding_history(symbol, last + 1, now).await?;
+            }
+            None => tail = client.fetch_funding_history(symbol, start_ms, now).await?,
+        }
+        Ok::<(), bybit_api::BybitError>(())
+    }.await;
+    if let Err(e) = result {
+        eprintln!("  ⚠  {} funding: {} — se guardan los rangos completos; el fallido se descarga de nuevo en la próxima ejecución", symbol, e);
+    }
+
+    let head: Vec<String> = head.iter().map(funding_row).collect();
+    let tail: Vec<String> = tail.iter().map(funding_row).collect();
+    merge_rows(&path, FUNDING_HEADER, existing.map(|r| r.0), &head, &tail);
+    save_manifest(&path, symbol, "funding", "", None, &[]);
+    println!("  {:<10} {:>4}  +{:>6} registros", symbol, "fund", head.len() + tail.len());
+}
+
+/// Guarda en instruments.json el tick size de `symbols` (se conservan los
+/// demás símbolos ya guardados, p. ej. los deslistados).
+async fn update_instruments(client: &BybitClient, data_dir: &Path, symbols: &[String]) {
+    let instruments = match client.fetch_linear_instruments().await {
+        Ok(list) => list,
+        Err(e) => {
+            eprintln!("  ⚠  instruments-info: {} — no se actualiza {:?}", e, backtest_common::instruments_path(data_dir));
+            return;
+        }
+    };
+    let mut specs = backtest_common::load_instruments(data_dir).unwrap_or_default();
+    for i in instruments.iter().filter(|i| i.tick_size > 0.0) {
+        specs.insert(i.symbol.clone(), backtest_common::InstrumentSpec { tick_size: i.tick_size });
+    }
+    for s in symbols.iter().filter(|s| !specs.contains_key(*s)) {
+        eprintln!("  ⚠  {}: sin datos de instrumento en Bybit (¿deslistado?)", s);
+    }
+    backtest_common::save_instruments(data_dir, &specs)
+        .unwrap_or_else(|e| panic!("no se pudo escribir {:?}: {}", backtest_common::instruments_path(data_dir), e));
+}
+
+// ── Main ──────────────────────────────────────────────────────────────────────
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let data_dir = cli.data_dir.clone().unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("data"));
+    std::fs::create_dir_all(&data_dir).expect("no se pudo crear el directorio de datos");
+    let start_ms = match cli.from {
+        Some(d) => d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis(),
+        None    => Utc::now().timestamp_millis() - cli.days * 86_400_000,
+    };
+
+    println!("\n🚀 Descargando {} desde {} para {} pares → {:?}\n",
+             cli.timeframes.join(", "), ms_to_date(start_ms), cli.symbols.len(), data_dir);
+
+    let client = BybitClient::public();
+    update_instruments(&client, &data_dir, &cli.symbols).await;
+    for symbol in &cli.symbols {
+        for tf in &cli.timeframes {
+            if !cli.no_update {
+                update_candles(&client, &data_dir, symbol, tf, start_ms).await;
+            }
+            check_candles(&client, &data_dir, symbol, tf, cli.repair).await;
+        }
+        if !cli.no_funding && !cli.no_update {
+            update_funding(&client, &data_dir, symbol, start_ms).await;
+        }
+    }
+    println!("\n✅ Descarga completa.\n");
+}
@@ -1,19 +1,211 @@
-/// Optimizador de parámetros FVG — grid search por símbolo
-/// Run: cargo run --bin optimize --release
+/// Optimizador de parámetros FVG — grid, random search o successive halving
+/// por símbolo (muestra completa o walk-forward)
+/// Run: cargo run --bin optimize --release -- [opciones]   (--help para la lista)
+///
+///   --objectives net_pnl,max_drawdown,trades,sharpe   objetivos del frente de Pareto
+///   --weights net_pnl=1,max_drawdown=2                elige por score ponderado
+///   --maximize profit_factor                          elige el máximo de un objetivo
+///   --constraint "max_drawdown<8"                     restricción (repetible)
+///
+/// Sin --weights ni --maximize se elige por `score` (ver abajo).
+/// Objetivos: net_pnl, max_drawdown, trades, sharpe, profit_factor, win_rate.
+use chrono::NaiveDate;
+use clap::{Parser, ValueEnum};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-// ── Constantes fijas (no optimizables) ────────────────────────────────────────
+#[allow(dead_code)] // parte de la API solo la usan download / convert
+#[path = "../candle_store.rs"]
+mod candle_store;
+#[allow(dead_code)] // parte de la API solo la usa el bin download
+#[path = "../data_quality.rs"]
+mod data_quality;
+#[allow(dead_code)] // parte de la API solo la usan backtest / convert / download
+#[path = "../backtest_common.rs"]
+mod backtest_common;
+
+use backtest_common::{
+    confirmed, funding_rate_sum, load_candles, load_funding, ms_to_date, parse_slippage, parse_timeframe, timeframe_ms, Candle,
+    Costs, Rng, Slippage, Store, MAKER_FEE, SLIPPAGE, TAKER_FEE,
+};
+
+// ── Valores por defecto (sobrescribibles por CLI) ─────────────────────────────
 const SYMBOLS: &[&str]  = &["BTCUSDT","ETHUSDT","BNBUSDT","XRPUSDT","SOLUSDT"];
 const INITIAL_BALANCE: f64 = 10_000.0;
 const MAX_RISK_PCT: f64    = 0.03;
 const MAX_DAILY_LOSS_PCT: f64 = 0.05;
-const EQUITY_FLOOR_PCT: f64  = 0.90;
+const MAX_TOTAL_LOSS_PCT: f64 = 0.10; // equity floor = 90 % del balance inicial
 const ATR_PERIOD: usize  = 14;
 const VOL_AVG_PERIOD: usize = 20;
 const MIN_TRADES: usize  = 15; // mínimo para ser estadísticamente relevante
 
+// ── Modo ──────────────────────────────────────────────────────────────────────
+// FullSample: grid search sobre todo el histórico (in-sample puro).
+// WalkForward: optimiza en cada ventana IS y valida en la OOS siguiente.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Mode { FullSample, WalkForward }
+
+// ── Walk-forward ──────────────────────────────────────────────────────────────
+// Rolling: la ventana IS avanza junto a la OOS (longitud fija).
+// Anchored: la IS empieza siempre en la primera vela y crece.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum WfWindow { Rolling, Anchored }
+const WF_IS_DAYS:  f64 = 365.0; // 1 año
+const WF_OOS_DAYS: f64 = 91.0;  // 1 trimestre
+
+// ── CLI ───────────────────────────────────────────────────────────────────────
+#[derive(Parser)]
+#[command(name = "optimize", about = "Optimizador de parámetros FVG por símbolo")]
+struct Cli {
+    /// Símbolos, separados por coma
+    #[arg(long, value_delimiter = ',', default_values_t = SYMBOLS.iter().map(|s| s.to_string()))]
+    symbols: Vec<String>,
+    /// Timeframe: sufijo del CSV ({SYMBOL}_{tf}.csv) — 15m, 1H, 4H, 1D…
+    #[arg(long, default_value = "4H", value_parser = parse_timeframe)]
+    timeframe: String,
+    /// Primera fecha del histórico (YYYY-MM-DD, UTC)
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Última fecha del histórico, inclusive (YYYY-MM-DD, UTC)
+    #[arg(long)]
+    to: Option<NaiveDate>,
+    /// Directorio con los CSV [por defecto: data/ del repo]
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+    /// Directorio de salida (walk-forward, Pareto) [por defecto: data-dir]
+    #[arg(long)]
+    out_dir: Option<PathBuf>,
+    /// Mejores parámetros por símbolo, entrada de `backtest --params`
+    /// [por defecto: out-dir/optimized_params.csv]
+    #[arg(long)]
+    params: Option<PathBuf>,
+    /// Estrategia de búsqueda en JSON [por defecto: optimize_search.json del repo]
+    #[arg(long)]
+    search: Option<PathBuf>,
+    /// Muestra completa o walk-forward (--mode walk-forward: solo valida, no
+    /// escribe --params ni el frente de Pareto)
+    #[arg(long, value_enum, default_value_t = Mode::FullSample)]
+    mode: Mode,
+    /// Ventana IS de longitud fija o anclada al inicio
+    #[arg(long, value_enum, default_value_t = WfWindow::Rolling)]
+    wf_window: WfWindow,
+    /// Longitud de la ventana in-sample (días)
+    #[arg(long, default_value_t = WF_IS_DAYS)]
+    wf_is_days: f64,
+    /// Longitud de la ventana out-of-sample (días)
+    #[arg(long, default_value_t = WF_OOS_DAYS)]
+    wf_oos_days: f64,
+    /// Balance inicial (USDT)
+    #[arg(long, default_value_t = INITIAL_BALANCE)]
+    balance: f64,
+    /// Riesgo por trade, % del balance
+    #[arg(long, default_value_t = MAX_RISK_PCT * 100.0)]
+    risk_pct: f64,
+    /// Pérdida diaria máxima, % del balance
+    #[arg(long, default_value_t = MAX_DAILY_LOSS_PCT * 100.0)]
+    daily_loss_pct: f64,
+    /// Pérdida total máxima, % del balance inicial
+    #[arg(long, default_value_t = MAX_TOTAL_LOSS_PCT * 100.0)]
+    max_loss_pct: f64,
+    /// Comisión taker (entradas a mercado, SL, time stop), % del nocional
+    #[arg(long, default_value_t = TAKER_FEE * 100.0)]
+    taker_fee: f64,
+    /// Comisión maker (TP límite reduce-only), % del nocional
+    #[arg(long, default_value_t = MAKER_FEE * 100.0)]
+    maker_fee: f64,
+    /// Slippage por fill taker: none, ticks:N o atr:K (fracción del ATR)
+    #[arg(long, default_value_t = SLIPPAGE, value_parser = parse_slippage)]
+    slippage: Slippage,
+    /// Trades mínimos para que una configuración sea elegible
+    #[arg(long, default_value_t = MIN_TRADES)]
+    min_trades: usize,
+    /// Formato de las velas: auto = {SYMBOL}_{tf}.bin si existe (bin convert), si no el CSV
+    #[arg(long, value_enum, default_value_t = Store::Auto)]
+    store: Store,
+    /// Correr aunque los datos tengan errores de validación (duplicados, OHLC incoherente…)
+    #[arg(long)]
+    allow_dirty_data: bool,
+
+    // Grid (method "grid" o sin fichero de búsqueda)
+    /// Grid: tamaño mínimo del gap (fracción del precio)
+    #[arg(long, value_delimiter = ',', default_values_t = GRID_GAP.to_vec())]
+    grid_gap: Vec<f64>,
+    /// Grid: multiplicador de volumen
+    #[arg(long, value_delimiter = ',', default_values_t = GRID_VOL.to_vec())]
+    grid_vol: Vec<f64>,
+    /// Grid: lookback de FVG (velas)
+    #[arg(long, value_delimiter = ',', default_values_t = GRID_LOOKBACK.to_vec())]
+    grid_lookback: Vec<usize>,
+    /// Grid: stop loss (× ATR)
+    #[arg(long, value_delimiter = ',', default_values_t = GRID_SL_ATR.to_vec())]
+    grid_sl_atr: Vec<f64>,
+    /// Grid: take profit (× riesgo)
+    #[arg(long, value_delimiter = ',', default_values_t = GRID_TP.to_vec())]
+    grid_tp: Vec<f64>,
+    /// Grid: time stop (velas)
+    #[arg(long, value_delimiter = ',', default_values_t = GRID_TSTOP.to_vec())]
+    grid_tstop: Vec<usize>,
+
+    // Selección
+    /// Objetivos del frente de Pareto [por defecto: net_pnl,max_drawdown,trades,sharpe]
+    #[arg(long, value_delimiter = ',', value_parser = parse_objective)]
+    objectives: Vec<Objective>,
+    /// Elige por score ponderado: objetivo=peso,…
+    #[arg(long, value_delimiter = ',', value_parser = parse_weight, conflicts_with = "maximize")]
+    weights: Vec<(Objective, f64)>,
+    /// Elige el máximo de un objetivo
+    #[arg(long, value_parser = parse_objective)]
+    maximize: Option<Objective>,
+    /// Restricción, p. ej. "max_drawdown<8" (repetible)
+    #[arg(long, value_parser = parse_constraint)]
+    constraint: Vec<Constraint>,
+}
+
+/// Configuración de la ejecución, derivada de Cli (fracciones, no %).
+struct Settings {
+    initial_balance:    f64,
+    max_risk_pct:       f64,
+    max_daily_loss_pct: f64,
+    equity_floor_pct:   f64,
+    min_trades:         usize,
+    taker_fee:          f64,
+    maker_fee:          f64,
+    slippage:           Slippage,
+    timeframe:          String,
+    bar_ms:             i64,
+    mode:               Mode,
+    wf_window:          WfWindow,
+    wf_is_bars:         usize,
+    wf_oos_bars:        usize,
+}
+
+impl Settings {
+    fn from_cli(cli: &Cli) -> Self {
+        let bar_ms = timeframe_ms(&cli.timeframe).unwrap();
+        let bars = |days: f64| ((days * 86_400_000.0 / bar_ms as f64).round() as usize).max(1);
+        Settings {
+            initial_balance:    cli.balance,
+            max_risk_pct:       cli.risk_pct / 100.0,
+            max_daily_loss_pct: cli.daily_loss_pct / 100.0,
+            equity_floor_pct:   1.0 - cli.max_loss_pct / 100.0,
+            min_trades:         cli.min_trades,
+            taker_fee:          cli.taker_fee / 100.0,
+            maker_fee:          cli.maker_fee / 100.0,
+            slippage:           cli.slippage,
+            timeframe:          cli.timeframe.clone(),
+            bar_ms,
+            mode:               cli.mode,
+            wf_window:          cli.wf_window,
+            wf_is_bars:         bars(cli.wf_is_days),
+            wf_oos_bars:        bars(cli.wf_oos_days),
+        }
+    }
+}
+
 // ── Grid de búsqueda ──────────────────────────────────────────────────────────
 const GRID_GAP:      &[f64]   = &[0.001, 0.002, 0.003, 0.005, 0.008];
 const GRID_VOL:      &[f64]   = &[1.0, 1.2, 1.5, 2.0];
@@ -22,11 +214,127 @@ const GRID_SL_ATR:   &[f64]   = &[0.5, 1.0, 1.5, 2.0];
 const GRID_TP:       &[f64]   = &[1.5, 2.0, 2.5, 3.0, 4.0, 5.0];
 const GRID_TSTOP:    &[usize] = &[5, 7, 10, 14, 20, 28, 35];
 
-// ── Datos ─────────────────────────────────────────────────────────────────────
-#[derive(Clone)]
-struct Candle { ts_ms: i64, open: f64, high: f64, low: f64, close: f64, volume: f64 }
+// ── Estrategia de búsqueda ────────────────────────────────────────────────────
+// Se lee de --search (por defecto optimize_search.json en la raíz del repo); si
+// no existe, grid con --grid-* (por defecto GRID_*).
+//
+//   {
+//     "method": "halving",         // "grid" | "random" | "halving"
+//     "trials": 2000,              // configuraciones muestreadas (random / halving)
+//     "seed":   42,
+//     "eta":    3,                 // halving: se queda 1/eta en cada ronda
+//     "min_fraction": 0.25,        // halving: fracción del histórico en la 1ª ronda
+//     "params": {
+//       "min_gap": { "min": 0.0005, "max": 0.01, "log": true },
+//       ...
+//     }
+//   }
+//
+// Dimensiones: min_gap, min_vol_mult, lookback, sl_atr_mult, tp_mult, time_stop
+// (obligatorias en random / halving) y bb_period, bias_threshold, bos_window
+// (opcionales: si faltan, ese filtro no se aplica). Las enteras se redondean.
+const SEARCH_FILE: &str = "optimize_search.json";
 
-#[derive(Clone)]
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Method { Grid, Random, Halving }
+
+#[derive(Deserialize, Clone, Copy)]
+struct Range {
+    min: f64,
+    max: f64,
+    #[serde(default)]
+    log: bool, // muestreo log-uniforme
+}
+
+#[derive(Deserialize)]
+struct SearchSpace {
+    min_gap:        Range,
+    min_vol_mult:   Range,
+    lookback:       Range,
+    sl_atr_mult:    Range,
+    tp_mult:        Range,
+    time_stop:      Range,
+    bb_period:      Option<Range>,
+    bias_threshold: Option<Range>,
+    bos_window:     Option<Range>,
+}
+
+#[derive(Deserialize)]
+struct Search {
+    method: Method,
+    #[serde(default)]
+    trials: usize,
+    #[serde(default)]
+    seed:   u64,
+    #[serde(default = "default_eta")]
+    eta:    usize,
+    #[serde(default = "default_min_fraction")]
+    min_fraction: f64,
+    params: Option<SearchSpace>,
+    #[serde(skip)]
+    select: Selection, // desde la línea de comandos
+    #[serde(skip)]
+    grid:   Grid,      // ídem (method "grid")
+}
+
+/// Valores de cada dimensión para method "grid" (producto cartesiano).
+struct Grid {
+    gap:      Vec<f64>,
+    vol:      Vec<f64>,
+    lookback: Vec<usize>,
+    sl_atr:   Vec<f64>,
+    tp:       Vec<f64>,
+    tstop:    Vec<usize>,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid { gap: GRID_GAP.to_vec(), vol: GRID_VOL.to_vec(), lookback: GRID_LOOKBACK.to_vec(),
+               sl_atr: GRID_SL_ATR.to_vec(), tp: GRID_TP.to_vec(), tstop: GRID_TSTOP.to_vec() }
+    }
+}
+
+fn default_eta() -> usize { 3 }
+fn default_min_fraction() -> f64 { 0.25 }
+
+fn load_search(path: &Path) -> Search {
+    let grid = Search { method: Method::Grid, trials: 0, seed: 0, eta: default_eta(),
+                        min_fraction: default_min_fraction(), params: None,
+                        select: Selection::default(), grid: Grid::default() };
+    let Ok(text) = std::fs::read_to_string(path) else { return grid };
+    let fail = |msg: String| -> ! {
+        eprintln!("  ✖  {:?}: {}", path, msg);
+        std::process::exit(1);
+    };
+    let search: Search = serde_json::from_str(&text).unwrap_or_else(|e| fail(e.to_string()));
+    if search.method != Method::Grid {
+        if search.params.is_none() {
+            fail(format!("\"params\" es obligatorio con method {:?}", search.method));
+        }
+        if search.trials == 0 { fail("\"trials\" debe ser > 0".to_string()); }
+        if search.eta < 2 { fail("\"eta\" debe ser ≥ 2".to_string()); }
+    }
+    search
+}
+
+impl Range {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        let u = rng.next_f64();
+        if self.log && self.min > 0.0 {
+            (self.min.ln() + u * (self.max.ln() - self.min.ln())).exp()
+        } else {
+            self.min + u * (self.max - self.min)
+        }
+    }
+
+    fn sample_int(&self, rng: &mut Rng) -> usize {
+        self.sample(rng).round().max(0.0) as usize
+    }
+}
+
+// ── Datos ─────────────────────────────────────────────────────────────────────
+#[derive(Clone, Serialize)]
 struct Params {
     min_gap:      f64,
     min_vol_mult: f64,
@@ -34,6 +342,21 @@ struct Params {
     sl_atr_mult:  f64,
     tp_mult:      f64,
     time_stop:    usize,
+    // Filtros de confirmación (como en el bot en vivo); 0 / None = sin filtro
+    bb_period:      usize,       // cierre por encima / debajo de la media BB
+    bias_threshold: Option<f64>, // cierre vs SMA(20) × (1 ± umbral)
+    bos_window:     usize,       // cierre rompe el máximo / mínimo de N velas
+}
+
+impl Params {
+    /// Resumen de los filtros activos, para las tablas.
+    fn filters(&self) -> String {
+        let mut out: Vec<String> = Vec::new();
+        if self.bb_period > 0 { out.push(format!("BB{}", self.bb_period)); }
+        if let Some(t) = self.bias_threshold { out.push(format!("bias{:.2}%", t * 100.0)); }
+        if self.bos_window > 0 { out.push(format!("BOS{}", self.bos_window)); }
+        if out.is_empty() { "-".to_string() } else { out.join(" ") }
+    }
 }
 
 #[derive(Clone)]
@@ -42,32 +365,13 @@ struct Result {
     trades:        usize,
     win_rate:      f64,
     profit_factor: f64,
-    total_pnl:     f64,
+    total_pnl:     f64, // neto de costes
+    gross_pnl:     f64,
     max_drawdown:  f64,
+    sharpe:        f64, // anualizado, retornos por trade
     score:         f64,
 }
 
-// ── CSV loader ────────────────────────────────────────────────────────────────
-fn load_csv(path: &Path) -> Vec<Candle> {
-    let mut out = Vec::with_capacity(9000);
-    for (i, line) in BufReader::new(File::open(path).unwrap()).lines().enumerate() {
-        let line = line.unwrap();
-        if i == 0 { continue; }
-        let f: Vec<&str> = line.split(',').collect();
-        if f.len() < 7 { continue; }
-        out.push(Candle {
-            ts_ms:  f[0].parse().unwrap_or(0),
-            open:   f[2].parse().unwrap_or(0.0),
-            high:   f[3].parse().unwrap_or(0.0),
-            low:    f[4].parse().unwrap_or(0.0),
-            close:  f[5].parse().unwrap_or(0.0),
-            volume: f[6].parse().unwrap_or(0.0),
-        });
-    }
-    out.sort_by_key(|c| c.ts_ms);
-    out
-}
-
 // ── Indicadores ───────────────────────────────────────────────────────────────
 #[inline]
 fn calc_atr(candles: &[Candle], period: usize) -> f64 {
@@ -85,11 +389,26 @@ fn calc_avg_vol(candles: &[Candle], period: usize) -> f64 {
     candles.iter().rev().take(n).map(|c| c.volume).sum::<f64>() / n as f64
 }
 
+/// ATR y volumen medio de cada vela, calculados una vez por símbolo.
+/// `atr[i]` / `avg_vol[i]` solo usan velas `..=i`, así que valen igual para
+/// cualquier prefijo `&candles[..end]` (ventanas walk-forward).
+struct Indicators {
+    atr:     Vec<f64>,
+    avg_vol: Vec<f64>,
+}
+
+fn indicators(candles: &[Candle]) -> Indicators {
+    Indicators {
+        atr:     (0..candles.len()).map(|i| calc_atr(&candles[..=i], ATR_PERIOD)).collect(),
+        avg_vol: (0..candles.len()).map(|i| calc_avg_vol(&candles[..=i], VOL_AVG_PERIOD)).collect(),
+    }
+}
+
 // ── Detección FVG con parámetros ──────────────────────────────────────────────
-fn find_signal(candles: &[Candle], i: usize, p: &Params) -> Option<(bool, f64, f64)> {
+fn find_signal(candles: &[Candle], ind: &Indicators, i: usize, p: &Params) -> Option<(bool, f64, f64)> {
     // returns (is_long, zone_low, zone_high)
     let current = &candles[i];
-    let avg_vol = calc_avg_vol(&candles[..=i], VOL_AVG_PERIOD);
+    let avg_vol = ind.avg_vol[i];
 
     let search_start = i.saturating_sub(p.lookback + 2);
 
@@ -97,7 +416,7 @@ fn find_signal(candles: &[Candle], i: usize, p: &Params) -> Option<(bool, f64, f
         let c1 = &candles[j];
         let c2 = &candles[j + 1];
         let c3 = &candles[j + 2];
-        let imp_vol = calc_avg_vol(&candles[..=j + 1], VOL_AVG_PERIOD);
+        let imp_vol = ind.avg_vol[j + 1];
 
         // ── Bullish FVG ───────────────────────────────────────────────────────
         if c3.low > c1.high {
@@ -110,7 +429,8 @@ fn find_signal(candles: &[Candle], i: usize, p: &Params) -> Option<(bool, f64, f
                 let retested = candles[j+3..=i].iter()
                     .any(|c| c.low <= zh + (zh - zl) * 0.5);
                 if retested && current.close > zh
-                    && current.volume > avg_vol * p.min_vol_mult {
+                    && current.volume > avg_vol * p.min_vol_mult
+                    && confirmed(candles, i, true, p.bb_period, p.bias_threshold, p.bos_window) {
                     return Some((true, zl, zh));
                 }
             }
@@ -127,7 +447,8 @@ fn find_signal(candles: &[Candle], i: usize, p: &Params) -> Option<(bool, f64, f
                 let retested = candles[j+3..=i].iter()
                     .any(|c| c.high >= zl - (zh - zl) * 0.5);
                 if retested && current.close < zl
-                    && current.volume > avg_vol * p.min_vol_mult {
+                    && current.volume > avg_vol * p.min_vol_mult
+                    && confirmed(candles, i, false, p.bb_period, p.bias_threshold, p.bos_window) {
                     return Some((false, zl, zh));
                 }
             }
@@ -137,80 +458,109 @@ fn find_signal(candles: &[Candle], i: usize, p: &Params) -> Option<(bool, f64, f
 }
 
 // ── Backtest parametrizado ────────────────────────────────────────────────────
-fn run_backtest(candles: &[Candle], p: &Params) -> (usize, f64, f64, f64, f64) {
-    // returns (trades, win_rate, profit_factor, total_pnl, max_drawdown)
-    let mut balance = INITIAL_BALANCE;
-    let mut open: Option<(bool, f64, f64, f64, f64, usize)> = None;
-    // (is_long, entry, sl, tp1, qty, entry_idx)
+// Simula desde la vela `from` hasta el final de `candles`; las velas anteriores
+// solo sirven de histórico para ATR / volumen / FVG. Si `trade_log` es Some,
+// registra (exit_ts, pnl neto) de cada trade cerrado.
+fn run_backtest(
+    candles: &[Candle], ind: &Indicators, from: usize, p: &Params, costs: &Costs, cfg: &Settings,
+    mut trade_log: Option<&mut Vec<(i64, f64)>>,
+) -> (usize, f64, f64, f64, f64, f64, f64) {
+    // returns (trades, win_rate, profit_factor, net_pnl, gross_pnl, max_drawdown, sharpe)
+    let mut balance = cfg.initial_balance;
+    let mut gross_total = 0.0f64;
+    let mut open: Option<(bool, f64, f64, f64, f64, f64, usize)> = None;
+    // (is_long, entry, fill, sl, tp1, qty, entry_idx)
 
     let mut wins = 0usize; let mut losses = 0usize;
     let mut gross_win = 0.0f64; let mut gross_loss = 0.0f64;
     let mut current_day = -1i64; let mut daily_pnl = 0.0f64;
     let mut trading_on = true;
-    let mut peak = INITIAL_BALANCE; let mut max_dd = 0.0f64;
+    let mut peak = cfg.initial_balance; let mut max_dd = 0.0f64;
+    let mut ret_sum = 0.0f64; let mut ret_sq = 0.0f64; // retorno por trade sobre el balance
 
     let min_i = ATR_PERIOD + VOL_AVG_PERIOD + 3;
 
-    for i in min_i..candles.len() {
+    for i in from.max(min_i)..candles.len() {
         let c = &candles[i];
         let day = c.ts_ms / 86_400_000;
         if day != current_day {
             current_day = day; daily_pnl = 0.0;
-            trading_on = balance >= INITIAL_BALANCE * EQUITY_FLOOR_PCT;
+            trading_on = balance >= cfg.initial_balance * cfg.equity_floor_pct;
         }
 
-        let atr = calc_atr(&candles[..=i], ATR_PERIOD);
+        let atr = ind.atr[i];
 
-        if let Some((is_long, entry, sl, tp1, qty, entry_idx)) = open {
+        if let Some((is_long, entry, fill, sl, tp1, qty, entry_idx)) = open {
             let sl_hit  = if is_long { c.low  <= sl  } else { c.high >= sl  };
             let tp_hit  = if is_long { c.high >= tp1 } else { c.low  <= tp1 };
             let time_ok = (i - entry_idx) >= p.time_stop;
 
             let close_p = if sl_hit { sl } else if tp_hit { tp1 } else if time_ok { c.close } else { continue; };
             let mult = if is_long { 1.0 } else { -1.0 };
-            let pnl  = (close_p - entry) * qty * mult;
+            // TP límite: maker sin slippage; SL / time stop a mercado: taker + slippage
+            let (exit_fill, exit_rate) = if !sl_hit && tp_hit {
+                (close_p, costs.maker_fee)
+            } else {
+                (close_p - costs.slippage(atr) * mult, costs.taker_fee)
+            };
+            let fees = fill * qty * costs.taker_fee + exit_fill * qty * exit_rate;
+            let funding = funding_rate_sum(&costs.funding, candles[entry_idx].ts_ms + cfg.bar_ms, c.ts_ms + cfg.bar_ms)
+                * entry * qty * mult;
+            gross_total += (close_p - entry) * qty * mult;
+            let pnl  = (exit_fill - fill) * qty * mult - fees - funding;
+            let ret  = pnl / balance;
+            ret_sum += ret; ret_sq += ret * ret;
             balance   += pnl; daily_pnl += pnl;
+            if let Some(log) = trade_log.as_deref_mut() { log.push((c.ts_ms, pnl)); }
             if pnl > 0.0 { wins += 1; gross_win  += pnl; }
             else         { losses += 1; gross_loss += pnl.abs(); }
             if balance > peak { peak = balance; }
             let dd = (peak - balance) / peak * 100.0;
             if dd > max_dd { max_dd = dd; }
             open = None;
-            if daily_pnl < -(balance.max(INITIAL_BALANCE) * MAX_DAILY_LOSS_PCT) { trading_on = false; }
+            if daily_pnl < -(balance.max(cfg.initial_balance) * cfg.max_daily_loss_pct) { trading_on = false; }
             continue;
         }
 
         if !trading_on || atr == 0.0 { continue; }
 
-        if let Some((is_long, _zl, _zh)) = find_signal(candles, i, p) {
+        if let Some((is_long, _zl, _zh)) = find_signal(candles, ind, i, p) {
             let entry = c.close;
             let sl = if is_long { _zl - atr * p.sl_atr_mult } else { _zh + atr * p.sl_atr_mult };
             let risk_unit = (entry - sl).abs();
             if risk_unit <= 0.0 || risk_unit > entry * 0.12 { continue; }
 
             let tp1 = if is_long { entry + risk_unit * p.tp_mult } else { entry - risk_unit * p.tp_mult };
-            let max_risk = balance * MAX_RISK_PCT;
-            let budget   = (balance * MAX_DAILY_LOSS_PCT + daily_pnl).max(0.0);
+            let max_risk = balance * cfg.max_risk_pct;
+            let budget   = (balance * cfg.max_daily_loss_pct + daily_pnl).max(0.0);
             let risk     = max_risk.min(budget);
             if risk <= 0.0 { continue; }
             let qty = (risk / risk_unit).floor();
             if qty <= 0.0 { continue; }
 
-            open = Some((is_long, entry, sl, tp1, qty, i));
+            let fill = entry + costs.slippage(atr) * if is_long { 1.0 } else { -1.0 };
+            open = Some((is_long, entry, fill, sl, tp1, qty, i));
         }
     }
 
     let n = wins + losses;
-    if n == 0 { return (0, 0.0, 0.0, 0.0, 0.0); }
+    if n == 0 { return (0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0); }
     let wr = wins as f64 / n as f64 * 100.0;
     let pf = if gross_loss == 0.0 { 99.0 } else { gross_win / gross_loss };
-    let total = balance - INITIAL_BALANCE;
-    (n, wr, pf, total, max_dd)
+    let total = balance - cfg.initial_balance;
+    // Sharpe anualizado de los retornos por trade (trades/año según el tramo simulado)
+    let start = from.max(min_i).min(candles.len() - 1);
+    let years = (candles[candles.len() - 1].ts_ms - candles[start].ts_ms) as f64 / (365.0 * 86_400_000.0);
+    let mean  = ret_sum / n as f64;
+    let std   = (ret_sq / n as f64 - mean * mean).max(0.0).sqrt();
+    let sharpe = if n < 2 || std == 0.0 || years <= 0.0 { 0.0 }
+                 else { mean / std * (n as f64 / years).sqrt() };
+    (n, wr, pf, total, gross_total, max_dd, sharpe)
 }
 
 // ── Función de puntuación ─────────────────────────────────────────────────────
-fn score(wr: f64, pf: f64, dd: f64, trades: usize) -> f64 {
-    if trades < MIN_TRADES || wr < 40.0 || pf < 0.5 { return 0.0; }
+fn score(wr: f64, pf: f64, dd: f64, trades: usize, min_trades: usize) -> f64 {
+    if trades < min_trades || wr < 40.0 || pf < 0.5 { return 0.0; }
     let wr_norm = (wr / 100.0).powf(2.0);        // premia win rate alto
     let pf_norm  = (pf / 3.0).min(1.0);          // premia profit factor
     let dd_pen   = 1.0 - (dd / 100.0).min(0.99); // penaliza drawdown
@@ -219,74 +569,585 @@ fn score(wr: f64, pf: f64, dd: f64, trades: usize) -> f64 {
 }
 
 // ── Optimización por símbolo ──────────────────────────────────────────────────
-fn optimize_symbol(symbol: &str, candles: &[Candle]) -> Vec<Result> {
-    let total = GRID_GAP.len() * GRID_VOL.len() * GRID_LOOKBACK.len()
-              * GRID_SL_ATR.len() * GRID_TP.len() * GRID_TSTOP.len();
-    let mut results: Vec<Result> = Vec::with_capacity(total / 5);
-    let mut done = 0usize;
-
-    for &gap in GRID_GAP {
-    for &vol in GRID_VOL {
-    for &lb in GRID_LOOKBACK {
-    for &sl_a in GRID_SL_ATR {
-    for &tp in GRID_TP {
-    for &ts in GRID_TSTOP {
-        let p = Params { min_gap: gap, min_vol_mult: vol, lookback: lb,
-                         sl_atr_mult: sl_a, tp_mult: tp, time_stop: ts };
-        let (n, wr, pf, pnl, dd) = run_backtest(candles, &p);
-        let sc = score(wr, pf, dd, n);
-        if sc > 0.0 {
-            results.push(Result { params: p, trades: n, win_rate: wr,
-                profit_factor: pf, total_pnl: pnl, max_drawdown: dd, score: sc });
-        }
-        done += 1;
-        if done % 500 == 0 {
-            eprint!("\r    {}/{} combinaciones ({:.0}%)   ", done, total,
-                    done as f64 / total as f64 * 100.0);
-        }
+fn grid(g: &Grid) -> Vec<Params> {
+    let mut grid: Vec<Params> = Vec::new();
+    for &gap in &g.gap {
+    for &vol in &g.vol {
+    for &lb in &g.lookback {
+    for &sl_a in &g.sl_atr {
+    for &tp in &g.tp {
+    for &ts in &g.tstop {
+        grid.push(Params { min_gap: gap, min_vol_mult: vol, lookback: lb,
+                           sl_atr_mult: sl_a, tp_mult: tp, time_stop: ts,
+                           bb_period: 0, bias_threshold: None, bos_window: 0 });
     }}}}}}
+    grid
+}
+
+fn sample_params(space: &SearchSpace, n: usize, seed: u64) -> Vec<Params> {
+    let mut rng = Rng(seed);
+    (0..n).map(|_| Params {
+        min_gap:        space.min_gap.sample(&mut rng),
+        min_vol_mult:   space.min_vol_mult.sample(&mut rng),
+        lookback:       space.lookback.sample_int(&mut rng).max(1),
+        sl_atr_mult:    space.sl_atr_mult.sample(&mut rng),
+        tp_mult:        space.tp_mult.sample(&mut rng),
+        time_stop:      space.time_stop.sample_int(&mut rng).max(1),
+        bb_period:      space.bb_period.as_ref().map_or(0, |r| r.sample_int(&mut rng)),
+        bias_threshold: space.bias_threshold.as_ref().map(|r| r.sample(&mut rng)),
+        bos_window:     space.bos_window.as_ref().map_or(0, |r| r.sample_int(&mut rng)),
+    }).collect()
+}
+
+/// Evalúa `candidates` en paralelo sobre las velas `from..` de `candles`.
+/// Devuelve un Result por candidato, en el mismo orden (score 0 incluido).
+fn evaluate(
+    candles: &[Candle], ind: &Indicators, from: usize, costs: &Costs, cfg: &Settings, candidates: Vec<Params>,
+) -> Vec<Result> {
+    let total = candidates.len();
+    let done  = AtomicUsize::new(0);
+
+    let results: Vec<Result> = candidates.into_par_iter().map(|p| {
+        let (n, wr, pf, pnl, gross, dd, sharpe) = run_backtest(candles, ind, from, &p, costs, cfg, None);
+        let d = done.fetch_add(1, Ordering::Relaxed) + 1;
+        if d.is_multiple_of(500) {
+            eprint!("\r    {}/{} combinaciones ({:.0}%)   ", d, total,
+                    d as f64 / total as f64 * 100.0);
+        }
+        Result { params: p, trades: n, win_rate: wr, profit_factor: pf,
+                 total_pnl: pnl, gross_pnl: gross, max_drawdown: dd, sharpe, score: score(wr, pf, dd, n, cfg.min_trades) }
+    }).collect();
     eprintln!("\r    {} combinaciones probadas               ", total);
-    results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
     results
 }
 
+/// Successive halving: todos los candidatos corren sobre los primeros
+/// `min_fraction` del histórico; pasa 1/eta (según la política de selección)
+/// a la ronda siguiente con eta× más histórico, hasta completar `candles`.
+fn successive_halving(
+    candles: &[Candle], ind: &Indicators, from: usize, costs: &Costs, cfg: &Settings,
+    mut candidates: Vec<Params>, search: &Search,
+) -> Vec<Result> {
+    let eta  = search.eta;
+    let span = candles.len().saturating_sub(from);
+    let mut fraction = search.min_fraction.clamp(0.0, 1.0);
+    loop {
+        let end = if fraction >= 1.0 { candles.len() } else { from + (span as f64 * fraction) as usize };
+        let results = evaluate(&candles[..end], ind, from, costs, cfg, candidates);
+        if end == candles.len() || results.len() <= 1 {
+            return results;
+        }
+        let results = search.select.rank(results);
+        let keep = results.len().div_ceil(eta);
+        eprintln!("    halving: {:.0}% del histórico → siguen {} de {}", fraction * 100.0, keep, results.len());
+        candidates = results.into_iter().take(keep).map(|r| r.params).collect();
+        fraction = (fraction * eta as f64).min(1.0);
+    }
+}
+
+/// Búsqueda sobre las velas `from..` de `candles` (ver run_backtest) con el
+/// método de `search`: todas las configuraciones evaluadas, en orden de
+/// candidato. No depende del número de hilos.
+fn run_search(
+    candles: &[Candle], ind: &Indicators, from: usize, costs: &Costs, cfg: &Settings, search: &Search,
+) -> Vec<Result> {
+    match (search.method, &search.params) {
+        (Method::Random, Some(space)) => {
+            evaluate(candles, ind, from, costs, cfg, sample_params(space, search.trials, search.seed))
+        }
+        (Method::Halving, Some(space)) => {
+            successive_halving(candles, ind, from, costs, cfg, sample_params(space, search.trials, search.seed), search)
+        }
+        _ => evaluate(candles, ind, from, costs, cfg, grid(&search.grid)),
+    }
+}
+
+/// run_search + selección: configuraciones válidas, de la mejor a la peor.
+fn optimize_symbol(
+    candles: &[Candle], ind: &Indicators, from: usize, costs: &Costs, cfg: &Settings, search: &Search,
+) -> Vec<Result> {
+    search.select.select(run_search(candles, ind, from, costs, cfg, search))
+}
+
+// ── Objetivos, frente de Pareto y selección ───────────────────────────────────
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Objective { NetPnl, MaxDrawdown, Trades, Sharpe, ProfitFactor, WinRate }
+
+const OBJECTIVES: &[(&str, Objective)] = &[
+    ("net_pnl",       Objective::NetPnl),
+    ("max_drawdown",  Objective::MaxDrawdown),
+    ("trades",        Objective::Trades),
+    ("sharpe",        Objective::Sharpe),
+    ("profit_factor", Objective::ProfitFactor),
+    ("win_rate",      Objective::WinRate),
+];
+
+impl Objective {
+    fn parse(name: &str) -> Option<Self> {
+        OBJECTIVES.iter().find(|(n, _)| *n == name).map(|(_, o)| *o)
+    }
+
+    fn name(self) -> &'static str {
+        OBJECTIVES.iter().find(|(_, o)| *o == self).map(|(n, _)| *n).unwrap()
+    }
+
+    fn value(self, r: &Result) -> f64 {
+        match self {
+            Objective::NetPnl       => r.total_pnl,
+            Objective::MaxDrawdown  => r.max_drawdown,
+            Objective::Trades       => r.trades as f64,
+            Objective::Sharpe       => r.sharpe,
+            Objective::ProfitFactor => r.profit_factor,
+            Objective::WinRate      => r.win_rate,
+        }
+    }
+
+    /// Valor orientado: mayor siempre es mejor.
+    fn utility(self, r: &Result) -> f64 {
+        if self == Objective::MaxDrawdown { -self.value(r) } else { self.value(r) }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Cmp { Lt, Le, Gt, Ge }
+
+#[derive(Clone, Copy, Debug)]
+struct Constraint { objective: Objective, cmp: Cmp, limit: f64 }
+
+impl Constraint {
+    /// "max_drawdown<8", "trades>=30", …
+    fn parse(text: &str) -> Option<Self> {
+        let (pos, cmp, len) = [("<=", Cmp::Le), (">=", Cmp::Ge), ("<", Cmp::Lt), (">", Cmp::Gt)]
+            .iter()
+            .find_map(|(op, cmp)| text.find(op).map(|pos| (pos, *cmp, op.len())))?;
+        Some(Constraint {
+            objective: Objective::parse(text[..pos].trim())?,
+            cmp,
+            limit: text[pos + len..].trim().parse().ok()?,
+        })
+    }
+
+    fn holds(&self, r: &Result) -> bool {
+        let v = self.objective.value(r);
+        match self.cmp {
+            Cmp::Lt => v <  self.limit,
+            Cmp::Le => v <= self.limit,
+            Cmp::Gt => v >  self.limit,
+            Cmp::Ge => v >= self.limit,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Policy {
+    Score,                          // función score() clásica
+    Weighted(Vec<(Objective, f64)>), // Σ peso × objetivo normalizado a [0, 1]
+    Maximize(Objective),
+}
+
+#[derive(Clone, Debug)]
+struct Selection {
+    objectives:  Vec<Objective>, // dimensiones del frente de Pareto
+    constraints: Vec<Constraint>,
+    policy:      Policy,
+    min_trades:  usize,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Selection {
+            objectives:  vec![Objective::NetPnl, Objective::MaxDrawdown, Objective::Trades, Objective::Sharpe],
+            constraints: Vec::new(),
+            policy:      Policy::Score,
+            min_trades:  MIN_TRADES,
+        }
+    }
+}
+
+impl Selection {
+    /// Configuración elegible: mínimo de trades (o score > 0 con la política
+    /// clásica) y todas las restricciones.
+    fn feasible(&self, r: &Result) -> bool {
+        let base = match self.policy {
+            Policy::Score => r.score > 0.0,
+            _             => r.trades >= self.min_trades,
+        };
+        base && self.constraints.iter().all(|c| c.holds(r))
+    }
+
+    /// Ordena de mejor a peor (las no elegibles al final). Empates por PnL
+    /// neto y después por orden original (sort estable).
+    fn rank(&self, results: Vec<Result>) -> Vec<Result> {
+        let ranges: Vec<(f64, f64)> = match &self.policy {
+            Policy::Weighted(w) => w.iter().map(|(o, _)| {
+                results.iter().filter(|r| self.feasible(r)).map(|r| o.utility(r))
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)))
+            }).collect(),
+            _ => Vec::new(),
+        };
+        let key = |r: &Result| -> f64 {
+            match &self.policy {
+                Policy::Score       => r.score,
+                Policy::Maximize(o) => o.utility(r),
+                Policy::Weighted(w) => w.iter().zip(&ranges).map(|((o, weight), (lo, hi))| {
+                    let span = hi - lo;
+                    weight * if span > 0.0 { (o.utility(r) - lo) / span } else { 1.0 }
+                }).sum(),
+            }
+        };
+        let mut keyed: Vec<(bool, f64, Result)> = results.into_iter()
+            .map(|r| (self.feasible(&r), key(&r), r))
+            .collect();
+        keyed.sort_by(|a, b| b.0.cmp(&a.0)
+            .then(b.1.total_cmp(&a.1))
+            .then(b.2.total_pnl.total_cmp(&a.2.total_pnl)));
+        keyed.into_iter().map(|(_, _, r)| r).collect()
+    }
+
+    /// Solo las configuraciones elegibles, de mejor a peor.
+    fn select(&self, results: Vec<Result>) -> Vec<Result> {
+        let mut ranked = self.rank(results);
+        ranked.retain(|r| self.feasible(r));
+        ranked
+    }
+
+    fn describe(&self) -> String {
+        let policy = match &self.policy {
+            Policy::Score       => "score".to_string(),
+            Policy::Maximize(o) => format!("max {}", o.name()),
+            Policy::Weighted(w) => w.iter().map(|(o, k)| format!("{}×{}", k, o.name()))
+                                    .collect::<Vec<_>>().join(" + "),
+        };
+        let cons: Vec<String> = self.constraints.iter().map(|c| {
+            let op = match c.cmp { Cmp::Lt => "<", Cmp::Le => "<=", Cmp::Gt => ">", Cmp::Ge => ">=" };
+            format!("{}{}{}", c.objective.name(), op, c.limit)
+        }).collect();
+        if cons.is_empty() { policy } else { format!("{} | {}", cons.join(", "), policy) }
+    }
+}
+
+fn parse_objective(name: &str) -> std::result::Result<Objective, String> {
+    Objective::parse(name.trim()).ok_or_else(|| format!("objetivo desconocido: {} (objetivos: {})", name,
+        OBJECTIVES.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(", ")))
+}
+
+fn parse_weight(kv: &str) -> std::result::Result<(Objective, f64), String> {
+    let (k, w) = kv.split_once('=').ok_or_else(|| format!("peso inválido: {}", kv))?;
+    let w: f64 = w.trim().parse().map_err(|_| format!("peso inválido: {}", kv))?;
+    Ok((parse_objective(k)?, w))
+}
+
+fn parse_constraint(text: &str) -> std::result::Result<Constraint, String> {
+    Constraint::parse(text).ok_or_else(|| format!("restricción inválida: {}", text))
+}
+
+/// Configuraciones con al menos `min_trades` no dominadas en `objectives`
+/// (ninguna otra es igual o mejor en todos y estrictamente mejor en alguno).
+fn pareto_front<'a>(results: &'a [Result], objectives: &[Objective], min_trades: usize) -> Vec<&'a Result> {
+    let valid: Vec<&Result> = results.iter().filter(|r| r.trades >= min_trades).collect();
+    let dominates = |a: &Result, b: &Result| {
+        objectives.iter().all(|o| o.utility(a) >= o.utility(b))
+            && objectives.iter().any(|o| o.utility(a) > o.utility(b))
+    };
+    let mut front: Vec<&Result> = valid.par_iter()
+        .filter(|r| !valid.iter().any(|other| dominates(other, r)))
+        .copied()
+        .collect();
+    if let Some(first) = objectives.first() {
+        front.sort_by(|a, b| first.utility(b).total_cmp(&first.utility(a)));
+    }
+    front
+}
+
+fn same_params(a: &Params, b: &Params) -> bool {
+    a.min_gap == b.min_gap && a.min_vol_mult == b.min_vol_mult && a.lookback == b.lookback
+        && a.sl_atr_mult == b.sl_atr_mult && a.tp_mult == b.tp_mult && a.time_stop == b.time_stop
+        && a.bb_period == b.bb_period && a.bias_threshold == b.bias_threshold && a.bos_window == b.bos_window
+}
+
+#[derive(Serialize)]
+struct ParetoRow<'a> {
+    symbol:        &'a str,
+    params:        &'a Params,
+    trades:        usize,
+    win_rate:      f64,
+    profit_factor: f64,
+    net_pnl:       f64,
+    gross_pnl:     f64,
+    max_drawdown:  f64,
+    sharpe:        f64,
+    score:         f64,
+    selected:      bool, // elegida por la política de selección
+}
+
+fn save_pareto(rows: &[ParetoRow], csv_path: &Path, json_path: &Path) {
+    let mut f = File::create(csv_path).unwrap();
+    writeln!(f, "symbol,min_gap,min_vol_mult,lookback,sl_atr_mult,tp_mult,time_stop,\
+                 bb_period,bias_threshold,bos_window,\
+                 trades,win_rate,profit_factor,net_pnl,gross_pnl,max_drawdown,sharpe,score,selected").unwrap();
+    for r in rows {
+        let p = r.params;
+        writeln!(f, "{},{},{},{},{},{},{},{},{},{},{},{:.1},{:.3},{:.2},{:.2},{:.1},{:.3},{:.2},{}",
+            r.symbol, p.min_gap, p.min_vol_mult, p.lookback, p.sl_atr_mult, p.tp_mult, p.time_stop,
+            p.bb_period, p.bias_threshold.map_or(String::new(), |t| t.to_string()), p.bos_window,
+            r.trades, r.win_rate, r.profit_factor, r.net_pnl, r.gross_pnl,
+            r.max_drawdown, r.sharpe, r.score, r.selected).unwrap();
+    }
+    let json = serde_json::to_string_pretty(rows).unwrap();
+    std::fs::write(json_path, json).unwrap();
+}
+
+// ── Walk-forward ──────────────────────────────────────────────────────────────
+type OosCurve = Vec<(i64, f64)>; // (exit_ts, equity) de las OOS encadenadas
+
+struct WfRun {
+    is_from:  usize, // IS = [is_from, is_to)
+    is_to:    usize, // OOS = [is_to, oos_to)
+    oos_to:   usize,
+    best:     Option<Result>, // None: ninguna combinación puntúa en la IS
+    oos_trades: usize, oos_win_rate: f64, oos_pf: f64, oos_pnl: f64, oos_dd: f64,
+    wfe:      f64,
+}
+
+/// Walk-forward efficiency: PnL por vela OOS / PnL por vela IS.
+fn wf_efficiency(oos_pnl: f64, oos_bars: usize, is_pnl: f64, is_bars: usize) -> f64 {
+    if is_pnl <= 0.0 || oos_bars == 0 || is_bars == 0 { return 0.0; }
+    (oos_pnl / oos_bars as f64) / (is_pnl / is_bars as f64)
+}
+
+/// Ejecuta todas las ventanas IS/OOS completas de `candles`. Cada ventana
+/// arranca con el balance inicial; la curva OOS (exit_ts, equity) suma el PnL de
+/// las ventanas OOS encadenadas, sin compounding entre ventanas.
+fn walk_forward(
+    candles: &[Candle], ind: &Indicators, costs: &Costs, cfg: &Settings, search: &Search,
+) -> (Vec<WfRun>, OosCurve) {
+    let mut runs: Vec<WfRun> = Vec::new();
+    let mut curve: OosCurve = Vec::new();
+    let mut equity = cfg.initial_balance;
+
+    let mut is_to = cfg.wf_is_bars;
+    while is_to + cfg.wf_oos_bars <= candles.len() {
+        let oos_to  = is_to + cfg.wf_oos_bars;
+        let is_from = match cfg.wf_window {
+            WfWindow::Rolling  => is_to - cfg.wf_is_bars,
+            WfWindow::Anchored => 0,
+        };
+        eprintln!("    ventana {}: IS {} → {}  |  OOS → {}", runs.len() + 1,
+                  ms_to_date(candles[is_from].ts_ms), ms_to_date(candles[is_to].ts_ms),
+                  ms_to_date(candles[oos_to - 1].ts_ms));
+
+        let best = optimize_symbol(&candles[..is_to], ind, is_from, costs, cfg, search).into_iter().next();
+        let mut run = WfRun { is_from, is_to, oos_to, best: None,
+            oos_trades: 0, oos_win_rate: 0.0, oos_pf: 0.0, oos_pnl: 0.0, oos_dd: 0.0, wfe: 0.0 };
+        if let Some(best) = best {
+            let mut log = Vec::new();
+            let (n, wr, pf, pnl, _, dd, _) = run_backtest(&candles[..oos_to], ind, is_to, &best.params, costs, cfg, Some(&mut log));
+            for (ts, p) in log {
+                equity += p;
+                curve.push((ts, equity));
+            }
+            run.oos_trades = n; run.oos_win_rate = wr; run.oos_pf = pf;
+            run.oos_pnl = pnl; run.oos_dd = dd;
+            run.wfe = wf_efficiency(pnl, oos_to - is_to, best.total_pnl, is_to - is_from);
+            run.best = Some(best);
+        }
+        runs.push(run);
+        is_to = oos_to;
+    }
+    (runs, curve)
+}
+
+/// (media, desviación típica, valores distintos)
+fn stability(vals: &[f64]) -> (f64, f64, usize) {
+    if vals.is_empty() { return (0.0, 0.0, 0); }
+    let n = vals.len() as f64;
+    let mean = vals.iter().sum::<f64>() / n;
+    let std  = (vals.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+    let mut distinct = vals.to_vec();
+    distinct.sort_by(f64::total_cmp);
+    distinct.dedup();
+    (mean, std, distinct.len())
+}
+
+fn print_walk_forward(candles: &[Candle], runs: &[WfRun], cfg: &Settings) {
+    println!("    Walk-forward {:?}: IS {} velas, OOS {} velas, {} ventanas\n",
+             cfg.wf_window, cfg.wf_is_bars, cfg.wf_oos_bars, runs.len());
+    println!("    {:>3}  {:>16}  {:>4}  {:>5}  {:>5}  {:>5}  {:>5}  {:>4}  {:>8}  {:>5}  {:>5}  {:>8}  {:>6}",
+             "#","OOS desde","LB","Gap%","Vol×","TP×","SL×","Stop","IS neto","OOS n","WR%","OOS neto","WFE");
+    println!("    {}", "─".repeat(104));
+    for (k, r) in runs.iter().enumerate() {
+        let since = ms_to_date(candles[r.is_to].ts_ms);
+        match &r.best {
+            Some(b) => {
+                let p = &b.params;
+                println!("    {:>3}  {:>16}  {:>4}  {:>4.1}%  {:>5.1}  {:>5.1}  {:>5.1}  {:>4}  {:>+8.0}  {:>5}  {:>5.1}  {:>+8.0}  {:>6.2}  {}",
+                         k + 1, since, p.lookback, p.min_gap * 100.0, p.min_vol_mult, p.tp_mult,
+                         p.sl_atr_mult, p.time_stop, b.total_pnl, r.oos_trades, r.oos_win_rate,
+                         r.oos_pnl, r.wfe, p.filters());
+            }
+            None => println!("    {:>3}  {:>16}  sin parámetros válidos en IS", k + 1, since),
+        }
+    }
+
+    let valid: Vec<&WfRun> = runs.iter().filter(|r| r.best.is_some()).collect();
+    if valid.is_empty() { println!(); return; }
+
+    // WFE global: PnL/vela de todas las OOS frente a PnL/vela de todas las IS
+    let oos_pnl: f64    = valid.iter().map(|r| r.oos_pnl).sum();
+    let oos_bars: usize = valid.iter().map(|r| r.oos_to - r.is_to).sum();
+    let is_pnl: f64     = valid.iter().map(|r| r.best.as_ref().unwrap().total_pnl).sum();
+    let is_bars: usize  = valid.iter().map(|r| r.is_to - r.is_from).sum();
+    let oos_trades: usize = valid.iter().map(|r| r.oos_trades).sum();
+    let profitable = valid.iter().filter(|r| r.oos_pnl > 0.0).count();
+    println!();
+    println!("    OOS total: {} trades  PnL={:+.0} neto  |  {}/{} ventanas OOS rentables  |  WFE {:.2}",
+             oos_trades, oos_pnl, profitable, valid.len(),
+             wf_efficiency(oos_pnl, oos_bars, is_pnl, is_bars));
+
+    // Estabilidad de parámetros: coeficiente de variación entre ventanas
+    println!("    Estabilidad de parámetros ({} ventanas):", valid.len());
+    let params: Vec<&Params> = valid.iter().map(|r| &r.best.as_ref().unwrap().params).collect();
+    let series: [(&str, Vec<f64>); 9] = [
+        ("min_gap",      params.iter().map(|p| p.min_gap).collect()),
+        ("min_vol_mult", params.iter().map(|p| p.min_vol_mult).collect()),
+        ("lookback",     params.iter().map(|p| p.lookback as f64).collect()),
+        ("sl_atr_mult",  params.iter().map(|p| p.sl_atr_mult).collect()),
+        ("tp_mult",      params.iter().map(|p| p.tp_mult).collect()),
+        ("time_stop",    params.iter().map(|p| p.time_stop as f64).collect()),
+        ("bb_period",    params.iter().map(|p| p.bb_period as f64).collect()),
+        ("bias_thresh",  params.iter().map(|p| p.bias_threshold.unwrap_or(0.0)).collect()),
+        ("bos_window",   params.iter().map(|p| p.bos_window as f64).collect()),
+    ];
+    for (name, vals) in &series {
+        let (mean, std, distinct) = stability(vals);
+        let cv = if mean == 0.0 { 0.0 } else { std / mean * 100.0 };
+        println!("      {:<13} media {:>8.4}  σ {:>8.4}  CV {:>5.1}%  {} valores distintos",
+                 name, mean, std, cv, distinct);
+    }
+    println!();
+}
+
+fn save_walk_forward(all: &[(&str, Vec<WfRun>, OosCurve)], windows_path: &Path, equity_path: &Path) {
+    let mut f = File::create(windows_path).unwrap();
+    writeln!(f, "symbol,window,is_start,oos_start,oos_end,min_gap,min_vol_mult,lookback,sl_atr_mult,\
+                 tp_mult,time_stop,bb_period,bias_threshold,bos_window,is_trades,is_pnl,oos_trades,oos_win_rate,oos_profit_factor,\
+                 oos_pnl,oos_max_drawdown,wfe").unwrap();
+    for (sym, runs, _) in all {
+        for (k, r) in runs.iter().enumerate() {
+            let Some(b) = &r.best else { continue };
+            let p = &b.params;
+            writeln!(f, "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{:.2},{},{:.1},{:.3},{:.2},{:.1},{:.3}",
+                sym, k + 1, r.is_from, r.is_to, r.oos_to,
+                p.min_gap, p.min_vol_mult, p.lookback, p.sl_atr_mult, p.tp_mult, p.time_stop,
+                p.bb_period, p.bias_threshold.map_or(String::new(), |t| t.to_string()), p.bos_window,
+                b.trades, b.total_pnl, r.oos_trades, r.oos_win_rate, r.oos_pf,
+                r.oos_pnl, r.oos_dd, r.wfe).unwrap();
+        }
+    }
+
+    let mut f = File::create(equity_path).unwrap();
+    writeln!(f, "symbol,timestamp_ms,datetime_utc,equity").unwrap();
+    for (sym, _, curve) in all {
+        for (ts, eq) in curve {
+            writeln!(f, "{},{},{},{:.2}", sym, ts, ms_to_date(*ts), eq).unwrap();
+        }
+    }
+}
+
 // ── Output CSV con mejores parámetros ─────────────────────────────────────────
 fn save_best(results_per_sym: &[(&str, &Result)], path: &Path) {
     let mut f = File::create(path).unwrap();
     writeln!(f, "symbol,min_gap,min_vol_mult,lookback,sl_atr_mult,tp_mult,time_stop,\
-                 trades,win_rate,profit_factor,total_pnl,max_drawdown,score").unwrap();
+                 bb_period,bias_threshold,bos_window,\
+                 trades,win_rate,profit_factor,gross_pnl,total_pnl,max_drawdown,score").unwrap();
     for (sym, r) in results_per_sym {
         let p = &r.params;
-        writeln!(f, "{},{},{},{},{},{},{},{},{:.1},{:.3},{:.2},{:.1},{:.2}",
+        writeln!(f, "{},{},{},{},{},{},{},{},{},{},{},{:.1},{:.3},{:.2},{:.2},{:.1},{:.2}",
             sym, p.min_gap, p.min_vol_mult, p.lookback, p.sl_atr_mult,
-            p.tp_mult, p.time_stop, r.trades, r.win_rate, r.profit_factor,
-            r.total_pnl, r.max_drawdown, r.score).unwrap();
+            p.tp_mult, p.time_stop,
+            p.bb_period, p.bias_threshold.map_or(String::new(), |t| t.to_string()), p.bos_window,
+            r.trades, r.win_rate, r.profit_factor,
+            r.gross_pnl, r.total_pnl, r.max_drawdown, r.score).unwrap();
     }
 }
 
 // ── Main ──────────────────────────────────────────────────────────────────────
 fn main() {
-    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("data");
-    let total_combos = GRID_GAP.len() * GRID_VOL.len() * GRID_LOOKBACK.len()
-                     * GRID_SL_ATR.len() * GRID_TP.len() * GRID_TSTOP.len();
+    let cli = Cli::parse();
+    let cfg = Settings::from_cli(&cli);
+    let data_dir = cli.data_dir.clone().unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("data"));
+    let out_dir  = cli.out_dir.clone().unwrap_or_else(|| data_dir.clone());
+    std::fs::create_dir_all(&out_dir).expect("no se pudo crear el directorio de salida");
+    let search_file = cli.search.clone().unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join(SEARCH_FILE));
+    let mut search = load_search(&search_file);
+    search.select.min_trades  = cli.min_trades;
+    search.select.constraints = cli.constraint.clone();
+    if !cli.objectives.is_empty() { search.select.objectives = cli.objectives.clone(); }
+    if !cli.weights.is_empty()    { search.select.policy = Policy::Weighted(cli.weights.clone()); }
+    if let Some(o) = cli.maximize { search.select.policy = Policy::Maximize(o); }
+    search.grid = Grid { gap: cli.grid_gap.clone(), vol: cli.grid_vol.clone(), lookback: cli.grid_lookback.clone(),
+                         sl_atr: cli.grid_sl_atr.clone(), tp: cli.grid_tp.clone(), tstop: cli.grid_tstop.clone() };
+
+    // Rango de fechas [from 00:00, to 24:00) UTC
+    let from_ms = cli.from.map_or(i64::MIN, |d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis());
+    let to_ms   = cli.to.map_or(i64::MAX, |d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis() + 86_400_000);
+
+    let (title, trials) = match search.method {
+        Method::Grid    => ("Grid Search      ", grid(&search.grid).len()),
+        Method::Random  => ("Random Search    ", search.trials),
+        Method::Halving => ("Succ. Halving    ", search.trials),
+    };
 
     println!("\n╔══════════════════════════════════════════════════════════════╗");
-    println!("║        FVG OPTIMIZADOR DE PARÁMETROS — Grid Search          ║");
+    println!("║        FVG OPTIMIZADOR DE PARÁMETROS — {}     ║", title);
     println!("║  {} combinaciones × {} símbolos = {} backtests     ║",
-             total_combos, SYMBOLS.len(), total_combos * SYMBOLS.len());
-    println!("╚══════════════════════════════════════════════════════════════╝\n");
+             trials, cli.symbols.len(), trials * cli.symbols.len());
+    println!("╚══════════════════════════════════════════════════════════════╝");
+    println!("  Velas {}   Capital {}   Riesgo {}%   Mín. {} trades",
+             cfg.timeframe, cfg.initial_balance, cfg.max_risk_pct * 100.0, cfg.min_trades);
+    println!("  Selección: {}   |   Pareto: {}\n", search.select.describe(),
+             search.select.objectives.iter().map(|o| o.name()).collect::<Vec<_>>().join(", "));
 
     let mut best_per_sym: Vec<(&str, Result)> = Vec::new();
+    let mut wf_all: Vec<(&str, Vec<WfRun>, OosCurve)> = Vec::new();
+    let mut fronts: Vec<(&str, Vec<Result>)> = Vec::new();
 
-    for &symbol in SYMBOLS {
-        let csv = data_dir.join(format!("{}_4H.csv", symbol));
-        if !csv.exists() { eprintln!("  ⚠  No existe: {:?}", csv); continue; }
-
-        let candles = load_csv(&csv);
+    let instruments = backtest_common::require_instruments(&data_dir);
+    for symbol in &cli.symbols {
+        let symbol = symbol.as_str();
+        let Some(tick) = instruments.get(symbol).map(|i| i.tick_size) else {
+            eprintln!("  ⚠  {} no está en {:?} — ejecuta `download` para este símbolo",
+                      symbol, backtest_common::instruments_path(&data_dir));
+            continue;
+        };
+        let Some(candles) = load_candles(&data_dir, symbol, &cfg.timeframe, cfg.bar_ms, (from_ms, to_ms),
+                                         cli.store, cli.allow_dirty_data) else {
+            eprintln!("  ⚠  No existe: {:?}", data_dir.join(format!("{}_{}.csv", symbol, cfg.timeframe)));
+            continue;
+        };
         println!("  ── {} ({} velas) ──────────────────────────────────────", symbol, candles.len());
         print!("    buscando…");
 
-        let results = optimize_symbol(symbol, &candles);
+        let funding = load_funding(&data_dir.join(format!("{}_funding.csv", symbol)));
+        if funding.is_empty() {
+            println!("    (sin {}_funding.csv — funding no incluido)", symbol);
+        }
+        let costs = Costs { tick, funding, taker_fee: cfg.taker_fee, maker_fee: cfg.maker_fee, slippage: cfg.slippage };
+        let ind   = indicators(&candles);
+
+        if cfg.mode == Mode::WalkForward {
+            let (runs, curve) = walk_forward(&candles, &ind, &costs, &cfg, &search);
+            print_walk_forward(&candles, &runs, &cfg);
+            wf_all.push((symbol, runs, curve));
+            continue;
+        }
+
+        let all   = run_search(&candles, &ind, 0, &costs, &cfg, &search);
+        let front: Vec<Result> = pareto_front(&all, &search.select.objectives, cfg.min_trades)
+            .into_iter().cloned().collect();
+        println!("    Frente de Pareto: {} configuraciones no dominadas", front.len());
+        fronts.push((symbol, front));
+        let results = search.select.select(all);
 
         if results.is_empty() {
             println!("    Sin resultados válidos.");
@@ -295,54 +1156,78 @@ fn main() {
 
         // Top 3 para este símbolo
         println!("    Top 3 configuraciones:\n");
-        println!("    {:>4}  {:>6}  {:>6}  {:>4}  {:>5}  {:>5}  {:>6}  {:>7}  {:>5}  {:>7}  {:>8}",
-                 "Rank","WR%","PF","LB","Gap%","Vol×","TP×","SL×ATR","Stop","PnL","Score");
-        println!("    {}", "─".repeat(75));
+        println!("    {:>4}  {:>6}  {:>6}  {:>4}  {:>5}  {:>5}  {:>6}  {:>7}  {:>5}  {:>7}  {:>7}  {:>8}  Filtros",
+                 "Rank","WR%","PF","LB","Gap%","Vol×","TP×","SL×ATR","Stop","Bruto","Neto","Score");
+        println!("    {}", "─".repeat(84));
 
         for (rank, r) in results.iter().take(3).enumerate() {
             let p = &r.params;
             println!(
-                "    {:>4}  {:>5.1}%  {:>5.2}  {:>4}  {:>4.1}%  {:>4.1}×  {:>4.1}×  {:>6.1}  {:>5}  {:>+7.0}  {:>8.1}",
+                "    {:>4}  {:>5.1}%  {:>5.2}  {:>4}  {:>4.1}%  {:>4.1}×  {:>4.1}×  {:>6.1}  {:>5}  {:>+7.0}  {:>+7.0}  {:>8.1}  {}",
                 rank + 1, r.win_rate, r.profit_factor, p.lookback,
                 p.min_gap * 100.0, p.min_vol_mult, p.tp_mult, p.sl_atr_mult,
-                p.time_stop, r.total_pnl, r.score
+                p.time_stop, r.gross_pnl, r.total_pnl, r.score, p.filters()
             );
         }
 
         let best = results.into_iter().next().unwrap();
         println!();
-        println!("    ✅ MEJOR: WR={:.1}%  PF={:.2}  DD={:.1}%  {} trades  PnL={:+.0}",
+        println!("    ✅ MEJOR: WR={:.1}%  PF={:.2}  DD={:.1}%  {} trades  PnL={:+.0} neto ({:+.0} bruto)",
                  best.win_rate, best.profit_factor, best.max_drawdown,
-                 best.trades, best.total_pnl);
+                 best.trades, best.total_pnl, best.gross_pnl);
         println!();
 
         best_per_sym.push((symbol, best));
     }
 
+    if cfg.mode == Mode::WalkForward {
+        let windows = out_dir.join("walkforward_windows.csv");
+        let equity  = out_dir.join("walkforward_oos_equity.csv");
+        save_walk_forward(&wf_all, &windows, &equity);
+        println!("  📄 Ventanas guardadas: {:?}", windows);
+        println!("  📈 Equity OOS guardada: {:?}\n", equity);
+        return;
+    }
+
     // ── Resumen final ─────────────────────────────────────────────────────────
     println!("╔══════════════════════════════════════════════════════════════╗");
     println!("║  PARÁMETROS ÓPTIMOS POR SÍMBOLO                              ║");
     println!("╠══════════════════════════════════════════════════════════════╣");
-    println!("  {:12}  {:>5}  {:>5}  {:>4}  {:>5}  {:>5}  {:>5}  {:>6}  {:>4}  {:>8}",
-             "Symbol","WR%","PF","LB","Gap%","Vol×","TP×","SL×","Stop","PnL");
-    println!("  {}", "─".repeat(72));
+    println!("  {:12}  {:>5}  {:>5}  {:>4}  {:>5}  {:>5}  {:>5}  {:>6}  {:>4}  {:>8}  {:>8}  Filtros",
+             "Symbol","WR%","PF","LB","Gap%","Vol×","TP×","SL×","Stop","Bruto","Neto");
+    println!("  {}", "─".repeat(82));
     for (sym, r) in &best_per_sym {
         let p = &r.params;
-        println!("  {:12}  {:>4.1}%  {:>4.2}  {:>4}  {:>4.1}%  {:>4.1}  {:>4.1}  {:>5.1}  {:>4}  {:>+8.0}",
+        println!("  {:12}  {:>4.1}%  {:>4.2}  {:>4}  {:>4.1}%  {:>4.1}  {:>4.1}  {:>5.1}  {:>4}  {:>+8.0}  {:>+8.0}  {}",
                  sym, r.win_rate, r.profit_factor, p.lookback,
                  p.min_gap * 100.0, p.min_vol_mult, p.tp_mult, p.sl_atr_mult,
-                 p.time_stop, r.total_pnl);
+                 p.time_stop, r.gross_pnl, r.total_pnl, p.filters());
     }
     println!("╚══════════════════════════════════════════════════════════════╝");
 
     // Guardar CSV con mejores parámetros
     let refs: Vec<(&str, &Result)> = best_per_sym.iter().map(|(s, r)| (*s, r)).collect();
-    let out = data_dir.join("optimized_params.csv");
+    let out = cli.params.clone().unwrap_or_else(|| out_dir.join("optimized_params.csv"));
     save_best(&refs, &out);
     println!("\n  📄 Parámetros guardados: {:?}", out);
 
+    let rows: Vec<ParetoRow> = fronts.iter().flat_map(|(sym, front)| {
+        let chosen = best_per_sym.iter().find(|(s, _)| s == sym).map(|(_, r)| &r.params);
+        front.iter().map(move |r| ParetoRow {
+            symbol: sym, params: &r.params, trades: r.trades, win_rate: r.win_rate,
+            profit_factor: r.profit_factor, net_pnl: r.total_pnl, gross_pnl: r.gross_pnl,
+            max_drawdown: r.max_drawdown, sharpe: r.sharpe, score: r.score,
+            selected: chosen.is_some_and(|p| same_params(p, &r.params)),
+        })
+    }).collect();
+    let pareto_csv  = out_dir.join("pareto_front.csv");
+    let pareto_json = out_dir.join("pareto_front.json");
+    save_pareto(&rows, &pareto_csv, &pareto_json);
+    println!("  📄 Frente de Pareto: {:?} / {:?}", pareto_csv, pareto_json);
+
     // ── Instrucciones para aplicar ────────────────────────────────────────────
-    println!("\n  Para aplicar los parámetros óptimos por símbolo, actualiza");
-    println!("  config.rs con los valores del símbolo de mayor puntuación,");
-    println!("  o edita src/bin/backtest.rs para usarlos individualmente.\n");
+    println!("\n  Para validar los parámetros óptimos por símbolo:");
+    println!("    cargo run --bin backtest --release -- --timeframe {} --params {:?} \\\n      --taker-fee {} --maker-fee {} --slippage {}",
+             cfg.timeframe, out, cfg.taker_fee * 100.0, cfg.maker_fee * 100.0, cfg.slippage);
+    println!("  o actualiza config.rs con los valores del símbolo de mayor puntuación.\n");
 }
@@ -0,0 +1,195 @@
+/// Replay del feed público grabado por el bot (config::WS_RECORD) a través del
+/// mismo parser que usa en vivo (BybitWsClient::handle_text).
+///
+/// Reconstruye los buffers de velas tal y como los tenía el bot en un instante
+/// dado (--until), los imprime y los pasa por los mismos filtros de entrada
+/// (eval_candles → bias 4H → BOS 1H → FVG 15M → breakout) y por el selector de
+/// señales; con --speed 1 reproduce el timing original. Sin cuenta ni
+/// posiciones: no hay sizing ni penalización por correlación con el libro.
+/// Run: cargo run --bin replay --release -- [opciones]   (--help para la lista)
+#[allow(dead_code)]
+#[path = "../bybit_api.rs"]
+mod bybit_api;
+#[allow(dead_code)]
+#[path = "../config.rs"]
+mod config;
+#[allow(dead_code)]
+#[path = "../fvg_detector.rs"]
+mod fvg_detector;
+#[allow(dead_code)]
+#[path = "../position_manager.rs"]
+mod position_manager;
+#[allow(dead_code)]
+#[path = "../signal_selector.rs"]
+mod signal_selector;
+#[allow(dead_code)]
+#[path = "../types.rs"]
+mod types;
+#[allow(dead_code)]
+#[path = "../websocket_handler.rs"]
+mod websocket_handler;
+#[allow(dead_code)]
+#[path = "../ws_recorder.rs"]
+mod ws_recorder;
+
+use chrono::{DateTime, NaiveDateTime};
+use clap::Parser;
+use config::{EVAL_MODE, MAX_OPEN_POSITIONS, TF_BIAS, TF_ENTRY, TF_STRUCT};
+use signal_selector::CandidateMetrics;
+use std::path::{Path, PathBuf};
+use types::{BiasDirection, SignalType, TradeSignal};
+use websocket_handler::BybitWsClient;
+
+// ── CLI ───────────────────────────────────────────────────────────────────────
+#[derive(Parser)]
+#[command(name = "replay", about = "Replay del feed WebSocket grabado")]
+struct Cli {
+    /// Fichero ws_*.log.gz o directorio con ellos [por defecto: config::WS_RECORD_DIR]
+    path: Option<PathBuf>,
+    /// Velocidad: 1 = tiempo real, 10 = diez veces más rápido, 0 = sin esperas
+    #[arg(long, default_value_t = 0.0)]
+    speed: f64,
+    /// Parar en este instante de recepción (UTC, "YYYY-MM-DD HH:MM:SS")
+    #[arg(long, value_parser = parse_utc)]
+    until: Option<i64>,
+    /// Símbolos, separados por coma [por defecto: todos los grabados]
+    #[arg(long, value_delimiter = ',')]
+    symbols: Vec<String>,
+    /// Intervalos de Bybit, separados por coma
+    #[arg(long, value_delimiter = ',', default_values_t = config::KLINE_INTERVALS.iter().map(|s| s.to_string()))]
+    intervals: Vec<String>,
+    /// Velas a mostrar por buffer (las más recientes)
+    #[arg(long, default_value_t = 5)]
+    last: usize,
+}
+
+fn parse_utc(s: &str) -> Result<i64, String> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .map(|d| d.and_utc().timestamp_millis())
+        .map_err(|e| format!("fecha inválida '{}': {}", s, e))
+}
+
+fn ms_to_utc(ms: i64) -> String {
+    DateTime::from_timestamp_millis(ms).map_or(ms.to_string(), |d| d.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+// ── Señales ───────────────────────────────────────────────────────────────────
+/// Filtros de entrada del bot (main.rs) sobre los buffers de `symbol`: la
+/// señal con sus métricas de ranking, o el motivo por el que no hay.
+fn evaluate(client: &BybitWsClient, symbol: &str) -> Result<(TradeSignal, &'static str, CandidateMetrics), String> {
+    let (raw_4h, raw_1h, raw_15m) = (client.get_candles(symbol, TF_BIAS),
+                                     client.get_candles(symbol, TF_STRUCT),
+                                     client.get_candles(symbol, TF_ENTRY));
+    let candles_4h  = fvg_detector::eval_candles(&raw_4h, EVAL_MODE);
+    let candles_1h  = fvg_detector::eval_candles(&raw_1h, EVAL_MODE);
+    let candles_15m = fvg_detector::eval_candles(&raw_15m, EVAL_MODE);
+    if candles_4h.len() < 20 || candles_15m.len() < 20 {
+        return Err(format!("datos insuficientes (4H {} / 15M {} velas)", candles_4h.len(), candles_15m.len()));
+    }
+    // Como en vivo: el precio es el de la última vela, aunque esté en formación
+    let current_price = raw_15m.last().map_or(0.0, |c| c.close);
+    let p = config::symbol_params(symbol);
+
+    let bias = fvg_detector::detect_bias(candles_4h, &p);
+    let (signal_type, side) = match bias {
+        BiasDirection::Bullish => (SignalType::BuyBreakout, "Buy"),
+        BiasDirection::Bearish => (SignalType::SellBreakout, "Sell"),
+        BiasDirection::Neutral => return Err("4H bias neutro".to_string()),
+    };
+    if candles_1h.len() <= p.bos_window {
+        return Err(format!("1H sin datos suficientes ({} velas)", candles_1h.len()));
+    }
+    if !fvg_detector::detect_structure_break(candles_1h, &bias, &p) {
+        return Err("1H sin BOS".to_string());
+    }
+
+    let fvg = match bias {
+        BiasDirection::Bullish => fvg_detector::detect_bullish_fvg(candles_15m, &p),
+        _                      => fvg_detector::detect_bearish_fvg(candles_15m, &p),
+    };
+    let Some(fvg) = fvg else {
+        return Err(match fvg_detector::scan_pending_fvg(candles_15m, &p) {
+            Some(pend) => format!("FVG 15M {} pendiente — falta: {}", pend.direction, pend.missing),
+            None       => format!("sin FVG 15M en ventana ({}v)", p.fvg_lookback),
+        });
+    };
+    let avg_volume_15m = candles_15m.iter().rev().take(20).map(|c| c.volume).sum::<f64>() / 20.0;
+    if !fvg_detector::check_fvg_breakout(&fvg, candles_15m.last().unwrap(), avg_volume_15m, &p) {
+        return Err(format!("FVG 15M [{:.2}–{:.2}] sin breakout", fvg.zone_low, fvg.zone_high));
+    }
+
+    let bb_4h = fvg_detector::bollinger_bands(candles_4h, 20);
+    let entry_price = position_manager::limit_entry_price(&fvg, &p).unwrap_or(current_price);
+    let mut sig = position_manager::build_signal(signal_type, fvg, entry_price);
+    position_manager::set_stop_loss(&mut sig, fvg_detector::calculate_atr(candles_4h, 14), &p, bb_4h.as_ref());
+    position_manager::calculate_take_profits(&mut sig, &p, bb_4h.as_ref());
+    position_manager::round_to_tick(&mut sig, p.tick_size);
+    if !position_manager::levels_consistent(&sig, side) {
+        return Err(format!("TP/SL incoherentes: entrada {:.6} SL {:.6} TP {:.6}", sig.entry_price, sig.stop_loss, sig.take_profit_1));
+    }
+    let ranking = CandidateMetrics::for_signal(&sig, side, candles_15m, candles_1h);
+    Ok((sig, side, ranking))
+}
+
+// ── Main ──────────────────────────────────────────────────────────────────────
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let path = cli.path.clone().unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join(config::WS_RECORD_DIR));
+    let files = ws_recorder::list_files(&path).unwrap_or_else(|e| panic!("no se pudo leer {:?}: {}", path, e));
+    if files.is_empty() {
+        eprintln!("  ⚠  Sin grabaciones en {:?}", path);
+        std::process::exit(1);
+    }
+    // Sin --symbols, los buffers se crean según aparecen los topics (una sola pasada)
+    let discover = cli.symbols.is_empty();
+
+    println!("\n▶  Replay de {} ficheros, {} × {} intervalos{}\n",
+             files.len(),
+             if discover { "todos los símbolos".to_string() } else { format!("{} símbolos", cli.symbols.len()) },
+             cli.intervals.len(),
+             cli.until.map_or(String::new(), |u| format!(", hasta {}", ms_to_utc(u))));
+
+    let symbol_refs: Vec<&str> = cli.symbols.iter().map(String::as_str).collect();
+    let interval_refs: Vec<&str> = cli.intervals.iter().map(String::as_str).collect();
+    let client = BybitWsClient::new(&symbol_refs, &interval_refs, 1);
+    let frames = client.replay(&files, cli.speed, cli.until, discover).await
+        .unwrap_or_else(|e| panic!("error leyendo la grabación: {}", e));
+    let symbols = if discover { client.buffered_symbols() } else { cli.symbols.clone() };
+
+    for symbol in &symbols {
+        for interval in &cli.intervals {
+            let candles = client.get_candles(symbol, interval);
+            if candles.is_empty() { continue; }
+            println!("  {} {} — {} velas en buffer", symbol, interval, candles.len());
+            for c in candles.iter().skip(candles.len().saturating_sub(cli.last)) {
+                println!("    {} {}  O {:<12} H {:<12} L {:<12} C {:<12} V {}",
+                         ms_to_utc(c.timestamp), if c.confirmed { "✓" } else { "…" },
+                         c.open, c.high, c.low, c.close, c.volume);
+            }
+        }
+    }
+
+    println!("\n  Señales ({:?}):", EVAL_MODE);
+    let mut signals: Vec<(&str, TradeSignal, &str, CandidateMetrics)> = Vec::new();
+    for symbol in &symbols {
+        match evaluate(&client, symbol) {
+            Ok((sig, side, ranking)) => {
+                println!("    {:<12} {} @ {:.6}  SL {:.6}  TP {:.6}  R:R {:.2}",
+                         symbol, side, sig.entry_price, sig.stop_loss, sig.take_profit_1, sig.risk_reward_ratio);
+                signals.push((symbol, sig, side, ranking));
+            }
+            Err(reason) => println!("    {:<12} {}", symbol, reason),
+        }
+    }
+    if !signals.is_empty() {
+        let candidates: Vec<CandidateMetrics> = signals.iter().map(|s| s.3.clone()).collect();
+        let picked = signal_selector::select(&candidates, &[], MAX_OPEN_POSITIONS);
+        println!("\n  Selección ({} señales, {} huecos):", signals.len(), MAX_OPEN_POSITIONS);
+        for (rank, (i, score)) in picked.iter().enumerate() {
+            println!("    {}. {:<12} {}  score {:.3} (base {:.3})", rank + 1, signals[*i].0, signals[*i].2,
+                     score, signal_selector::base_score(&candidates[*i]));
+        }
+    }
+    println!("\n✅ {} frames aplicados.\n", frames);
+}
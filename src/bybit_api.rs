@@ -1,11 +1,14 @@
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
 use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/// Data returned by `get_position_info` for a live exchange position.
+/// Data returned by `get_all_open_positions` / `get_position_info` for a live exchange leg.
 #[derive(Debug, Clone)]
 pub struct ExchangePositionInfo {
+    #[allow(dead_code)] // also in the PositionKey the legs are stored under
+    pub position_idx: u8, // 0 one-way, 1 long leg, 2 short leg (hedge mode)
     pub side:         String,
     pub size:         f64,
     pub avg_price:    f64,
@@ -14,10 +17,79 @@ pub struct ExchangePositionInfo {
     pub created_time: i64, // Unix seconds
 }
 
+/// Current state of one order as reported by `/v5/order/realtime` (or history).
+#[derive(Debug, Clone)]
+pub struct OrderSnapshot {
+    pub status:       String, // New | PartiallyFilled | Filled | Cancelled | Rejected | …
+    pub cum_exec_qty: f64,
+    pub avg_price:    f64,    // 0.0 until something fills
+    pub reject_reason: String,
+}
+
+/// An order still working on the exchange, from `/v5/order/realtime`.
+#[derive(Debug, Clone)]
+pub struct OpenOrderInfo {
+    pub symbol:        String,
+    pub order_id:      String,
+    pub order_link_id: String, // starts with ENTRY_LINK_PREFIX for the bot's entries
+    pub side:          String,
+    pub position_idx:  u8,
+    pub qty:           f64,
+    pub cum_exec_qty:  f64,
+}
+
+/// Per-symbol account settings as reported by `/v5/position/list`.
+#[derive(Debug, Clone)]
+pub struct SymbolAccountSettings {
+    pub leverage:   f64,
+    pub hedge_mode: bool, // true if the symbol reports positionIdx 1/2 legs
+    pub isolated:   bool, // tradeMode == 1
+}
+
+/// Active USDT linear perpetual from `/v5/market/instruments-info`.
+#[derive(Debug, Clone)]
+pub struct LinearInstrument {
+    pub symbol:         String,
+    pub launch_time_ms: i64,
+    #[allow(dead_code)] // download bin (instruments.json)
+    pub tick_size:      f64,
+}
+
+/// 24h market stats from `/v5/market/tickers` (USDT values).
+#[derive(Debug, Clone)]
+pub struct LinearTicker {
+    pub turnover_24h:        f64,
+    pub open_interest_value: f64,
+    pub bid:                 f64,
+    pub ask:                 f64,
+}
+
 use crate::config::BYBIT_REST_URL;
+use crate::types::PositionKey;
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// `orderLinkId` prefix of every entry order the bot places, so entries left
+/// working by a previous run can be told apart from manual orders.
+pub const ENTRY_LINK_PREFIX: &str = "fvg-entry-";
+
+static LINK_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Unique `orderLinkId` for a new entry (Bybit limit: 36 chars).
+fn entry_link_id() -> String {
+    format!("{}{}-{}", ENTRY_LINK_PREFIX, BybitClient::timestamp_ms(), LINK_SEQ.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Page sizes of the public history endpoints (Bybit maximums).
+#[allow(dead_code)] // history download (src/bin/download.rs)
+const KLINE_PAGE_LIMIT: usize = 1000;
+#[allow(dead_code)]
+const FUNDING_PAGE_LIMIT: usize = 200;
+/// Pause between history pages: keeps bulk downloads far below the public
+/// 600 req / 5 s IP limit.
+#[allow(dead_code)]
+const HISTORY_PAGE_DELAY: Duration = Duration::from_millis(150);
+
 // ── Error types ───────────────────────────────────────────────────────────────
 
 #[derive(Debug)]
@@ -53,6 +125,16 @@ fn classify_error(ret_code: i64, http_status: u16, msg: &str) -> BybitError {
     }
 }
 
+/// Bar length of a Bybit kline interval ("15", "240", "D", "W"); None for "M".
+pub fn interval_ms(interval: &str) -> Option<i64> {
+    match interval {
+        "D" => Some(86_400_000),
+        "W" => Some(7 * 86_400_000),
+        "M" => None,
+        mins => mins.parse::<i64>().ok().map(|m| m * 60_000),
+    }
+}
+
 /// Generic retry wrapper with exponential backoff.
 async fn with_retry<F, Fut, T>(operation: F, max_retries: u32) -> Result<T, BybitError>
 where
@@ -111,6 +193,18 @@ impl BybitClient {
         BybitClient { client, base_url: BYBIT_REST_URL.to_string(), api_key, api_secret }
     }
 
+    /// Client without credentials — only the public market-data endpoints
+    /// (klines, funding history, instruments) work.
+    #[allow(dead_code)] // download bin
+    pub fn public() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(15))
+            .connect_timeout(Duration::from_secs(5))
+            .build()
+            .expect("HTTP client build failed");
+        BybitClient { client, base_url: BYBIT_REST_URL.to_string(), api_key: String::new(), api_secret: String::new() }
+    }
+
     fn timestamp_ms() -> u64 {
         SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -143,23 +237,95 @@ impl BybitClient {
         headers
     }
 
+    /// Signed GET; returns the full JSON on retCode=0.
+    async fn signed_get(&self, path: &str, query: &str) -> Result<serde_json::Value, BybitError> {
+        let ts = Self::timestamp_ms().to_string();
+        let recv_window = "5000";
+        let payload = format!("{}{}{}{}", ts, self.api_key, recv_window, query);
+        let signature = self.sign(&payload);
+
+        let url = format!("{}{}?{}", self.base_url, path, query);
+        let resp = self
+            .client
+            .get(&url)
+            .header("X-BAPI-API-KEY", &self.api_key)
+            .header("X-BAPI-TIMESTAMP", &ts)
+            .header("X-BAPI-SIGN", signature)
+            .header("X-BAPI-RECV-WINDOW", recv_window)
+            .send()
+            .await
+            .map_err(|e| BybitError::Transient(format!("HTTP error: {}", e)))?;
+
+        let http_status = resp.status().as_u16();
+        let json: serde_json::Value = resp
+            .json()
+            .await
+            .map_err(|e| BybitError::Transient(format!("Parse error: {}", e)))?;
+
+        let ret_code = json["retCode"].as_i64().unwrap_or(-1);
+        if ret_code == 0 {
+            Ok(json)
+        } else {
+            let msg = json["retMsg"].as_str().unwrap_or("unknown");
+            Err(classify_error(ret_code, http_status, msg))
+        }
+    }
+
+    /// Signed POST; retCodes in `accept` (e.g. "not modified") count as success.
+    async fn signed_post(
+        &self,
+        path: &str,
+        body: serde_json::Value,
+        accept: &[i64],
+    ) -> Result<serde_json::Value, BybitError> {
+        let body = body.to_string();
+        let url = format!("{}{}", self.base_url, path);
+        let headers = self.signed_headers(&body);
+
+        let resp = self
+            .client
+            .post(&url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| BybitError::Transient(format!("HTTP error: {}", e)))?;
+
+        let http_status = resp.status().as_u16();
+        let json: serde_json::Value = resp
+            .json()
+            .await
+            .map_err(|e| BybitError::Transient(format!("Parse error: {}", e)))?;
+
+        let ret_code = json["retCode"].as_i64().unwrap_or(-1);
+        if ret_code == 0 || accept.contains(&ret_code) {
+            Ok(json)
+        } else {
+            let msg = json["retMsg"].as_str().unwrap_or("unknown");
+            Err(classify_error(ret_code, http_status, msg))
+        }
+    }
+
     // ── Internal raw methods (no retry) ──────────────────────────────────────
 
     async fn place_order_raw(
         &self,
-        symbol: &str,
+        key: &PositionKey,
         side: &str,
         qty: f64,
         stop_loss: f64,
         take_profit: f64,
         price_decimals: usize,
     ) -> Result<String, BybitError> {
+        let symbol = key.symbol.as_str();
         let body = serde_json::json!({
             "category":   "linear",
             "symbol":     symbol,
             "side":       side,
+            "positionIdx": key.position_idx,
             "orderType":  "Market",
             "qty":        format!("{:.4}", qty),
+            "orderLinkId": entry_link_id(),
             "stopLoss":   format!("{:.*}", price_decimals, stop_loss),
             "takeProfit": format!("{:.*}", price_decimals, take_profit),
             "tpslMode":   "Full",
@@ -201,16 +367,19 @@ impl BybitClient {
 
     async fn close_position_raw(
         &self,
-        symbol: &str,
+        key: &PositionKey,
         side: &str,
         qty: f64,
     ) -> Result<String, BybitError> {
+        let symbol = key.symbol.as_str();
         let close_side = if side == "Buy" { "Sell" } else { "Buy" };
 
+        // In hedge mode the reduce-only order must target the leg being closed
         let body = serde_json::json!({
             "category":     "linear",
             "symbol":       symbol,
             "side":         close_side,
+            "positionIdx":  key.position_idx,
             "orderType":    "Market",
             "qty":          format!("{:.4}", qty),
             "reduceOnly":   true,
@@ -250,6 +419,7 @@ impl BybitClient {
         }
     }
 
+    #[allow(dead_code)]
     async fn get_position_raw(
         &self,
         symbol: &str,
@@ -292,11 +462,15 @@ impl BybitClient {
         symbol: &str,
         interval: &str,
         limit: usize,
+        end_ms: Option<i64>,
     ) -> Result<Vec<crate::types::Candle>, BybitError> {
-        let url = format!(
+        let mut url = format!(
             "https://api.bybit.com/v5/market/kline?category=linear&symbol={}&interval={}&limit={}",
             symbol, interval, limit
         );
+        if let Some(end) = end_ms {
+            url.push_str(&format!("&end={}", end));
+        }
         let resp = self
             .client
             .get(&url)
@@ -320,6 +494,9 @@ impl BybitClient {
             .as_array()
             .ok_or_else(|| BybitError::Transient("missing result.list".into()))?;
 
+        // The newest row is the bar still forming; anything that ended is closed.
+        let bar_ms = interval_ms(interval);
+        let now_ms = Self::timestamp_ms() as i64;
         let mut candles: Vec<crate::types::Candle> = list
             .iter()
             .filter_map(|row| {
@@ -330,7 +507,8 @@ impl BybitClient {
                 let low: f64 = arr[3].as_str()?.parse().ok()?;
                 let close: f64 = arr[4].as_str()?.parse().ok()?;
                 let volume: f64 = arr[5].as_str()?.parse().ok()?;
-                Some(crate::types::Candle { timestamp: ts, open, high, low, close, volume })
+                let confirmed = bar_ms.is_some_and(|ms| ts + ms <= now_ms);
+                Some(crate::types::Candle { timestamp: ts, open, high, low, close, volume, confirmed })
             })
             .collect();
         candles.reverse(); // Bybit returns newest-first; reverse to oldest-first
@@ -339,10 +517,10 @@ impl BybitClient {
 
     // ── Public methods with retry ─────────────────────────────────────────────
 
-    /// Place a market order.  side = "Buy" | "Sell"
+    /// Place a market order on the leg given by `key`.  side = "Buy" | "Sell"
     pub async fn place_order(
         &self,
-        symbol: &str,
+        key: &PositionKey,
         side: &str,
         qty: f64,
         stop_loss: f64,
@@ -350,35 +528,36 @@ impl BybitClient {
         price_decimals: usize,
     ) -> Result<String, BybitError> {
         let s = self.clone();
-        let sym = symbol.to_string();
+        let k = key.clone();
         let si = side.to_string();
         with_retry(|| {
             let s = s.clone();
-            let sym = sym.clone();
+            let k = k.clone();
             let si = si.clone();
-            async move { s.place_order_raw(&sym, &si, qty, stop_loss, take_profit, price_decimals).await }
+            async move { s.place_order_raw(&k, &si, qty, stop_loss, take_profit, price_decimals).await }
         }, 3).await
     }
 
-    /// Close an open position with a market order (opposite side).
+    /// Close an open position leg with a reduce-only market order (opposite side).
     pub async fn close_position(
         &self,
-        symbol: &str,
+        key: &PositionKey,
         side: &str,
         qty: f64,
     ) -> Result<String, BybitError> {
         let s = self.clone();
-        let sym = symbol.to_string();
+        let k = key.clone();
         let si = side.to_string();
         with_retry(|| {
             let s = s.clone();
-            let sym = sym.clone();
+            let k = k.clone();
             let si = si.clone();
-            async move { s.close_position_raw(&sym, &si, qty).await }
+            async move { s.close_position_raw(&k, &si, qty).await }
         }, 3).await
     }
 
     /// Fetch current position for a symbol.
+    #[allow(dead_code)]
     pub async fn get_position(&self, symbol: &str) -> Result<serde_json::Value, BybitError> {
         let s = self.clone();
         let sym = symbol.to_string();
@@ -390,6 +569,7 @@ impl BybitClient {
     }
 
     /// Parse position data from exchange. Returns None if no open position (size == 0).
+    #[allow(dead_code)]
     pub async fn get_position_info(
         &self,
         symbol: &str,
@@ -407,6 +587,7 @@ impl BybitClient {
         }
 
         Ok(Some(ExchangePositionInfo {
+            position_idx: entry["positionIdx"].as_u64().unwrap_or(0) as u8,
             side:         entry["side"].as_str().unwrap_or("Buy").to_string(),
             size,
             avg_price:    entry["avgPrice"].as_str().and_then(|s| s.parse().ok()).unwrap_or(0.0),
@@ -420,10 +601,11 @@ impl BybitClient {
     }
 
     /// Fetch ALL open linear positions in a single authenticated REST call.
-    /// Returns a map of symbol → ExchangePositionInfo (only symbols with size > 0).
+    /// Returns a map of leg → ExchangePositionInfo (only legs with size > 0).
+    /// In hedge mode a symbol can appear twice (positionIdx 1 and 2).
     pub async fn get_all_open_positions(
         &self,
-    ) -> Result<std::collections::HashMap<String, ExchangePositionInfo>, BybitError> {
+    ) -> Result<std::collections::HashMap<PositionKey, ExchangePositionInfo>, BybitError> {
         let ts = Self::timestamp_ms().to_string();
         let recv_window = "5000";
         let query = "category=linear&settleCoin=USDT&limit=200";
@@ -464,10 +646,12 @@ impl BybitClient {
             let size: f64 = entry["size"].as_str().and_then(|s| s.parse().ok()).unwrap_or(0.0);
             if size == 0.0 { continue; }
             let symbol = match entry["symbol"].as_str() {
-                Some(s) => s.to_string(),
+                Some(s) => s,
                 None => continue,
             };
-            map.insert(symbol, ExchangePositionInfo {
+            let position_idx = entry["positionIdx"].as_u64().unwrap_or(0) as u8;
+            map.insert(PositionKey::new(symbol, position_idx), ExchangePositionInfo {
+                position_idx,
                 side:         entry["side"].as_str().unwrap_or("Buy").to_string(),
                 size,
                 avg_price:    entry["avgPrice"].as_str().and_then(|s| s.parse().ok()).unwrap_or(0.0),
@@ -508,13 +692,120 @@ impl BybitClient {
             let s = s.clone();
             let sym = sym.clone();
             let iv = iv.clone();
-            async move { s.fetch_klines_raw(&sym, &iv, limit).await }
+            async move { s.fetch_klines_raw(&sym, &iv, limit, None).await }
         }, 3).await
     }
 
-    /// Fetch all active USDT linear perpetual symbols from Bybit (public, no auth).
-    /// Returns symbols sorted alphabetically.
-    pub async fn fetch_linear_symbols(&self) -> Result<Vec<String>, BybitError> {
+    /// Fetch every kline with open time in `[start_ms, end_ms]`, paging
+    /// backwards from `end_ms` (KLINE_PAGE_LIMIT per request, HISTORY_PAGE_DELAY
+    /// between requests). Returns candles oldest-first, without duplicates.
+    #[allow(dead_code)] // download bin
+    pub async fn fetch_klines_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_ms: i64,
+        end_ms: i64,
+    ) -> Result<Vec<crate::types::Candle>, BybitError> {
+        let mut out: Vec<crate::types::Candle> = Vec::new();
+        let mut page_end = end_ms;
+        while page_end >= start_ms {
+            let s = self.clone();
+            let sym = symbol.to_string();
+            let iv = interval.to_string();
+            let page = with_retry(|| {
+                let s = s.clone();
+                let sym = sym.clone();
+                let iv = iv.clone();
+                async move { s.fetch_klines_raw(&sym, &iv, KLINE_PAGE_LIMIT, Some(page_end)).await }
+            }, 5).await?;
+            let Some(oldest) = page.first().map(|c| c.timestamp) else { break };
+            out.extend(page.into_iter().filter(|c| c.timestamp >= start_ms && c.timestamp <= end_ms));
+            if oldest <= start_ms || oldest > page_end { break; }
+            page_end = oldest - 1;
+            tokio::time::sleep(HISTORY_PAGE_DELAY).await;
+        }
+        out.sort_by_key(|c| c.timestamp);
+        out.dedup_by_key(|c| c.timestamp);
+        Ok(out)
+    }
+
+    #[allow(dead_code)]
+    async fn fetch_funding_raw(
+        &self,
+        symbol: &str,
+        start_ms: i64,
+        end_ms: i64,
+    ) -> Result<Vec<(i64, f64)>, BybitError> {
+        let url = format!(
+            "https://api.bybit.com/v5/market/funding/history?category=linear&symbol={}&limit={}&startTime={}&endTime={}",
+            symbol, FUNDING_PAGE_LIMIT, start_ms, end_ms
+        );
+        let resp = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| BybitError::Transient(format!("HTTP error: {}", e)))?;
+
+        let http_status = resp.status().as_u16();
+        let json: serde_json::Value = resp
+            .json()
+            .await
+            .map_err(|e| BybitError::Transient(format!("Parse error: {}", e)))?;
+
+        let ret_code = json["retCode"].as_i64().unwrap_or(-1);
+        if ret_code != 0 {
+            let msg = json["retMsg"].as_str().unwrap_or("unknown");
+            return Err(classify_error(ret_code, http_status, msg));
+        }
+
+        let list = json["result"]["list"]
+            .as_array()
+            .ok_or_else(|| BybitError::Transient("missing result.list".into()))?;
+        Ok(list
+            .iter()
+            .filter_map(|row| {
+                let ts: i64 = row["fundingRateTimestamp"].as_str()?.parse().ok()?;
+                let rate: f64 = row["fundingRate"].as_str()?.parse().ok()?;
+                Some((ts, rate))
+            })
+            .collect())
+    }
+
+    /// Funding rate history `(timestamp_ms, rate)` in `[start_ms, end_ms]`,
+    /// oldest-first. Pages backwards like `fetch_klines_range`.
+    #[allow(dead_code)] // download bin
+    pub async fn fetch_funding_history(
+        &self,
+        symbol: &str,
+        start_ms: i64,
+        end_ms: i64,
+    ) -> Result<Vec<(i64, f64)>, BybitError> {
+        let mut out: Vec<(i64, f64)> = Vec::new();
+        let mut page_end = end_ms;
+        while page_end >= start_ms {
+            let s = self.clone();
+            let sym = symbol.to_string();
+            let page = with_retry(|| {
+                let s = s.clone();
+                let sym = sym.clone();
+                async move { s.fetch_funding_raw(&sym, start_ms, page_end).await }
+            }, 5).await?;
+            let Some(oldest) = page.iter().map(|r| r.0).min() else { break };
+            out.extend(page);
+            if oldest <= start_ms || oldest > page_end { break; }
+            page_end = oldest - 1;
+            tokio::time::sleep(HISTORY_PAGE_DELAY).await;
+        }
+        out.sort_by_key(|r| r.0);
+        out.dedup_by_key(|r| r.0);
+        Ok(out)
+    }
+
+    /// Fetch active USDT linear perpetuals with their listing time (public endpoint).
+    /// Sorted by symbol.
+    pub async fn fetch_linear_instruments(&self) -> Result<Vec<LinearInstrument>, BybitError> {
         let url = "https://api.bybit.com/v5/market/instruments-info\
                    ?category=linear&status=Trading&limit=1000";
         let resp = self
@@ -540,7 +831,7 @@ impl BybitClient {
             .as_array()
             .ok_or_else(|| BybitError::Permanent("instruments-info: missing list".into()))?;
 
-        let mut symbols: Vec<String> = list
+        let mut instruments: Vec<LinearInstrument> = list
             .iter()
             .filter_map(|item| {
                 let symbol   = item["symbol"].as_str()?;
@@ -548,20 +839,74 @@ impl BybitClient {
                 let contract = item["contractType"].as_str().unwrap_or("");
                 // Only perpetuals — exclude dated futures (LinearFutures) like XRPUSDT-27MAR26
                 if quote == "USDT" && contract == "LinearPerpetual" {
-                    Some(symbol.to_string())
+                    Some(LinearInstrument {
+                        symbol: symbol.to_string(),
+                        launch_time_ms: item["launchTime"].as_str().and_then(|s| s.parse().ok()).unwrap_or(0),
+                        tick_size: item["priceFilter"]["tickSize"].as_str().and_then(|s| s.parse().ok()).unwrap_or(0.0),
+                    })
                 } else {
                     None
                 }
             })
             .collect();
-        symbols.sort();
-        Ok(symbols)
+        instruments.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        Ok(instruments)
+    }
+
+    /// Fetch all active USDT linear perpetual symbols from Bybit (public endpoint).
+    /// Returns symbols sorted alphabetically.
+    #[allow(dead_code)]
+    pub async fn fetch_linear_symbols(&self) -> Result<Vec<String>, BybitError> {
+        Ok(self.fetch_linear_instruments().await?.into_iter().map(|i| i.symbol).collect())
+    }
+
+    /// 24h ticker of every linear symbol, keyed by symbol (public endpoint).
+    pub async fn fetch_linear_tickers(&self) -> Result<std::collections::HashMap<String, LinearTicker>, BybitError> {
+        let url = "https://api.bybit.com/v5/market/tickers?category=linear";
+        let resp = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| BybitError::Transient(format!("HTTP error: {}", e)))?;
+
+        let http_status = resp.status().as_u16();
+        let json: serde_json::Value = resp
+            .json()
+            .await
+            .map_err(|e| BybitError::Transient(format!("Parse error: {}", e)))?;
+
+        let ret_code = json["retCode"].as_i64().unwrap_or(-1);
+        if ret_code != 0 {
+            let msg = json["retMsg"].as_str().unwrap_or("unknown");
+            return Err(classify_error(ret_code, http_status, msg));
+        }
+
+        let list = json["result"]["list"]
+            .as_array()
+            .ok_or_else(|| BybitError::Permanent("tickers: missing list".into()))?;
+
+        let num = |v: &serde_json::Value| v.as_str().and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
+        Ok(list
+            .iter()
+            .filter_map(|item| {
+                let symbol = item["symbol"].as_str()?.to_string();
+                Some((symbol, LinearTicker {
+                    turnover_24h:        num(&item["turnover24h"]),
+                    open_interest_value: num(&item["openInterestValue"]),
+                    bid:                 num(&item["bid1Price"]),
+                    ask:                 num(&item["ask1Price"]),
+                }))
+            })
+            .collect())
     }
 
-    /// Place a limit order (better fill, maker fees). side = "Buy" | "Sell"
+    /// Place a post-only limit order (maker fees; rejected instead of crossing
+    /// the book). side = "Buy" | "Sell"
+    #[allow(clippy::too_many_arguments)]
     pub async fn place_limit_order(
         &self,
-        symbol: &str,
+        key: &PositionKey,
         side: &str,
         qty: f64,
         price: f64,
@@ -569,17 +914,20 @@ impl BybitClient {
         take_profit: f64,
         price_decimals: usize,
     ) -> Result<String, BybitError> {
+        let symbol = key.symbol.as_str();
         let body = serde_json::json!({
             "category":   "linear",
             "symbol":     symbol,
             "side":       side,
+            "positionIdx": key.position_idx,
             "orderType":  "Limit",
             "qty":        format!("{:.4}", qty),
             "price":      format!("{:.*}", price_decimals, price),
+            "orderLinkId": entry_link_id(),
             "stopLoss":   format!("{:.*}", price_decimals, stop_loss),
             "takeProfit": format!("{:.*}", price_decimals, take_profit),
             "tpslMode":   "Full",
-            "timeInForce":"GTC"
+            "timeInForce":"PostOnly"
         })
         .to_string();
 
@@ -614,4 +962,221 @@ impl BybitClient {
             Err(classify_error(ret_code, http_status, msg))
         }
     }
+
+    // ── Account settings: leverage, margin mode, position mode ───────────────
+
+    /// Maximum leverage allowed by the instrument (public, no auth).
+    pub async fn get_max_leverage(&self, symbol: &str) -> Result<f64, BybitError> {
+        let url = format!(
+            "https://api.bybit.com/v5/market/instruments-info?category=linear&symbol={}",
+            symbol
+        );
+        let resp = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| BybitError::Transient(format!("HTTP error: {}", e)))?;
+
+        let http_status = resp.status().as_u16();
+        let json: serde_json::Value = resp
+            .json()
+            .await
+            .map_err(|e| BybitError::Transient(format!("Parse error: {}", e)))?;
+
+        let ret_code = json["retCode"].as_i64().unwrap_or(-1);
+        if ret_code != 0 {
+            let msg = json["retMsg"].as_str().unwrap_or("unknown");
+            return Err(classify_error(ret_code, http_status, msg));
+        }
+
+        json["result"]["list"][0]["leverageFilter"]["maxLeverage"]
+            .as_str()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| BybitError::Permanent(format!("{}: maxLeverage missing", symbol)))
+    }
+
+    /// Leverage, position mode and trade mode currently configured for a symbol.
+    pub async fn get_symbol_settings(&self, symbol: &str) -> Result<SymbolAccountSettings, BybitError> {
+        let query = format!("category=linear&symbol={}", symbol);
+        let json = with_retry(|| self.signed_get("/v5/position/list", &query), 3).await?;
+        let list = json["result"]["list"]
+            .as_array()
+            .filter(|l| !l.is_empty())
+            .ok_or_else(|| BybitError::Permanent(format!("{}: empty position list", symbol)))?;
+
+        let first = &list[0];
+        Ok(SymbolAccountSettings {
+            leverage:   first["leverage"].as_str().and_then(|s| s.parse().ok()).unwrap_or(0.0),
+            hedge_mode: list.iter().any(|e| e["positionIdx"].as_i64().unwrap_or(0) != 0),
+            isolated:   first["tradeMode"].as_i64() == Some(1),
+        })
+    }
+
+    /// Set buy and sell leverage for a symbol. "Leverage not modified" (110043) is success.
+    pub async fn set_leverage(&self, symbol: &str, leverage: f64) -> Result<(), BybitError> {
+        let lev = format!("{}", leverage);
+        let body = serde_json::json!({
+            "category":     "linear",
+            "symbol":       symbol,
+            "buyLeverage":  lev,
+            "sellLeverage": lev
+        });
+        with_retry(|| self.signed_post("/v5/position/set-leverage", body.clone(), &[110043]), 3).await?;
+        log::info!("[{}] Leverage set to {}×", symbol, lev);
+        Ok(())
+    }
+
+    /// Switch a symbol between one-way (mode 0) and hedge (mode 3) position mode.
+    /// "Position mode not modified" (110025) is success.
+    pub async fn switch_position_mode(&self, symbol: &str, hedge: bool) -> Result<(), BybitError> {
+        let body = serde_json::json!({
+            "category": "linear",
+            "symbol":   symbol,
+            "mode":     if hedge { 3 } else { 0 }
+        });
+        with_retry(|| self.signed_post("/v5/position/switch-mode", body.clone(), &[110025]), 3).await?;
+        Ok(())
+    }
+
+    /// Account-wide margin mode (unified account): `REGULAR_MARGIN` (cross),
+    /// `ISOLATED_MARGIN` or `PORTFOLIO_MARGIN`.
+    pub async fn get_margin_mode(&self) -> Result<String, BybitError> {
+        let json = with_retry(|| self.signed_get("/v5/account/info", ""), 3).await?;
+        json["result"]["marginMode"]
+            .as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| BybitError::Permanent("account/info: marginMode missing".into()))
+    }
+
+    pub async fn set_margin_mode(&self, mode: &str) -> Result<(), BybitError> {
+        let body = serde_json::json!({ "setMarginMode": mode });
+        let json = with_retry(|| self.signed_post("/v5/account/set-margin-mode", body.clone(), &[]), 3).await?;
+        // Bybit answers retCode=0 with a non-empty `reasons` list when the switch is refused
+        if let Some(reasons) = json["result"]["reasons"].as_array().filter(|r| !r.is_empty()) {
+            let msg = reasons
+                .iter()
+                .filter_map(|r| r["reasonMsg"].as_str())
+                .collect::<Vec<_>>()
+                .join("; ");
+            return Err(BybitError::Permanent(format!("set-margin-mode refused: {}", msg)));
+        }
+        Ok(())
+    }
+
+    // ── Order status / cancel ─────────────────────────────────────────────────
+
+    /// Look up one order: open and recent orders first, then order history.
+    pub async fn get_order(&self, symbol: &str, order_id: &str) -> Result<OrderSnapshot, BybitError> {
+        let query = format!("category=linear&symbol={}&orderId={}", symbol, order_id);
+        let mut json = with_retry(|| self.signed_get("/v5/order/realtime", &query), 3).await?;
+        if json["result"]["list"].as_array().is_none_or(|l| l.is_empty()) {
+            json = with_retry(|| self.signed_get("/v5/order/history", &query), 3).await?;
+        }
+        let item = json["result"]["list"]
+            .as_array()
+            .and_then(|l| l.first())
+            .ok_or_else(|| BybitError::Permanent(format!("order {} not found", order_id)))?;
+
+        Ok(OrderSnapshot {
+            status:       item["orderStatus"].as_str().unwrap_or("").to_string(),
+            cum_exec_qty: item["cumExecQty"].as_str().and_then(|s| s.parse().ok()).unwrap_or(0.0),
+            avg_price:    item["avgPrice"].as_str().and_then(|s| s.parse().ok()).unwrap_or(0.0),
+            reject_reason: item["rejectReason"].as_str().unwrap_or("").to_string(),
+        })
+    }
+
+    /// Every order still working on a USDT linear contract (all symbols, paged).
+    pub async fn get_open_orders(&self) -> Result<Vec<OpenOrderInfo>, BybitError> {
+        let parse = |v: &serde_json::Value| v.as_str().and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
+        let mut out = Vec::new();
+        let mut cursor = String::new();
+        loop {
+            let mut query = "category=linear&settleCoin=USDT&openOnly=0&limit=50".to_string();
+            if !cursor.is_empty() {
+                query.push_str(&format!("&cursor={}", cursor));
+            }
+            let json = with_retry(|| self.signed_get("/v5/order/realtime", &query), 3).await?;
+            for item in json["result"]["list"].as_array().into_iter().flatten() {
+                out.push(OpenOrderInfo {
+                    symbol:        item["symbol"].as_str().unwrap_or("").to_string(),
+                    order_id:      item["orderId"].as_str().unwrap_or("").to_string(),
+                    order_link_id: item["orderLinkId"].as_str().unwrap_or("").to_string(),
+                    side:          item["side"].as_str().unwrap_or("").to_string(),
+                    position_idx:  item["positionIdx"].as_u64().unwrap_or(0) as u8,
+                    qty:           parse(&item["qty"]),
+                    cum_exec_qty:  parse(&item["cumExecQty"]),
+                });
+            }
+            cursor = json["result"]["nextPageCursor"].as_str().unwrap_or("").to_string();
+            if cursor.is_empty() {
+                break;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Cancel an open order. "Order not exists or too late to cancel" (110001)
+    /// is returned as success — callers re-read the order to see how it ended.
+    pub async fn cancel_order(&self, symbol: &str, order_id: &str) -> Result<(), BybitError> {
+        let body = serde_json::json!({
+            "category": "linear",
+            "symbol":   symbol,
+            "orderId":  order_id
+        });
+        with_retry(|| self.signed_post("/v5/order/cancel", body.clone(), &[110001]), 3).await?;
+        log::info!("Order cancelled: {} {}", symbol, order_id);
+        Ok(())
+    }
+
+    // ── Fees / funding ────────────────────────────────────────────────────────
+
+    /// Sum of `execFee` over every execution of one order (USDT; negative = maker rebate).
+    pub async fn get_order_fees(&self, symbol: &str, order_id: &str) -> Result<f64, BybitError> {
+        let query = format!("category=linear&symbol={}&orderId={}&limit=100", symbol, order_id);
+        let json = with_retry(|| self.signed_get("/v5/execution/list", &query), 3).await?;
+        Ok(json["result"]["list"]
+            .as_array()
+            .map(|l| {
+                l.iter()
+                    .filter_map(|e| e["execFee"].as_str().and_then(|s| s.parse::<f64>().ok()))
+                    .sum()
+            })
+            .unwrap_or(0.0))
+    }
+
+    /// Net funding paid by one leg since `since_ms` (USDT; positive = paid, negative = received),
+    /// from the SETTLEMENT entries of the account transaction log. The log only covers 7 days.
+    pub async fn get_funding_paid(&self, key: &PositionKey, side: &str, since_ms: i64) -> Result<f64, BybitError> {
+        let end_ms = Self::timestamp_ms() as i64;
+        let start_ms = since_ms.max(end_ms - 7 * 24 * 3600 * 1000 + 60_000);
+        let mut total = 0.0;
+        let mut cursor = String::new();
+        loop {
+            let mut query = format!(
+                "accountType=UNIFIED&category=linear&currency=USDT&type=SETTLEMENT&startTime={}&endTime={}&limit=50",
+                start_ms, end_ms
+            );
+            if !cursor.is_empty() {
+                query.push_str(&format!("&cursor={}", cursor));
+            }
+            let json = with_retry(|| self.signed_get("/v5/account/transaction-log", &query), 3).await?;
+            for item in json["result"]["list"].as_array().into_iter().flatten() {
+                if item["symbol"].as_str() != Some(key.symbol.as_str()) {
+                    continue;
+                }
+                // Hedge mode: both legs settle separately; the log reports the leg's side
+                let item_side = item["side"].as_str().unwrap_or("");
+                if key.position_idx != 0 && !item_side.is_empty() && item_side != side {
+                    continue;
+                }
+                total += item["funding"].as_str().and_then(|s| s.parse::<f64>().ok()).unwrap_or(0.0);
+            }
+            cursor = json["result"]["nextPageCursor"].as_str().unwrap_or("").to_string();
+            if cursor.is_empty() {
+                break;
+            }
+        }
+        Ok(total)
+    }
 }
@@ -0,0 +1,158 @@
+//! Columnar binary candle store, shared by the backtest, optimize and convert
+//! binaries (`#[path]` include — the live bot does not use it).
+//!
+//! One file per symbol and interval, `{SYMBOL}_{tf}.bin`, next to the CSV it
+//! was converted from. Layout, all little-endian:
+//!
+//! ```text
+//! header   48 bytes   magic "FVGCNDL1", bar_ms i64, rows u64, first_ts i64, last_ts i64, reserved u64
+//! ts       rows × i64
+//! open     rows × f64
+//! high     rows × f64
+//! low      rows × f64
+//! close    rows × f64
+//! volume   rows × f64
+//! ```
+//!
+//! Rows are sorted by time and unique, so a date range is located by binary
+//! search on the memory-mapped `ts` column and only that slice is decoded.
+//! `candles.index.json` in the same directory lists every store with its
+//! interval, row count and time range.
+
+use crate::data_quality::Bar;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"FVGCNDL1";
+const HEADER_LEN: usize = 48;
+const COLUMNS: usize = 6; // ts, open, high, low, close, volume
+pub const INDEX_FILE: &str = "candles.index.json";
+
+pub fn store_path(data_dir: &Path, symbol: &str, tf: &str) -> PathBuf {
+    data_dir.join(format!("{}_{}.bin", symbol, tf))
+}
+
+/// Writes `bars` (sorted by time, no duplicates) to `path` via a temporary file.
+pub fn write(path: &Path, bar_ms: i64, bars: &[Bar]) -> io::Result<()> {
+    if bars.windows(2).any(|w| w[1].ts_ms <= w[0].ts_ms) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "bars must be sorted and unique"));
+    }
+    let tmp = path.with_extension("bin.tmp");
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    w.write_all(MAGIC)?;
+    w.write_all(&bar_ms.to_le_bytes())?;
+    w.write_all(&(bars.len() as u64).to_le_bytes())?;
+    w.write_all(&bars.first().map_or(0, |b| b.ts_ms).to_le_bytes())?;
+    w.write_all(&bars.last().map_or(0, |b| b.ts_ms).to_le_bytes())?;
+    w.write_all(&0u64.to_le_bytes())?;
+    for b in bars { w.write_all(&b.ts_ms.to_le_bytes())?; }
+    let fields: [fn(&Bar) -> f64; 5] = [|b| b.open, |b| b.high, |b| b.low, |b| b.close, |b| b.volume];
+    for field in fields {
+        for b in bars { w.write_all(&field(b).to_le_bytes())?; }
+    }
+    w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+/// A store file mapped read-only.
+pub struct CandleFile {
+    map:          Mmap,
+    pub bar_ms:   i64,
+    pub rows:     usize,
+    pub first_ts: i64,
+    pub last_ts:  i64,
+}
+
+impl CandleFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the file is only ever replaced by rename (see `write`), never
+        // modified in place, so the mapped bytes do not change under us.
+        let map = unsafe { Mmap::map(&file)? };
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}: {}", path, msg));
+        if map.len() < HEADER_LEN || &map[..8] != MAGIC {
+            return Err(invalid("not a candle store"));
+        }
+        let word = |i: usize| i64::from_le_bytes(map[8 + i * 8..16 + i * 8].try_into().unwrap());
+        let (bar_ms, first_ts, last_ts) = (word(0), word(2), word(3));
+        let rows = usize::try_from(word(1) as u64).map_err(|_| invalid("row count overflow"))?;
+        let len = rows.checked_mul(COLUMNS * 8).and_then(|n| n.checked_add(HEADER_LEN))
+            .ok_or_else(|| invalid("row count overflow"))?;
+        if map.len() != len {
+            return Err(invalid("truncated file"));
+        }
+        Ok(CandleFile { map, bar_ms, rows, first_ts, last_ts })
+    }
+
+    fn cell(&self, col: usize, i: usize) -> [u8; 8] {
+        let at = HEADER_LEN + (col * self.rows + i) * 8;
+        self.map[at..at + 8].try_into().unwrap()
+    }
+
+    pub fn ts(&self, i: usize) -> i64 {
+        i64::from_le_bytes(self.cell(0, i))
+    }
+
+    pub fn bar(&self, i: usize) -> Bar {
+        let f = |col| f64::from_le_bytes(self.cell(col, i));
+        Bar { ts_ms: self.ts(i), open: f(1), high: f(2), low: f(3), close: f(4), volume: f(5) }
+    }
+
+    /// Rows with open time in `[from_ms, to_ms)`.
+    pub fn range(&self, from_ms: i64, to_ms: i64) -> Range<usize> {
+        let lower_bound = |t: i64| {
+            let (mut lo, mut hi) = (0, self.rows);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                if self.ts(mid) < t { lo = mid + 1 } else { hi = mid }
+            }
+            lo
+        };
+        let a = lower_bound(from_ms);
+        a..lower_bound(to_ms).max(a)
+    }
+
+    pub fn bars(&self, rows: Range<usize>) -> Vec<Bar> {
+        rows.map(|i| self.bar(i)).collect()
+    }
+}
+
+// ── Index ─────────────────────────────────────────────────────────────────────
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub symbol:    String,
+    pub timeframe: String,
+    pub file:      String,
+    pub bar_ms:    i64,
+    pub rows:      usize,
+    pub first_ts:  i64,
+    pub last_ts:   i64,
+}
+
+/// `{SYMBOL}_{tf}` → entry.
+pub type Index = BTreeMap<String, IndexEntry>;
+
+pub fn load_index(data_dir: &Path) -> Index {
+    std::fs::read_to_string(data_dir.join(INDEX_FILE)).ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Records (or replaces) the entry for `symbol`/`tf` from the store's header.
+pub fn update_index(data_dir: &Path, symbol: &str, tf: &str) -> io::Result<()> {
+    let path = store_path(data_dir, symbol, tf);
+    let f = CandleFile::open(&path)?;
+    let mut index = load_index(data_dir);
+    index.insert(format!("{}_{}", symbol, tf), IndexEntry {
+        symbol: symbol.to_string(), timeframe: tf.to_string(),
+        file: path.file_name().unwrap().to_string_lossy().into_owned(),
+        bar_ms: f.bar_ms, rows: f.rows, first_ts: f.first_ts, last_ts: f.last_ts,
+    });
+    std::fs::write(data_dir.join(INDEX_FILE), serde_json::to_string_pretty(&index)?)
+}
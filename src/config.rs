@@ -14,16 +14,107 @@ pub const EQUITY_FLOOR_PCT: f64 = 0.90;      // 90 %
 pub const TRADING_PAIRS: &[&str] = &["BTCUSDT", "ETHUSDT", "BNBUSDT", "XRPUSDT", "SOLUSDT"];
 pub const MAX_OPEN_POSITIONS: usize = 2;
 
-/// If true, bot fetches the full list of active USDT linear perpetuals from Bybit at startup
-/// and scans all of them (currently ~300 pairs).
+// ─── Portfolio caps (HyroTrader position rules) ───────────────────────────────
+/// Funded accounts add the 25 % per-position exposure limit.
+pub const FUNDED_ACCOUNT: bool = false;
+pub const MAX_GROSS_NOTIONAL_MULT: f64 = 2.0;  // total open notional ≤ 2× initial balance
+pub const MAX_SYMBOL_NOTIONAL_PCT: f64 = if FUNDED_ACCOUNT { 0.25 } else { 1.0 };
+pub const MAX_EFFECTIVE_LEVERAGE: f64 = 10.0;  // gross notional / equity
+pub const MAX_LOW_CAP_RISK_PCT: f64 = 0.05;    // riesgo agregado a SL en low-caps
+
+/// Pairs treated as large caps; everything else counts towards the low-cap risk cap.
+pub const LARGE_CAP_PAIRS: &[&str] = &["BTCUSDT", "ETHUSDT", "BNBUSDT", "XRPUSDT", "SOLUSDT"];
+
+pub fn is_low_cap(symbol: &str) -> bool {
+    !LARGE_CAP_PAIRS.contains(&symbol)
+}
+
+// ─── Account settings (enforced at startup and on first trade per symbol) ─────
+/// Requested leverage; capped by the instrument max and MAX_EFFECTIVE_LEVERAGE.
+pub const TARGET_LEVERAGE: f64 = 5.0;
+/// Unified-account margin mode: "REGULAR_MARGIN" (cross) or "ISOLATED_MARGIN".
+pub const MARGIN_MODE: &str = "REGULAR_MARGIN";
+/// Seconds before a symbol whose settings could not be confirmed is retried.
+pub const SETTINGS_RETRY_SECS: u64 = 30 * 60;
+/// Hedge mode: long and short legs per symbol (positionIdx 1/2), so opposite-bias
+/// signals can coexist. If false, symbols are kept in one-way mode (positionIdx 0).
+pub const HEDGE_MODE: bool = false;
+
+// ─── Costs ────────────────────────────────────────────────────────────────────
+/// Taker fee used to estimate costs when execution data is unavailable
+/// (manual closes, failed `/v5/execution/list` calls).
+pub const TAKER_FEE_RATE: f64 = 0.00055;
+/// Append-only CSV with one row per realised close (gross, fees, funding, net).
+pub const TRADE_JOURNAL_PATH: &str = "data/live_trades.csv";
+
+// ─── Entry orders ─────────────────────────────────────────────────────────────
+#[allow(dead_code)] // variants are picked by editing ENTRY_MODE
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryMode {
+    Market,    // market order at the breakout close
+    LimitEdge, // post-only limit at the near edge of the FVG (zone_high long / zone_low short)
+    LimitMid,  // post-only limit at the FVG midpoint
+}
+pub const ENTRY_MODE: EntryMode = EntryMode::Market;
+/// Entry-TF candles a resting limit entry may wait for a fill before it is cancelled.
+pub const LIMIT_EXPIRY_CANDLES: i64 = 4;
+
+// ─── Strategy evaluation ──────────────────────────────────────────────────────
+#[allow(dead_code)] // variants are picked by editing EVAL_MODE
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EvalMode {
+    ClosedBars, // signals only on closed candles (confirm = true), as in the backtest
+    Intrabar,   // also on the forming candle, whose close/volume still move
+}
+pub const EVAL_MODE: EvalMode = EvalMode::ClosedBars;
+
+// ─── Signal selection (when more signals fire than free slots) ────────────────
+pub const SCORE_W_RR:        f64 = 0.35;
+pub const SCORE_W_FVG_ATR:   f64 = 0.20;
+pub const SCORE_W_VOLUME:    f64 = 0.20;
+pub const SCORE_W_LIQUIDITY: f64 = 0.25;
+/// 15M turnover per candle (USDT) that earns the full liquidity score.
+pub const LIQUIDITY_REF_USDT: f64 = 5_000_000.0;
+/// 1H returns used for the correlation penalty (~2 days).
+pub const CORRELATION_WINDOW: usize = 48;
+/// Score multiplier is 1 − PENALTY × max same-direction correlation.
+pub const CORRELATION_PENALTY: f64 = 0.8;
+
+/// If true, bot trades the active USDT linear perpetuals that pass the
+/// universe filters below, refreshed while running (see universe.rs).
 /// If false, uses only TRADING_PAIRS above.
 pub const USE_ALL_PAIRS: bool = true;
 
+// ─── Symbol universe (USE_ALL_PAIRS) ──────────────────────────────────────────
+/// instruments-info and 24h tickers are re-read this often; symbols that pass
+/// the filters below are subscribed at runtime, the rest dropped — never one
+/// with an open position or a working order.
+pub const UNIVERSE_REFRESH_SECS: u64 = 60 * 60;
+pub const UNIVERSE_MIN_TURNOVER_24H: f64 = 10_000_000.0; // USDT
+pub const UNIVERSE_MIN_OPEN_INTEREST: f64 = 5_000_000.0; // USDT
+/// New listings trade erratically: wait this long after launch.
+pub const UNIVERSE_MIN_LISTING_DAYS: i64 = 30;
+/// Max bid/ask spread as a fraction of the mid price.
+pub const UNIVERSE_MAX_SPREAD_PCT: f64 = 0.001; // 0.1 %
+
 // ─── Multi-timeframe intervals ────────────────────────────────────────────────
 pub const TF_BIAS:   &str = "240"; // 4H — bias direction via SMA(20)
 pub const TF_STRUCT: &str = "60";  // 1H — Break of Structure confirmation
 pub const TF_ENTRY:  &str = "15";  // 15M — FVG entry
 pub const KLINE_INTERVALS: &[&str] = &[TF_BIAS, TF_STRUCT, TF_ENTRY];
+/// Public kline connections; symbols are spread round-robin over them, so a
+/// drop only stalls (and backfills) that share of the universe.
+pub const WS_CONNECTIONS: usize = 4;
+/// How often buffers flagged with missing bars (WS reconnect, bar-time jump)
+/// are refetched over REST. Entries on the symbol wait for the backfill.
+pub const GAP_BACKFILL_SECS: u64 = 5;
+
+// ─── Public WS feed recording (replay: cargo run --bin replay) ───────────────
+/// Write every public WebSocket frame, with its local receive time, to disk.
+pub const WS_RECORD: bool = false;
+pub const WS_RECORD_DIR: &str = "data/ws_record";
+/// A new gzip file is started after this many seconds.
+pub const WS_RECORD_ROTATE_SECS: u64 = 60 * 60;
 
 // ─── Parámetros optimizados por símbolo (resultado del grid search) ───────────
 // Generados por: cargo run --bin optimize --release
@@ -36,22 +127,38 @@ pub const KLINE_INTERVALS: &[&str] = &[TF_BIAS, TF_STRUCT, TF_ENTRY];
 //           XRPUSDT  56.8  1.49    8  0.8%  1.0   1.5  2.0   14
 //           SOLUSDT  57.1  1.83   12  0.8%  1.2   4.0  1.5    7
 
+//
+// Filtros de confirmación: iguales para todos los símbolos salvo que se
+// sobrescriban, p. ej. `SymbolParams { bos_window: 12, ..params(…) }`
+// (bb_period / bias_threshold / bos_window de optimized_params.csv; un
+// periodo 0 o un umbral vacío allí = sin ese filtro, aquí 0).
+pub const BB_PERIOD:      usize = 20;    // 15M: cierre vs media de Bollinger(N)
+pub const BIAS_THRESHOLD: f64   = 0.002; // 4H: cierre vs SMA(20) × (1 ± umbral)
+pub const BOS_WINDOW:     usize = 20;    // 1H: velas previas que debe romper el cierre
+
 pub struct SymbolParams {
-    pub min_gap_pct:   f64,   // mínimo tamaño del gap FVG como % del precio
-    pub min_vol_mult:  f64,   // multiplicador de volumen mínimo
-    pub fvg_lookback:  usize, // velas hacia atrás para buscar FVG
-    pub sl_atr_mult:   f64,   // multiplicador ATR para el stop-loss
-    pub tp_mult:       f64,   // ratio riesgo:recompensa
-    pub time_stop:     usize, // velas máximas en posición
-    pub qty_step:      f64,   // paso mínimo de cantidad (Bybit lotSize)
-    pub tick_size:     f64,   // paso mínimo de precio (Bybit priceFilter)
+    pub min_gap_pct:    f64,   // mínimo tamaño del gap FVG como % del precio
+    pub min_vol_mult:   f64,   // multiplicador de volumen mínimo
+    pub fvg_lookback:   usize, // velas hacia atrás para buscar FVG
+    pub sl_atr_mult:    f64,   // multiplicador ATR para el stop-loss
+    pub tp_mult:        f64,   // ratio riesgo:recompensa
+    pub time_stop:      usize, // velas máximas en posición
+    pub qty_step:       f64,   // paso mínimo de cantidad (Bybit lotSize)
+    pub tick_size:      f64,   // paso mínimo de precio (Bybit priceFilter)
+    pub bb_period:      usize, // periodo de la media BB del filtro de entrada
+    pub bias_threshold: f64,   // umbral del bias sobre la SMA(20)
+    pub bos_window:     usize, // velas del break of structure
 }
 
+#[allow(clippy::too_many_arguments)]
 pub const fn params(
     min_gap_pct: f64, min_vol_mult: f64, fvg_lookback: usize,
     sl_atr_mult: f64, tp_mult: f64, time_stop: usize, qty_step: f64, tick_size: f64,
 ) -> SymbolParams {
-    SymbolParams { min_gap_pct, min_vol_mult, fvg_lookback, sl_atr_mult, tp_mult, time_stop, qty_step, tick_size }
+    SymbolParams {
+        min_gap_pct, min_vol_mult, fvg_lookback, sl_atr_mult, tp_mult, time_stop, qty_step, tick_size,
+        bb_period: BB_PERIOD, bias_threshold: BIAS_THRESHOLD, bos_window: BOS_WINDOW,
+    }
 }
 
 /// Number of decimal places for a given tick_size (e.g. 0.01 → 2, 0.0001 → 4).
@@ -0,0 +1,313 @@
+//! Historical candle CSV validation, shared by the backtest, optimize and
+//! download binaries (`#[path]` include — the live bot does not use it).
+//!
+//! `read_csv` parses strictly: a row with a missing or unparsable field is
+//! reported and skipped, never turned into zeros. `validate` checks the series
+//! against its bar interval: order, duplicates, alignment, gaps, zero-volume
+//! bars and OHLC consistency.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// One parsed CSV row (`timestamp_ms,datetime_utc,open,high,low,close,volume[,turnover]`).
+#[derive(Clone, Copy, Debug)]
+pub struct Bar {
+    pub ts_ms:  i64,
+    pub open:   f64,
+    pub high:   f64,
+    pub low:    f64,
+    pub close:  f64,
+    pub volume: f64,
+}
+
+#[derive(Clone, Debug)]
+pub enum Issue {
+    /// Line `line` (1-based, header = 1) has fewer than 7 fields or a field
+    /// that does not parse.
+    Unparsable { line: usize },
+    /// `ts_ms` appears more than once.
+    Duplicate { ts_ms: i64 },
+    /// `ts_ms` is earlier than the row before it.
+    OutOfOrder { ts_ms: i64 },
+    /// `ts_ms` is not a multiple of the bar interval.
+    Misaligned { ts_ms: i64 },
+    /// No bars with open time in `[from_ms, to_ms]` (`missing` bars).
+    Gap { from_ms: i64, to_ms: i64, missing: usize },
+    ZeroVolume { ts_ms: i64 },
+    /// high < low, open/close outside [low, high], or a non-positive price.
+    BadOhlc { ts_ms: i64 },
+}
+
+impl Issue {
+    /// Errors make the series unusable as-is; the rest (gaps, zero volume)
+    /// also happen on the exchange itself (maintenance, delistings) and are
+    /// only warnings.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Issue::Gap { .. } | Issue::ZeroVolume { .. })
+    }
+
+    /// Human-readable issue category, as used by `Quality::summary`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Issue::Unparsable { .. } => "unparsable rows",
+            Issue::Duplicate { .. }  => "duplicate timestamps",
+            Issue::OutOfOrder { .. } => "out-of-order rows",
+            Issue::Misaligned { .. } => "misaligned timestamps",
+            Issue::Gap { .. }        => "gaps",
+            Issue::ZeroVolume { .. } => "zero-volume bars",
+            Issue::BadOhlc { .. }    => "inconsistent OHLC",
+        }
+    }
+
+    /// Bar open time to refetch, if the issue concerns a single bar.
+    pub fn bar_ts(&self) -> Option<i64> {
+        match *self {
+            Issue::ZeroVolume { ts_ms } | Issue::BadOhlc { ts_ms } => Some(ts_ms),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of `check_csv`.
+#[derive(Default)]
+pub struct Quality {
+    pub rows:   usize,
+    pub issues: Vec<Issue>,
+}
+
+impl Quality {
+    pub fn errors(&self) -> usize {
+        self.issues.iter().filter(|i| i.is_error()).count()
+    }
+
+    pub fn gaps(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.issues.iter().filter_map(|i| match *i {
+            Issue::Gap { from_ms, to_ms, .. } => Some((from_ms, to_ms)),
+            _ => None,
+        })
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// One line per issue kind: count and first occurrence.
+    pub fn summary(&self) -> Vec<String> {
+        const KINDS: [&str; 7] = [
+            "unparsable rows", "duplicate timestamps", "out-of-order rows", "misaligned timestamps",
+            "gaps", "zero-volume bars", "inconsistent OHLC",
+        ];
+        KINDS.iter().filter_map(|name| {
+            let matching: Vec<&Issue> = self.issues.iter().filter(|i| i.kind() == *name).collect();
+            let first = matching.first()?;
+            let detail = match **first {
+                Issue::Unparsable { line } => format!("line {}", line),
+                Issue::Gap { from_ms, to_ms, .. } => {
+                    let missing: usize = matching.iter().map(|i| match i {
+                        Issue::Gap { missing, .. } => *missing,
+                        _ => 0,
+                    }).sum();
+                    format!("{} bars missing, first {} → {}", missing, fmt_ms(from_ms), fmt_ms(to_ms))
+                }
+                Issue::Duplicate { ts_ms } | Issue::OutOfOrder { ts_ms } | Issue::Misaligned { ts_ms }
+                | Issue::ZeroVolume { ts_ms } | Issue::BadOhlc { ts_ms } => format!("first {}", fmt_ms(ts_ms)),
+            };
+            Some(format!("{} {} ({})", matching.len(), name, detail))
+        }).collect()
+    }
+}
+
+/// Parses one data row; None if a field is missing or does not parse.
+pub fn parse_row(line: &str) -> Option<Bar> {
+    let f: Vec<&str> = line.split(',').map(str::trim).collect();
+    if f.len() < 7 { return None; }
+    Some(Bar {
+        ts_ms:  f[0].parse().ok()?,
+        open:   f[2].parse().ok()?,
+        high:   f[3].parse().ok()?,
+        low:    f[4].parse().ok()?,
+        close:  f[5].parse().ok()?,
+        volume: f[6].parse().ok()?,
+    })
+}
+
+/// Strict CSV parse. Rows come back in file order.
+pub fn read_csv(path: &Path) -> std::io::Result<(Vec<Bar>, Vec<Issue>)> {
+    let mut bars = Vec::with_capacity(9000);
+    let mut issues = Vec::new();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate().skip(1) {
+        let line = line?;
+        if line.trim().is_empty() { continue; }
+        match parse_row(&line) {
+            Some(b) => bars.push(b),
+            None    => issues.push(Issue::Unparsable { line: i + 1 }),
+        }
+    }
+    Ok((bars, issues))
+}
+
+/// Checks `bars` (in file order) against a `bar_ms` interval.
+pub fn validate(bars: &[Bar], bar_ms: i64) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for w in bars.windows(2) {
+        if w[1].ts_ms < w[0].ts_ms { issues.push(Issue::OutOfOrder { ts_ms: w[1].ts_ms }); }
+    }
+    for b in bars {
+        if b.ts_ms.rem_euclid(bar_ms) != 0 { issues.push(Issue::Misaligned { ts_ms: b.ts_ms }); }
+        let prices_ok = b.low > 0.0 && b.high >= b.low
+            && (b.low..=b.high).contains(&b.open) && (b.low..=b.high).contains(&b.close);
+        if !prices_ok { issues.push(Issue::BadOhlc { ts_ms: b.ts_ms }); }
+        else if b.volume <= 0.0 { issues.push(Issue::ZeroVolume { ts_ms: b.ts_ms }); }
+    }
+
+    let mut ts: Vec<i64> = bars.iter().map(|b| b.ts_ms).collect();
+    ts.sort_unstable();
+    for w in ts.windows(2) {
+        if w[1] == w[0] {
+            issues.push(Issue::Duplicate { ts_ms: w[0] });
+        } else if w[1] - w[0] > bar_ms {
+            issues.push(Issue::Gap {
+                from_ms: w[0] + bar_ms,
+                to_ms:   w[1] - bar_ms,
+                missing: ((w[1] - w[0]) / bar_ms - 1) as usize,
+            });
+        }
+    }
+    issues
+}
+
+/// `read_csv` + `validate`. Bars are returned sorted by time and without
+/// duplicates (first occurrence kept), whatever the verdict.
+pub fn check_csv(path: &Path, bar_ms: i64) -> std::io::Result<(Vec<Bar>, Quality)> {
+    let (mut bars, mut issues) = read_csv(path)?;
+    issues.extend(validate(&bars, bar_ms));
+    bars.sort_by_key(|b| b.ts_ms);
+    bars.dedup_by_key(|b| b.ts_ms);
+    let rows = bars.len();
+    Ok((bars, Quality { rows, issues }))
+}
+
+fn fmt_ms(ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(ms).map_or(ms.to_string(), |d| d.format("%Y-%m-%d %H:%M").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const H: i64 = 3_600_000;
+
+    fn bar(ts_ms: i64) -> Bar {
+        Bar { ts_ms, open: 100.0, high: 101.0, low: 99.0, close: 100.5, volume: 10.0 }
+    }
+
+    fn series(hours: &[i64]) -> Vec<Bar> {
+        hours.iter().map(|h| bar(h * H)).collect()
+    }
+
+    fn kinds(issues: &[Issue]) -> Vec<&'static str> {
+        issues.iter().map(Issue::kind).collect()
+    }
+
+    #[test]
+    fn clean_series_has_no_issues() {
+        assert!(validate(&series(&[0, 1, 2, 3]), H).is_empty());
+    }
+
+    #[test]
+    fn gap_covers_the_missing_bars() {
+        let issues = validate(&series(&[0, 1, 5, 6]), H);
+        assert_eq!(issues.len(), 1);
+        let Issue::Gap { from_ms, to_ms, missing } = issues[0] else { panic!("{:?}", issues) };
+        assert_eq!((from_ms, to_ms, missing), (2 * H, 4 * H, 3));
+        assert!(!issues[0].is_error());
+    }
+
+    #[test]
+    fn duplicate_timestamp() {
+        let issues = validate(&series(&[0, 1, 1, 2]), H);
+        assert_eq!(kinds(&issues), ["duplicate timestamps"]);
+        assert!(matches!(issues[0], Issue::Duplicate { ts_ms } if ts_ms == H));
+        assert!(issues[0].is_error());
+    }
+
+    #[test]
+    fn out_of_order_row() {
+        let issues = validate(&series(&[0, 2, 1, 3]), H);
+        assert_eq!(kinds(&issues), ["out-of-order rows"]);
+        assert!(matches!(issues[0], Issue::OutOfOrder { ts_ms } if ts_ms == H));
+    }
+
+    #[test]
+    fn misaligned_timestamp() {
+        let mut bars = series(&[0, 1]);
+        bars[1].ts_ms -= 60_000;
+        let issues = validate(&bars, H);
+        assert_eq!(kinds(&issues), ["misaligned timestamps"]);
+        assert!(matches!(issues[0], Issue::Misaligned { ts_ms } if ts_ms == H - 60_000));
+    }
+
+    #[test]
+    fn inconsistent_ohlc() {
+        let mut bars = series(&[0, 1, 2, 3]);
+        bars[0].high = 98.0;  // high < low
+        bars[1].open = 102.0; // open above high
+        bars[2].close = 98.0; // close below low
+        bars[3].low = 0.0;    // non-positive price
+        bars[3].open = 0.5;
+        bars[3].close = 0.5;
+        let issues = validate(&bars, H);
+        assert_eq!(kinds(&issues), ["inconsistent OHLC"; 4]);
+        assert_eq!(issues.iter().filter_map(Issue::bar_ts).collect::<Vec<_>>(), [0, H, 2 * H, 3 * H]);
+    }
+
+    #[test]
+    fn zero_volume_is_a_warning() {
+        let mut bars = series(&[0, 1]);
+        bars[1].volume = 0.0;
+        let issues = validate(&bars, H);
+        assert_eq!(kinds(&issues), ["zero-volume bars"]);
+        assert!(!issues[0].is_error());
+        assert_eq!(issues[0].bar_ts(), Some(H));
+    }
+
+    #[test]
+    fn bad_ohlc_is_not_also_zero_volume() {
+        let mut bars = series(&[0]);
+        bars[0].high = 98.0;
+        bars[0].volume = 0.0;
+        assert_eq!(kinds(&validate(&bars, H)), ["inconsistent OHLC"]);
+    }
+
+    #[test]
+    fn parse_row_rejects_missing_and_bad_fields() {
+        assert!(parse_row("0,1970-01-01 00:00,100,101,99,100.5,10,1000").is_some());
+        assert!(parse_row("0,1970-01-01 00:00,100,101,99,100.5").is_none());
+        assert!(parse_row("0,1970-01-01 00:00,100,abc,99,100.5,10").is_none());
+        assert!(parse_row("0,1970-01-01 00:00,100,,99,100.5,10").is_none());
+    }
+
+    #[test]
+    fn check_csv_reports_lines_and_returns_sorted_unique_bars() {
+        let path = std::env::temp_dir().join(format!("data_quality_{}.csv", std::process::id()));
+        std::fs::write(&path, "timestamp_ms,datetime_utc,open,high,low,close,volume\n\
+            7200000,x,100,101,99,100,10\n\
+            0,x,100,101,99,100,10\n\
+            oops\n\
+            \n\
+            3600000,x,100,101,99,100,10\n\
+            3600000,x,200,201,199,200,10\n").unwrap();
+        let result = check_csv(&path, H);
+        std::fs::remove_file(&path).unwrap();
+        let (bars, quality) = result.unwrap();
+
+        assert_eq!(bars.iter().map(|b| b.ts_ms).collect::<Vec<_>>(), [0, H, 2 * H]);
+        assert_eq!(bars[1].open, 100.0); // first occurrence kept
+        assert_eq!(quality.rows, 3);
+        assert!(matches!(quality.issues[0], Issue::Unparsable { line: 4 }));
+        assert_eq!(quality.errors(), 3); // unparsable, out of order, duplicate
+        assert_eq!(quality.gaps().count(), 0);
+        assert_eq!(quality.summary().len(), 3);
+    }
+}
@@ -1,10 +1,37 @@
-use crate::config::SymbolParams;
+use crate::config::{EvalMode, SymbolParams};
 use crate::types::{BiasDirection, Candle, FVGType, FVGZone};
 
 const VOL_AVG_PERIOD: usize = 20;
-const BB_PERIOD: usize = 20;
 const BB_MULT: f64 = 2.0;
 
+/// Velas que evalúa la estrategia: en `ClosedBars` se descarta la vela en
+/// formación del final del buffer (confirm = false); en `Intrabar`, todas.
+pub fn eval_candles(candles: &[Candle], mode: EvalMode) -> &[Candle] {
+    match (mode, candles.last()) {
+        (EvalMode::ClosedBars, Some(last)) if !last.confirmed => &candles[..candles.len() - 1],
+        _ => candles,
+    }
+}
+
+/// ATR simple (media del true range) de las últimas `period` velas; 0 si no
+/// hay suficientes.
+pub fn calculate_atr(candles: &[Candle], period: usize) -> f64 {
+    if candles.len() < period + 1 {
+        return 0.0;
+    }
+    let start = candles.len() - period - 1;
+    let mut tr_sum = 0.0;
+    for i in (start + 1)..candles.len() {
+        let curr = &candles[i];
+        let prev = &candles[i - 1];
+        let tr = (curr.high - curr.low)
+            .max((curr.high - prev.close).abs())
+            .max((curr.low - prev.close).abs());
+        tr_sum += tr;
+    }
+    tr_sum / period as f64
+}
+
 /// Bandas de Bollinger calculadas sobre los últimos `period` cierres.
 #[derive(Debug, Clone)]
 pub struct BollingerBands {
@@ -14,9 +41,9 @@ pub struct BollingerBands {
 }
 
 /// Calcula las Bandas de Bollinger estándar (SMA ± 2σ).
-/// Retorna `None` si no hay suficientes velas.
+/// Retorna `None` si no hay suficientes velas o `period` es 0.
 pub fn bollinger_bands(candles: &[Candle], period: usize) -> Option<BollingerBands> {
-    if candles.len() < period { return None; }
+    if period == 0 || candles.len() < period { return None; }
     let closes: Vec<f64> = candles.iter().rev().take(period).map(|c| c.close).collect();
     let middle = closes.iter().sum::<f64>() / period as f64;
     let variance = closes.iter().map(|p| (p - middle).powi(2)).sum::<f64>() / period as f64;
@@ -49,7 +76,7 @@ fn scan_pending_bullish(candles: &[Candle], p: &SymbolParams) -> Option<PendingF
     let current     = &candles[n - 1];
     let avg_vol     = avg_volume(candles, VOL_AVG_PERIOD);
     let search_start = n.saturating_sub(p.fvg_lookback + 2);
-    let bb_middle   = bollinger_bands(candles, BB_PERIOD).map(|b| b.middle).unwrap_or(0.0);
+    let bb_middle   = bollinger_bands(candles, p.bb_period).map(|b| b.middle).unwrap_or(0.0);
 
     for j in search_start..(n - 2) {
         let c1 = &candles[j];
@@ -103,7 +130,7 @@ fn scan_pending_bearish(candles: &[Candle], p: &SymbolParams) -> Option<PendingF
     let current     = &candles[n - 1];
     let avg_vol     = avg_volume(candles, VOL_AVG_PERIOD);
     let search_start = n.saturating_sub(p.fvg_lookback + 2);
-    let bb_middle   = bollinger_bands(candles, BB_PERIOD).map(|b| b.middle).unwrap_or(f64::MAX);
+    let bb_middle   = bollinger_bands(candles, p.bb_period).map(|b| b.middle).unwrap_or(f64::MAX);
 
     for j in search_start..(n - 2) {
         let c1 = &candles[j];
@@ -151,15 +178,14 @@ fn scan_pending_bearish(candles: &[Candle], p: &SymbolParams) -> Option<PendingF
 }
 
 /// Determines 4H bias via SMA(20).
-/// Bullish: close > SMA × (1 + 0.2%), Bearish: close < SMA × (1 − 0.2%), else Neutral.
-pub fn detect_bias(candles: &[Candle]) -> BiasDirection {
+/// Bullish: close > SMA × (1 + p.bias_threshold), Bearish: close < SMA × (1 − p.bias_threshold), else Neutral.
+pub fn detect_bias(candles: &[Candle], p: &SymbolParams) -> BiasDirection {
     if candles.len() < 20 { return BiasDirection::Neutral; }
     let sma20: f64 = candles.iter().rev().take(20).map(|c| c.close).sum::<f64>() / 20.0;
     let last = candles.last().unwrap().close;
-    const THRESHOLD: f64 = 0.002; // 0.2%
-    if last > sma20 * (1.0 + THRESHOLD) {
+    if last > sma20 * (1.0 + p.bias_threshold) {
         BiasDirection::Bullish
-    } else if last < sma20 * (1.0 - THRESHOLD) {
+    } else if last < sma20 * (1.0 - p.bias_threshold) {
         BiasDirection::Bearish
     } else {
         BiasDirection::Neutral
@@ -167,13 +193,15 @@ pub fn detect_bias(candles: &[Candle]) -> BiasDirection {
 }
 
 /// Confirms Break of Structure on 1H candles.
-/// Bullish BOS: current 1H close > max_high of prior 20 candles.
-/// Bearish BOS: current 1H close < min_low of prior 20 candles.
-pub fn detect_structure_break(candles: &[Candle], bias: &BiasDirection) -> bool {
+/// Bullish BOS: current 1H close > max_high of the prior `p.bos_window` candles.
+/// Bearish BOS: current 1H close < min_low of the prior `p.bos_window` candles.
+/// `p.bos_window` = 0 turns the filter off (any non-neutral bias passes).
+pub fn detect_structure_break(candles: &[Candle], bias: &BiasDirection, p: &SymbolParams) -> bool {
     let n = candles.len();
-    if n < 21 { return false; }
+    if p.bos_window == 0 { return *bias != BiasDirection::Neutral; }
+    if n < p.bos_window + 1 { return false; }
     let current = &candles[n - 1];
-    let window = &candles[n - 21..n - 1]; // 20 velas previas
+    let window = &candles[n - 1 - p.bos_window..n - 1]; // velas previas
     match bias {
         BiasDirection::Bullish => {
             let swing_high = window.iter().map(|c| c.high).fold(f64::NEG_INFINITY, f64::max);
@@ -201,7 +229,7 @@ pub fn detect_bullish_fvg(candles: &[Candle], p: &SymbolParams) -> Option<FVGZon
     let search_start = n.saturating_sub(p.fvg_lookback + 2);
 
     // Banda media de Bollinger como filtro de entrada alcista
-    let bb_middle = bollinger_bands(candles, BB_PERIOD).map(|b| b.middle).unwrap_or(0.0);
+    let bb_middle = bollinger_bands(candles, p.bb_period).map(|b| b.middle).unwrap_or(0.0);
 
     for j in search_start..(n - 2) {
         let c1 = &candles[j];
@@ -256,7 +284,7 @@ pub fn detect_bearish_fvg(candles: &[Candle], p: &SymbolParams) -> Option<FVGZon
     let search_start = n.saturating_sub(p.fvg_lookback + 2);
 
     // Banda media de Bollinger como filtro de entrada bajista
-    let bb_middle = bollinger_bands(candles, BB_PERIOD).map(|b| b.middle).unwrap_or(f64::MAX);
+    let bb_middle = bollinger_bands(candles, p.bb_period).map(|b| b.middle).unwrap_or(f64::MAX);
 
     for j in search_start..(n - 2) {
         let c1 = &candles[j];
@@ -15,34 +15,52 @@ fn jemalloc_purge() {
     log::debug!("jemalloc: epoch advanced — dirty pages scheduled for release");
 }
 
+mod account_setup;
 mod bybit_api;
 mod config;
 mod fvg_detector;
+mod order_tracker;
 mod position_manager;
+mod signal_selector;
 mod telegram;
+mod trade_journal;
 mod types;
+mod universe;
 mod websocket_handler;
 #[cfg(feature = "private-ws")]
 mod websocket_private;
+mod ws_recorder;
 
 use chrono::Timelike;
 use config::{
-    symbol_params, tick_decimals, ACCOUNT_BALANCE, EQUITY_FLOOR_PCT, KLINE_INTERVALS,
-    MAX_DAILY_LOSS_PCT, MAX_OPEN_POSITIONS, MAX_RISK_PER_TRADE_PCT, TRADING_PAIRS, TF_BIAS,
-    TF_ENTRY, TF_STRUCT, USE_ALL_PAIRS,
+    symbol_params, tick_decimals, ACCOUNT_BALANCE, CORRELATION_WINDOW, ENTRY_MODE, EVAL_MODE, GAP_BACKFILL_SECS,
+    EQUITY_FLOOR_PCT, KLINE_INTERVALS, LIMIT_EXPIRY_CANDLES, MAX_DAILY_LOSS_PCT,
+    MAX_OPEN_POSITIONS, MAX_RISK_PER_TRADE_PCT, HEDGE_MODE, SETTINGS_RETRY_SECS, TAKER_FEE_RATE,
+    TRADING_PAIRS, TF_BIAS, TF_ENTRY, TF_STRUCT, UNIVERSE_REFRESH_SECS, USE_ALL_PAIRS, WS_CONNECTIONS, WS_RECORD, WS_RECORD_DIR,
+    WS_RECORD_ROTATE_SECS,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::Semaphore;
 use bybit_api::ExchangePositionInfo;
-use types::{BiasDirection, PositionData, RiskMetrics, SignalType, TradeSignal};
+use config::EntryMode;
+use types::{BiasDirection, PositionData, PositionKey, RiskMetrics, SignalType, TradeSignal};
 
 struct OpenPosition {
     data: PositionData,
     side: String,
 }
 
+/// Entry signal that passed validation this cycle, waiting for a free slot.
+struct PendingOrder {
+    symbol: String,
+    signal: TradeSignal,
+    side: String,
+    price_decimals: usize,
+    ranking: signal_selector::CandidateMetrics,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
@@ -51,14 +69,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let bybit = bybit_api::BybitClient::new();
 
     // ── Determine trading pairs ───────────────────────────────────────────────
-    let trading_pairs: Vec<String> = if USE_ALL_PAIRS {
-        match bybit.fetch_linear_symbols().await {
-            Ok(pairs) => {
-                log::info!("Fetched {} USDT linear symbols from Bybit", pairs.len());
+    // With USE_ALL_PAIRS the list follows the liquidity-filtered universe and
+    // is refreshed every UNIVERSE_REFRESH_SECS (see the main loop).
+    let mut trading_pairs: Vec<String> = if USE_ALL_PAIRS {
+        match universe::fetch(&bybit).await {
+            Ok(pairs) if !pairs.is_empty() => {
+                log::info!("Universe: {} USDT linear symbols pass the liquidity filters", pairs.len());
                 pairs
             }
+            Ok(_) => {
+                log::warn!("Universe: no symbol passes the liquidity filters — falling back to default pairs");
+                TRADING_PAIRS.iter().map(|s| s.to_string()).collect()
+            }
             Err(e) => {
-                log::warn!("fetch_linear_symbols failed: {} — falling back to default pairs", e);
+                log::warn!("Universe fetch failed: {} — falling back to default pairs", e);
                 TRADING_PAIRS.iter().map(|s| s.to_string()).collect()
             }
         }
@@ -80,20 +104,74 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         wins_today: 0,
     };
 
-    // One open position slot per symbol
-    let mut positions: HashMap<String, OpenPosition> = HashMap::new();
-
-    // ── WebSocket: single connection, all symbols ─────────────────────────────
-    let ws_client = websocket_handler::BybitWsClient::new(&pair_refs, KLINE_INTERVALS);
+    // One open position per symbol (one-way) or per leg (hedge mode)
+    let mut positions: HashMap<PositionKey, OpenPosition> = HashMap::new();
+    // Every order placed by the bot, until its final state has been applied.
+    // Entry orders still working (incl. resting limits) count against position slots.
+    let mut orders = order_tracker::OrderTracker::default();
+
+    // ── WebSocket: WS_CONNECTIONS shards, each with its own reconnect ────────
+    let mut ws_client = websocket_handler::BybitWsClient::new(&pair_refs, KLINE_INTERVALS, WS_CONNECTIONS);
+    if WS_RECORD {
+        match ws_recorder::Recorder::start(WS_RECORD_DIR, WS_RECORD_ROTATE_SECS) {
+            Ok(recorder) => ws_client = ws_client.with_recorder(recorder),
+            Err(e) => log::warn!("WS recorder not started: {} — feed will not be recorded", e),
+        }
+    }
     let candle_map = ws_client.candle_map.clone();
-    tokio::spawn(async move {
-        websocket_handler::reconnect_with_backoff(&ws_client, 20, 5)
-            .await
-            .unwrap_or_else(|e| log::error!("WebSocket failed permanently: {}", e));
-    });
+    let candle_gaps = ws_client.gaps.clone();
+    let ws_client = Arc::new(ws_client);
+    for shard in 0..ws_client.shard_count() {
+        let ws_client = ws_client.clone();
+        tokio::spawn(async move {
+            websocket_handler::reconnect_with_backoff(&ws_client, shard, 20, 5)
+                .await
+                .unwrap_or_else(|e| log::error!("WebSocket shard {} failed permanently: {}", shard, e));
+        });
+    }
+
+    // ── Entry orders left working by a previous run ──────────────────────────
+    // Their signals died with that run, so they cannot be re-tracked: cancel
+    // them before reconciling, so any partial fill is final when it is imported.
+    cancel_leftover_entries(&bybit).await;
 
     // ── Reconcile positions with exchange after restart ───────────────────────
     reconcile_positions(&bybit, &mut positions, &pair_refs).await;
+    if USE_ALL_PAIRS {
+        // Positions on symbols outside the universe still need their candles.
+        let mut held: Vec<String> = positions.keys()
+            .map(|k| k.symbol.clone())
+            .filter(|s| !trading_pairs.contains(s))
+            .collect();
+        held.sort();
+        held.dedup();
+        if !held.is_empty() {
+            log::info!("Universe: keeping {} symbols with open positions: {}", held.len(), held.join(", "));
+            ws_client.add_symbols(&held);
+            trading_pairs.extend(held);
+        }
+    }
+
+    // ── Account settings: margin mode now, leverage/position mode per symbol ─
+    // Symbols are only traded once their settings have been read back.
+    let mut account_ready = match account_setup::configure_account(&bybit).await {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("Account settings not confirmed: {} — entries paused", e);
+            tg.notify_risk_alert(&format!("Account settings not confirmed: {}. Entries paused.", e))
+                .await;
+            false
+        }
+    };
+    let mut settings_ok: HashSet<String> = HashSet::new();
+    let mut settings_failed: HashMap<String, Instant> = HashMap::new();
+    let open_symbols: HashSet<String> = positions.keys().map(|k| k.symbol.clone()).collect();
+    for sym in &open_symbols {
+        match account_setup::prepare_symbol(&bybit, sym).await {
+            Ok(_) => { settings_ok.insert(sym.clone()); }
+            Err(e) => log::warn!("[{}] Settings of open position not confirmed: {}", sym, e),
+        }
+    }
 
     // ── Pre-load historical candles via REST in parallel ─────────────────────
     // Semaphore limits concurrent HTTP requests (important with many pairs).
@@ -119,13 +197,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let key = format!("{}_{}", symbol, tf);
                     match bybit.fetch_klines(&symbol, &tf, 30).await {
                         Ok(candles) => {
-                            let count = {
-                                let mut map = candle_map.lock().unwrap();
-                                if let Some(buf) = map.get_mut(&key) {
-                                    for c in candles { buf.push_back(c); }
-                                    buf.len()
-                                } else { 0 }
-                            };
+                            websocket_handler::merge_backfill(&candle_map, &key, candles);
+                            let count = candle_map.lock().unwrap().get(&key).map_or(0, |b| b.len());
                             log::info!("[{} {}] pre-loaded {} candles", symbol, tf, count);
                         }
                         Err(e) => log::warn!("[{} {}] prefetch failed: {}", symbol, tf, e),
@@ -136,6 +209,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .collect();
     for h in prefetch_handles { let _ = h.await; }
 
+    // ── Candle gap backfill (WS reconnects, missed bars) ──────────────────────
+    tokio::spawn(backfill_gaps(bybit.clone(), candle_map.clone(), candle_gaps.clone(), sem.clone()));
+
     // ── Private WebSocket (production only, not available on demo) ────────────
     #[cfg(feature = "private-ws")]
     let _private_ws_positions = {
@@ -185,6 +261,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut last_status_ts = Instant::now()
         .checked_sub(status_interval)
         .unwrap_or_else(Instant::now);
+    let universe_interval = Duration::from_secs(UNIVERSE_REFRESH_SECS);
+    let mut last_universe_ts = Instant::now();
 
     loop {
         // Snapshot candles for all symbols under a single lock
@@ -194,49 +272,53 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .map(|(sym, buf)| (sym.clone(), buf.iter().cloned().collect()))
                 .collect()
         };
+        let gap_keys: HashSet<String> = candle_gaps.lock().unwrap().keys().cloned().collect();
+
+        if !account_ready {
+            account_ready = account_setup::configure_account(&bybit).await.is_ok();
+        }
 
         let mut status_lines: Vec<String> = Vec::new();
         // Collect validated entry signals; orders executed in parallel after loop
-        let mut pending_orders: Vec<(String, TradeSignal, String, usize)> = Vec::new();
+        let mut pending_orders: Vec<PendingOrder> = Vec::new();
 
         // ── Detect manually closed positions ─────────────────────────────────
-        // Single REST call fetches all open positions; any locally tracked symbol
+        // Single REST call fetches all open positions; any locally tracked leg
         // absent from the exchange result was closed outside the bot.
+        // Legs with an exit order in flight are settled by the order tracker instead.
         if !positions.is_empty() {
             match bybit.get_all_open_positions().await {
                 Ok(exchange_pos) => {
-                    let manually_closed: Vec<String> = positions
+                    let manually_closed: Vec<PositionKey> = positions
                         .keys()
-                        .filter(|sym| !exchange_pos.contains_key(*sym))
+                        .filter(|key| !exchange_pos.contains_key(*key) && !orders.has_live_exit(key))
                         .cloned()
                         .collect();
 
-                    for sym in manually_closed {
-                        if let Some(op) = positions.get(&sym) {
-                            let entry = op.data.actual_entry.unwrap_or(op.data.entry_price);
-                            let side = op.side.clone();
-                            // unrealized_pnl is the best estimate we have at this point
-                            let pnl_estimate = op.data.unrealized_pnl;
+                    for key in manually_closed {
+                        let Some(op) = positions.get(&key) else { continue };
+                        let entry = op.data.actual_entry.unwrap_or(op.data.entry_price);
+                        let side = op.side.clone();
+                        let qty = op.data.position_size;
+                        // Derive an approximate exit price from the (side-signed) unrealized_pnl so metrics stay consistent
+                        let multiplier = if side == "Buy" { 1.0_f64 } else { -1.0_f64 };
+                        let exit_price = if qty > 0.0 {
+                            entry + (op.data.unrealized_pnl / qty) * multiplier
+                        } else {
+                            entry
+                        };
+                        settle_funding(&bybit, &mut positions, &key).await;
+                        // No execution data for a close we did not place: estimate a taker fee
+                        let exit_fee = qty * exit_price * TAKER_FEE_RATE;
+                        if let Some(t) = close_position_local(
+                            &mut positions, &key, &mut metrics, qty, exit_price, exit_fee, "Manual close",
+                        ) {
                             log::warn!(
-                                "[{}] Manual close detected — {} entry={:.2} pnl≈{:+.2}",
-                                sym, side, entry, pnl_estimate
+                                "[{}] Manual close detected — {} entry={:.2} pnl≈{:+.2} (net)",
+                                key, side, entry, t.net_pnl
                             );
-                            tg.notify_manual_close(&sym, &side, entry, pnl_estimate).await;
+                            tg.notify_manual_close(&key.symbol, &side, entry, t.net_pnl).await;
                         }
-                        // Derive an approximate exit price from unrealized_pnl so metrics stay consistent
-                        let exit_price = if let Some(op) = positions.get(&sym) {
-                            let entry = op.data.actual_entry.unwrap_or(op.data.entry_price);
-                            let multiplier = if op.side == "Buy" { 1.0_f64 } else { -1.0_f64 };
-                            let qty = op.data.position_size;
-                            if qty > 0.0 {
-                                entry + (op.data.unrealized_pnl / qty) * multiplier
-                            } else {
-                                entry
-                            }
-                        } else {
-                            0.0
-                        };
-                        close_position_local(&mut positions, &sym, &mut metrics, exit_price);
                     }
                 }
                 Err(e) => {
@@ -253,24 +335,37 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let key_1h  = format!("{}_{}", symbol, TF_STRUCT);
             let key_15m = format!("{}_{}", symbol, TF_ENTRY);
 
-            let candles_4h = match all_candles.get(&key_4h) {
+            // Signals are evaluated on the EVAL_MODE view; prices for open
+            // positions always come from the latest (possibly forming) candle.
+            let Some(current_price) = all_candles.get(&key_15m).and_then(|c| c.last()).map(|c| c.close) else {
+                continue;
+            };
+            let eval = |key: &str| all_candles.get(key).map(|c| fvg_detector::eval_candles(c, EVAL_MODE));
+            let candles_4h = match eval(&key_4h) {
                 Some(c) if c.len() >= 20 => c,
                 _ => continue,
             };
-            let candles_15m = match all_candles.get(&key_15m) {
+            let candles_15m = match eval(&key_15m) {
                 Some(c) if c.len() >= 20 => c,
                 _ => continue,
             };
 
             let p = symbol_params(&symbol);
             // ATR on 4H as fallback; BB(20,2σ) on 4H for primary SL/TP
-            let atr = calculate_atr(candles_4h, 14);
+            let atr = fvg_detector::calculate_atr(candles_4h, 14);
             let bb_4h = fvg_detector::bollinger_bands(candles_4h, 20);
-            let current_price = candles_15m.last().unwrap().close;
 
-            // ── Manage existing position ──────────────────────────────────────
-            if let Some(op) = positions.get_mut(&symbol) {
-                position_manager::update_position_pnl(&mut op.data, current_price);
+            // ── Manage existing position(s) ───────────────────────────────────
+            // One leg per symbol in one-way mode; long and short legs in hedge mode.
+            let legs: Vec<PositionKey> = positions
+                .keys()
+                .filter(|k| k.symbol == symbol)
+                .cloned()
+                .collect();
+            let mut open_sides: Vec<String> = Vec::new();
+            for key in legs {
+                let Some(op) = positions.get_mut(&key) else { continue };
+                position_manager::update_position_pnl(&mut op.data, &op.side, current_price);
                 metrics.current_equity = metrics.account_balance + op.data.unrealized_pnl;
 
                 let now_ts = chrono::Utc::now().timestamp();
@@ -298,49 +393,67 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     None
                 };
 
-                let mut position_closed = false;
-                if let Some(reason) = close_reason {
-                    match bybit.close_position(&symbol, &side, pos_qty).await {
-                        Ok(_) => {
-                            let multiplier = if side == "Buy" { 1.0 } else { -1.0 };
-                            let exit = op.data.actual_exit.unwrap_or(current_price);
-                            let pnl = (exit - entry) * pos_qty * multiplier;
-                            tg.notify_trade_close(&symbol, &side, entry, exit, pnl, reason)
-                                .await;
-                            close_position_local(
-                                &mut positions,
-                                &symbol,
-                                &mut metrics,
-                                current_price,
+                // An exit order is already working: wait for the tracker to confirm it
+                let closing = orders.has_live_exit(&key);
+                if let Some(reason) = close_reason.filter(|_| !closing) {
+                    // A partially filled limit entry must not keep adding to a leg being closed
+                    let working_entry = orders.live_entries()
+                        .find(|o| o.key == key)
+                        .map(|o| o.order_id.clone());
+                    if let Some(entry_id) = working_entry {
+                        if let Err(e) = bybit.cancel_order(&key.symbol, &entry_id).await {
+                            log::warn!("[{}] Cancel working entry {} failed: {}", key, entry_id, e);
+                        }
+                    }
+                    match bybit.close_position(&key, &side, pos_qty).await {
+                        Ok(order_id) => {
+                            let close_side = if side == "Buy" { "Sell" } else { "Buy" };
+                            log::info!("[{}] Close submitted ({}) orderId={}", key, reason, order_id);
+                            orders.submit(
+                                &order_id,
+                                key.clone(),
+                                close_side,
+                                pos_qty,
+                                order_tracker::OrderPurpose::Exit { reason: reason.to_string() },
                             );
-                            position_closed = true;
                         }
                         Err(e) => {
-                            log::error!("[{}] Close order failed: {}", symbol, e);
+                            log::error!("[{}] Close order failed: {}", key, e);
                             tg.notify_risk_alert(&format!(
                                 "[{}] Close order failed: {}",
-                                symbol, e
+                                key, e
                             ))
                             .await;
                         }
                     }
                 }
 
-                // Si la posición sigue abierta, mostrar estado y saltar detección de entrada.
-                // Si se cerró en este ciclo, dejar que el flujo continúe para buscar nueva señal.
-                if !position_closed {
-                    let h = (now_ts - pos_entry_time) / 3600;
-                    let pnl_emoji = if pos_pnl >= 0.0 { "📈" } else { "📉" };
-                    let side_emoji = if side == "Buy" { "🟢" } else { "🔴" };
+                // La posición sigue abierta hasta que el tracker confirme el fill del cierre:
+                // mostrar estado y saltar detección de entrada (en hedge mode solo para ese lado).
+                let h = (now_ts - pos_entry_time) / 3600;
+                let pnl_emoji = if pos_pnl >= 0.0 { "📈" } else { "📉" };
+                let side_emoji = if side == "Buy" { "🟢" } else { "🔴" };
+                let state = if closing || orders.has_live_exit(&key) { "cerrando" } else { "posición abierta" };
+                status_lines.push(format!(
+                    "{side_emoji} <b>{key}</b> — {state}\n\
+                     {side} @ <code>{entry:.2}</code> → <code>{current_price:.2}</code>\n\
+                     SL: <code>{pos_sl:.2}</code> | TP: <code>{pos_tp1:.2}</code>\n\
+                     {pnl_emoji} PnL: <code>{pos_pnl:+.2} USDT</code> | {h}h abierta",
+                ));
+                open_sides.push(side);
+            }
+            // A working entry order (e.g. resting limit) blocks its side just like an open leg
+            for o in orders.live_entries().filter(|o| o.key.symbol == symbol) {
+                if let order_tracker::OrderPurpose::Entry { signal, .. } = &o.purpose {
                     status_lines.push(format!(
-                        "{side_emoji} <b>{symbol}</b> — posición abierta\n\
-                         {side} @ <code>{entry:.2}</code> → <code>{current_price:.2}</code>\n\
-                         SL: <code>{pos_sl:.2}</code> | TP: <code>{pos_tp1:.2}</code>\n\
-                         {pnl_emoji} PnL: <code>{pos_pnl:+.2} USDT</code> | {h}h abierta",
+                        "📌 <b>{}</b> — orden {} {:?} @ <code>{:.2}</code> | precio <code>{current_price:.2}</code>",
+                        o.key, o.side, o.state, signal.entry_price
                     ));
-                    continue;
                 }
-                // position_closed == true → fall through to entry detection below
+                open_sides.push(o.side.clone());
+            }
+            if !open_sides.is_empty() && !HEDGE_MODE {
+                continue;
             }
 
             // ── Look for new entry signals ────────────────────────────────────
@@ -352,16 +465,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 continue;
             }
 
-            if positions.len() >= MAX_OPEN_POSITIONS {
+            if !account_ready {
+                status_lines.push(format!(
+                    "⛔ <b>{symbol}</b> | <code>{current_price:.2}</code> | margin mode sin confirmar"
+                ));
+                continue;
+            }
+
+            let used_slots = used_slots(&positions, &orders);
+            if used_slots >= MAX_OPEN_POSITIONS {
                 status_lines.push(format!(
                     "⏸ <b>{symbol}</b> | <code>{:.2}</code> | máx posiciones ({}/{})",
-                    current_price, positions.len(), MAX_OPEN_POSITIONS
+                    current_price, used_slots, MAX_OPEN_POSITIONS
+                ));
+                continue;
+            }
+
+            // Bars may be missing until the REST backfill lands: a false
+            // adjacency would fake the three-candle FVG pattern.
+            if [&key_4h, &key_1h, &key_15m].iter().any(|k| gap_keys.contains(*k)) {
+                status_lines.push(format!(
+                    "🔄 <b>{symbol}</b> | <code>{current_price:.2}</code> | velas incompletas — backfill pendiente"
                 ));
                 continue;
             }
 
             // ── Filter 1: 4H bias via SMA(20) ────────────────────────────────
-            let bias = fvg_detector::detect_bias(candles_4h);
+            let bias = fvg_detector::detect_bias(candles_4h, &p);
             if bias == BiasDirection::Neutral {
                 log::info!("[{}] 4H bias Neutral — skip", symbol);
                 status_lines.push(format!(
@@ -370,9 +500,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 continue;
             }
 
+            // Hedge mode: the leg on the bias side is already open
+            let bias_side = if bias == BiasDirection::Bullish { "Buy" } else { "Sell" };
+            if open_sides.iter().any(|s| s == bias_side) {
+                continue;
+            }
+
             // ── Filter 2: 1H Break of Structure ──────────────────────────────
-            let candles_1h = match all_candles.get(&key_1h) {
-                Some(c) if c.len() >= 21 => c,
+            let candles_1h = match eval(&key_1h) {
+                Some(c) if c.len() > p.bos_window => c,
                 _ => {
                     let bias_label = if bias == BiasDirection::Bullish { "alcista" } else { "bajista" };
                     status_lines.push(format!(
@@ -381,7 +517,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     continue;
                 }
             };
-            let structure_ok = fvg_detector::detect_structure_break(candles_1h, &bias);
+            let structure_ok = fvg_detector::detect_structure_break(candles_1h, &bias, &p);
             if !structure_ok {
                 let bias_label = if bias == BiasDirection::Bullish { "4H↑" } else { "4H↓" };
                 log::info!("[{}] {} — 1H sin BOS aún", symbol, bias_label);
@@ -418,18 +554,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             let entry_signal: Option<(TradeSignal, &str)> = if let Some(fvg) = fvg_opt {
                 if fvg_detector::check_fvg_breakout(&fvg, last_15m, avg_volume_15m, &p) {
-                    let mut sig = build_signal(signal_type, fvg, current_price);
+                    // Limit modes enter at the FVG edge/midpoint instead of the breakout close
+                    let entry_price = position_manager::limit_entry_price(&fvg, &p)
+                        .unwrap_or(current_price);
+                    let mut sig = position_manager::build_signal(signal_type, fvg, entry_price);
                     position_manager::set_stop_loss(&mut sig, atr, &p, bb_4h.as_ref());
                     position_manager::calculate_take_profits(&mut sig, &p, bb_4h.as_ref());
-
-                    // Round SL/TP to tick_size BEFORE sizing so position qty
-                    // matches the actual SL distance the exchange will use.
-                    let tick = p.tick_size;
-                    if tick > 0.0 {
-                        sig.stop_loss     = (sig.stop_loss / tick).round() * tick;
-                        sig.take_profit_1 = (sig.take_profit_1 / tick).round() * tick;
-                        sig.take_profit_2 = (sig.take_profit_2 / tick).round() * tick;
-                    }
+                    position_manager::round_to_tick(&mut sig, p.tick_size);
 
                     sig.position_size =
                         position_manager::calculate_position_size(&sig, &metrics, &p);
@@ -463,27 +594,63 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 ),
             }
 
-            if let Some((sig, side)) = entry_signal {
+            if let Some((mut sig, side)) = entry_signal {
                 // Hard guard: TP/SL must be directionally consistent with trade side.
-                let tp_ok = if side == "Buy" {
-                    sig.take_profit_1 > sig.entry_price && sig.stop_loss < sig.entry_price
-                } else {
-                    sig.take_profit_1 < sig.entry_price && sig.stop_loss > sig.entry_price
-                };
-                if !tp_ok {
+                if !position_manager::levels_consistent(&sig, side) {
                     log::error!(
                         "[{}] TP/SL direction mismatch! side={} entry={:.6} sl={:.6} tp={:.6} fvg_type={:?}",
                         symbol, side, sig.entry_price, sig.stop_loss, sig.take_profit_1, sig.fvg_zone.fvg_type
                     );
                     continue;
                 }
+
+                // Portfolio caps: open positions, resting limit entries and
+                // orders already queued this cycle
+                let exposures: Vec<position_manager::Exposure> = positions
+                    .iter()
+                    .map(|(k, op)| position_manager::Exposure::from_position(&k.symbol, &op.data))
+                    .chain(orders.live_entries().filter(|o| !positions.contains_key(&o.key)).filter_map(|o| {
+                        match &o.purpose {
+                            order_tracker::OrderPurpose::Entry { signal, .. } => {
+                                Some(position_manager::Exposure::from_signal(&o.key.symbol, signal))
+                            }
+                            order_tracker::OrderPurpose::Exit { .. } => None,
+                        }
+                    }))
+                    .chain(pending_orders.iter().map(|o| {
+                        position_manager::Exposure::from_signal(&o.symbol, &o.signal)
+                    }))
+                    .collect();
+                match position_manager::validate_portfolio(&mut sig, &symbol, &exposures, &metrics, &p) {
+                    Err(e) => {
+                        log::warn!("[{}] Trade skipped: {}", symbol, e);
+                        continue;
+                    }
+                    Ok(Some(cap)) => log::info!(
+                        "[{}] Downsized to qty={:.4} (risk {:.2}) by {}",
+                        symbol, sig.position_size, sig.risk_amount, cap
+                    ),
+                    Ok(None) => {}
+                }
+
                 match position_manager::validate_trade(&sig, &metrics) {
                     Err(e) => {
                         log::warn!("[{}] Trade skipped: {}", symbol, e);
                     }
                     Ok(_) => {
-                        let pd = tick_decimals(p.tick_size);
-                        pending_orders.push((symbol.clone(), sig, side.to_string(), pd));
+                        if !ensure_symbol_settings(
+                            &bybit, &tg, &symbol, &mut settings_ok, &mut settings_failed,
+                        ).await {
+                            continue;
+                        }
+                        let ranking = signal_selector::CandidateMetrics::for_signal(&sig, side, candles_15m, candles_1h);
+                        pending_orders.push(PendingOrder {
+                            symbol: symbol.clone(),
+                            signal: sig,
+                            side: side.to_string(),
+                            price_decimals: tick_decimals(p.tick_size),
+                            ranking,
+                        });
                     }
                 }
             }
@@ -525,29 +692,108 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         if !pending_orders.is_empty() {
             // Verify live exchange position count before placing any order.
             // This guards against state drift (e.g. manual trades, restart races).
+            let pair_refs: Vec<&str> = trading_pairs.iter().map(|s| s.as_str()).collect();
             let exchange_open = bybit
                 .count_open_exchange_positions(&pair_refs)
                 .await;
-            if exchange_open >= MAX_OPEN_POSITIONS {
+            let working_entries = orders.live_entries().filter(|o| !positions.contains_key(&o.key)).count();
+            if exchange_open + working_entries >= MAX_OPEN_POSITIONS {
                 log::warn!(
-                    "Exchange already has {} open positions (max {}). Skipping {} pending order(s).",
-                    exchange_open, MAX_OPEN_POSITIONS, pending_orders.len()
+                    "Exchange already has {} open positions + {} working entries (max {}). Skipping {} pending order(s).",
+                    exchange_open, working_entries, MAX_OPEN_POSITIONS, pending_orders.len()
                 );
                 pending_orders.clear();
             }
 
-            // Respect the global position cap even if multiple signals fired this cycle
-            let slots_available = MAX_OPEN_POSITIONS.saturating_sub(positions.len());
-            let order_handles: Vec<_> = pending_orders
+            // Respect the global position cap even if multiple signals fired this cycle:
+            // rank candidates and penalise those correlated with the book.
+            let slots_available =
+                MAX_OPEN_POSITIONS.saturating_sub(used_slots(&positions, &orders));
+            let open_legs: Vec<signal_selector::OpenLeg> = positions
+                .iter()
+                .map(|(key, op)| (key, op.side.as_str()))
+                .chain(
+                    orders.live_entries()
+                        .filter(|o| !positions.contains_key(&o.key))
+                        .map(|o| (&o.key, o.side.as_str())),
+                )
+                .map(|(key, side)| signal_selector::OpenLeg {
+                    direction: if side == "Buy" { 1.0 } else { -1.0 },
+                    returns: all_candles
+                        .get(&format!("{}_{}", key.symbol, TF_STRUCT))
+                        .map(|c| signal_selector::rolling_returns(c, CORRELATION_WINDOW))
+                        .unwrap_or_default(),
+                })
+                .collect();
+            let candidates: Vec<signal_selector::CandidateMetrics> =
+                pending_orders.iter().map(|o| o.ranking.clone()).collect();
+            let picked = signal_selector::select(&candidates, &open_legs, slots_available);
+            if pending_orders.len() > picked.len() {
+                let ranking: Vec<String> = picked
+                    .iter()
+                    .map(|(i, score)| format!("{} ({:.3})", pending_orders[*i].symbol, score))
+                    .collect();
+                log::info!(
+                    "{} signal(s) for {} slot(s) — selected: {}",
+                    pending_orders.len(), slots_available, ranking.join(", ")
+                );
+            }
+            let mut queue: Vec<Option<PendingOrder>> = pending_orders.into_iter().map(Some).collect();
+            let selected: Vec<PendingOrder> = picked
+                .iter()
+                .filter_map(|(i, _)| queue[*i].take())
+                .collect();
+
+            let order_handles: Vec<_> = selected
                 .into_iter()
-                .take(slots_available)
-                .map(|(symbol, sig, side, price_dec)| {
+                .map(|PendingOrder { symbol, signal: sig, side, price_decimals: price_dec, .. }| {
                     let bybit = bybit.clone();
                     let tg = tg.clone();
                     tokio::spawn(async move {
+                        let key = PositionKey::for_side(&symbol, &side);
+                        if ENTRY_MODE != EntryMode::Market {
+                            return match bybit
+                                .place_limit_order(
+                                    &key,
+                                    &side,
+                                    sig.position_size,
+                                    sig.entry_price,
+                                    sig.stop_loss,
+                                    sig.take_profit_1,
+                                    price_dec,
+                                )
+                                .await
+                            {
+                                Ok(order_id) => {
+                                    tg.notify_limit_placed(
+                                        &symbol,
+                                        &side,
+                                        sig.position_size,
+                                        sig.entry_price,
+                                        limit_expiry_secs() / 60,
+                                    )
+                                    .await;
+                                    log::info!(
+                                        "[{}] {} limit qty={:.4} @ {:.2} sl={:.2} tp1={:.2} orderId={}",
+                                        key, side, sig.position_size, sig.entry_price,
+                                        sig.stop_loss, sig.take_profit_1, order_id
+                                    );
+                                    Some((key, sig, side, order_id))
+                                }
+                                Err(e) => {
+                                    log::error!("[{}] Place limit order failed: {}", symbol, e);
+                                    tg.notify_risk_alert(&format!(
+                                        "[{}] Limit order placement failed: {}",
+                                        symbol, e
+                                    ))
+                                    .await;
+                                    None
+                                }
+                            };
+                        }
                         match bybit
                             .place_order(
-                                &symbol,
+                                &key,
                                 &side,
                                 sig.position_size,
                                 sig.stop_loss,
@@ -557,19 +803,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             .await
                         {
                             Ok(order_id) => {
-                                tg.notify_trade_open(
-                                    &symbol,
-                                    &side,
-                                    sig.position_size,
-                                    sig.entry_price,
-                                    sig.stop_loss,
-                                    sig.take_profit_1,
-                                    sig.take_profit_2,
-                                )
-                                .await;
+                                // Position is created once the tracker confirms the fill
                                 log::info!(
-                                    "[{}] {} qty={:.4} entry={:.2} sl={:.2} tp1={:.2} orderId={}",
-                                    symbol,
+                                    "[{}] {} submitted qty={:.4} entry≈{:.2} sl={:.2} tp1={:.2} orderId={}",
+                                    key,
                                     side,
                                     sig.position_size,
                                     sig.entry_price,
@@ -577,7 +814,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     sig.take_profit_1,
                                     order_id
                                 );
-                                Some((symbol, sig, side, order_id))
+                                Some((key, sig, side, order_id))
                             }
                             Err(e) => {
                                 log::error!("[{}] Place order failed: {}", symbol, e);
@@ -594,19 +831,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .collect();
 
             for handle in order_handles {
-                if let Ok(Some((symbol, sig, side, order_id))) = handle.await {
-                    positions.insert(
-                        symbol.clone(),
-                        OpenPosition {
-                            data: create_position(&sig, &order_id),
-                            side,
-                        },
+                if let Ok(Some((key, sig, side, order_id))) = handle.await {
+                    let expires_at = (ENTRY_MODE != EntryMode::Market)
+                        .then(|| chrono::Utc::now().timestamp() + limit_expiry_secs());
+                    let qty = sig.position_size;
+                    orders.submit(
+                        &order_id,
+                        key,
+                        &side,
+                        qty,
+                        order_tracker::OrderPurpose::Entry { signal: sig, expires_at },
                     );
-                    metrics.trades_today += 1;
                 }
             }
         }
 
+        // ── Order lifecycle: apply fills / cancels / rejections ──────────────
+        if !orders.is_empty() {
+            sync_orders(&bybit, &tg, &mut orders, &mut positions, &all_candles, &mut metrics).await;
+        }
+
         // ── Status report every 5 minutes ────────────────────────────────────
         if last_status_ts.elapsed() >= status_interval && !status_lines.is_empty() {
             tg.notify_status(
@@ -624,6 +868,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             jemalloc_purge();
         }
 
+        // ── Symbol universe refresh ───────────────────────────────────────────
+        // Symbols with a position or a working order are never dropped.
+        if USE_ALL_PAIRS && last_universe_ts.elapsed() >= universe_interval {
+            last_universe_ts = Instant::now();
+            match universe::fetch(&bybit).await {
+                Ok(target) if !target.is_empty() => {
+                    let held: HashSet<String> = positions.keys()
+                        .map(|k| k.symbol.clone())
+                        .chain(orders.order_ids().iter()
+                            .filter_map(|id| orders.get(id))
+                            .filter(|o| !o.state.is_terminal())
+                            .map(|o| o.key.symbol.clone()))
+                        .collect();
+                    let changes = universe::diff(&trading_pairs, &target, &held);
+                    if !changes.kept.is_empty() {
+                        log::info!("Universe: kept while held: {}", changes.kept.join(", "));
+                    }
+                    if !changes.is_empty() {
+                        ws_client.remove_symbols(&changes.removed);
+                        ws_client.add_symbols(&changes.added);
+                        trading_pairs.retain(|s| !changes.removed.contains(s));
+                        trading_pairs.extend(changes.added.iter().cloned());
+                        log::info!(
+                            "Universe refreshed: +{} −{} → {} pairs",
+                            changes.added.len(), changes.removed.len(), trading_pairs.len()
+                        );
+                        tg.send(&format!(
+                            "🔄 <b>Universe refreshed</b> — {} pairs\n+ {}\n− {}",
+                            trading_pairs.len(),
+                            if changes.added.is_empty() { "—".to_string() } else { changes.added.join(", ") },
+                            if changes.removed.is_empty() { "—".to_string() } else { changes.removed.join(", ") },
+                        ))
+                        .await;
+                    }
+                }
+                Ok(_) => log::warn!("Universe refresh: no symbol passes the filters — keeping current list"),
+                Err(e) => log::warn!("Universe refresh failed: {} — keeping current list", e),
+            }
+        }
+
         // ── Daily reset at UTC midnight ───────────────────────────────────────
         if is_daily_reset_time() {
             tg.notify_daily_summary(
@@ -662,14 +946,59 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 // ── Helpers ───────────────────────────────────────────────────────────────────
 
+/// Refetches every buffer flagged in `gaps` (`BUFFER_SIZE` bars over REST) and
+/// clears the flag once merged, unless the buffer was flagged again during the
+/// fetch. A gap that REST also has is real exchange data, so it is logged and
+/// cleared rather than retried forever.
+async fn backfill_gaps(
+    bybit: bybit_api::BybitClient,
+    candle_map: websocket_handler::CandleMap,
+    gaps: websocket_handler::GapSet,
+    sem: Arc<Semaphore>,
+) {
+    let mut tick = tokio::time::interval(Duration::from_secs(GAP_BACKFILL_SECS));
+    loop {
+        tick.tick().await;
+        let keys: Vec<(String, u64)> = gaps.lock().unwrap().iter().map(|(k, seq)| (k.clone(), *seq)).collect();
+        if keys.is_empty() { continue; }
+        log::info!("Backfilling {} candle buffers", keys.len());
+
+        let handles: Vec<_> = keys.into_iter().filter_map(|(key, seq)| {
+            let (symbol, interval) = key.rsplit_once('_')?;
+            let (symbol, interval) = (symbol.to_string(), interval.to_string());
+            let (bybit, candle_map, gaps, sem) = (bybit.clone(), candle_map.clone(), gaps.clone(), sem.clone());
+            Some(tokio::spawn(async move {
+                let _permit = sem.acquire().await.unwrap();
+                match bybit.fetch_klines(&symbol, &interval, websocket_handler::BUFFER_SIZE).await {
+                    Ok(candles) => {
+                        if !websocket_handler::merge_backfill(&candle_map, &key, candles) {
+                            log::warn!("[{} {}] bar gap also present on REST — accepted as is", symbol, interval);
+                        }
+                        let mut gaps = gaps.lock().unwrap();
+                        if gaps.get(&key) == Some(&seq) {
+                            gaps.remove(&key);
+                            log::info!("[{} {}] backfilled", symbol, interval);
+                        } else {
+                            log::info!("[{} {}] backfilled, flagged again meanwhile — retrying", symbol, interval);
+                        }
+                    }
+                    Err(e) => log::warn!("[{} {}] backfill failed: {} — retrying", symbol, interval, e),
+                }
+            }))
+        }).collect();
+        for h in handles { let _ = h.await; }
+    }
+}
+
 /// Reconcile local position state with exchange after restart.
 /// Uses a single REST call to fetch all open positions (no per-symbol loop).
+/// Legs are matched by (symbol, positionIdx), so hedge-mode legs stay separate.
 /// - Orphan (exchange open, no local state) → imports into local state.
 /// - Stale (local state, exchange size=0) → clears local state.
 /// - Size mismatch → updates local qty to match exchange.
 async fn reconcile_positions(
     bybit: &bybit_api::BybitClient,
-    local_positions: &mut HashMap<String, OpenPosition>,
+    local_positions: &mut HashMap<PositionKey, OpenPosition>,
     _symbols: &[&str],
 ) {
     log::info!("Reconciling positions with exchange (single call)…");
@@ -681,25 +1010,32 @@ async fn reconcile_positions(
         }
     };
 
+    if !HEDGE_MODE && exchange_positions.keys().any(|k| k.position_idx != 0) {
+        log::error!(
+            "Exchange reports hedge-mode legs but HEDGE_MODE is off — \
+             new entries on those symbols are refused until they are back in one-way mode."
+        );
+    }
+
     // Stale locals: in local state but size=0 on exchange
-    let stale: Vec<String> = local_positions
+    let stale: Vec<PositionKey> = local_positions
         .keys()
-        .filter(|sym| !exchange_positions.contains_key(*sym))
+        .filter(|key| !exchange_positions.contains_key(*key))
         .cloned()
         .collect();
-    for sym in stale {
-        log::warn!("[{}] Local position exists but exchange size=0. Clearing.", sym);
-        local_positions.remove(&sym);
+    for key in stale {
+        log::warn!("[{}] Local position exists but exchange size=0. Clearing.", key);
+        local_positions.remove(&key);
     }
 
     // Size mismatch or orphan: positions on exchange
-    for (symbol, info) in exchange_positions {
-        match local_positions.get_mut(&symbol) {
+    for (key, info) in exchange_positions {
+        match local_positions.get_mut(&key) {
             Some(local) => {
                 if (local.data.position_size - info.size).abs() > 0.001 {
                     log::warn!(
                         "[{}] Size mismatch: local={:.4}, exchange={:.4}. Using exchange.",
-                        symbol, local.data.position_size, info.size
+                        key, local.data.position_size, info.size
                     );
                     local.data.position_size = info.size;
                 }
@@ -707,12 +1043,10 @@ async fn reconcile_positions(
             None => {
                 log::warn!(
                     "[{}] Orphan position imported: {} size={:.4} @ {:.2}",
-                    symbol, info.side, info.size, info.avg_price
-                );
-                local_positions.insert(
-                    symbol.clone(),
-                    orphan_to_open_position(&symbol, info),
+                    key, info.side, info.size, info.avg_price
                 );
+                let position = orphan_to_open_position(&key, info);
+                local_positions.insert(key, position);
             }
         }
     }
@@ -720,8 +1054,31 @@ async fn reconcile_positions(
     log::info!("Position reconciliation complete ({} open).", local_positions.len());
 }
 
+/// Cancels the bot's entry orders (`orderLinkId` with ENTRY_LINK_PREFIX) still
+/// working on the exchange. Manual orders are left alone. A filled part stays
+/// as a position and is picked up by `reconcile_positions`.
+async fn cancel_leftover_entries(bybit: &bybit_api::BybitClient) {
+    let open = match bybit.get_open_orders().await {
+        Ok(open) => open,
+        Err(e) => {
+            log::warn!("Open-order check failed: {} — leftover entries (if any) stay working.", e);
+            return;
+        }
+    };
+    for order in open.iter().filter(|o| o.order_link_id.starts_with(bybit_api::ENTRY_LINK_PREFIX)) {
+        let key = PositionKey::new(&order.symbol, order.position_idx);
+        log::warn!(
+            "[{}] Leftover entry {} {} ({:.4}/{:.4} filled) — cancelling.",
+            key, order.side, order.order_id, order.cum_exec_qty, order.qty
+        );
+        if let Err(e) = bybit.cancel_order(&order.symbol, &order.order_id).await {
+            log::error!("[{}] Could not cancel leftover entry {}: {}", key, order.order_id, e);
+        }
+    }
+}
+
 /// Build an OpenPosition from exchange data when no local state exists.
-fn orphan_to_open_position(symbol: &str, info: ExchangePositionInfo) -> OpenPosition {
+fn orphan_to_open_position(key: &PositionKey, info: ExchangePositionInfo) -> OpenPosition {
     let sl = if info.stop_loss > 0.0 { info.stop_loss } else {
         // Fallback: SL 5% away from entry in the opposite direction
         if info.side == "Buy" {
@@ -739,7 +1096,7 @@ fn orphan_to_open_position(symbol: &str, info: ExchangePositionInfo) -> OpenPosi
     };
     log::info!(
         "[{}] Imported {} @ {:.2} | sl={:.2} tp={:.2} qty={:.4}",
-        symbol, info.side, info.avg_price, sl, tp, info.size
+        key, info.side, info.avg_price, sl, tp, info.size
     );
     OpenPosition {
         side: info.side,
@@ -757,71 +1114,354 @@ fn orphan_to_open_position(symbol: &str, info: ExchangePositionInfo) -> OpenPosi
             max_favorable_excursion:   info.avg_price,
             order_id:                  String::new(),
             actual_exit:               None,
+            fees_paid:                 0.0,
+            funding_paid:              0.0,
+            entry_slippage_bps:        0.0,
         },
     }
 }
 
-fn build_signal(
-    signal_type: SignalType,
-    fvg_zone: types::FVGZone,
-    current_price: f64,
-) -> TradeSignal {
-    TradeSignal {
-        signal_type,
-        fvg_zone,
-        entry_price: current_price,
-        stop_loss: 0.0,
-        take_profit_1: 0.0,
-        take_profit_2: 0.0,
-        position_size: 0.0,
-        risk_amount: 0.0,
-        risk_reward_ratio: 0.0,
-        timestamp: chrono::Utc::now().timestamp(),
+/// True once the symbol's leverage and position mode have been confirmed.
+/// A symbol that failed is not retried before SETTINGS_RETRY_SECS.
+async fn ensure_symbol_settings(
+    bybit: &bybit_api::BybitClient,
+    tg: &telegram::TelegramBot,
+    symbol: &str,
+    settings_ok: &mut HashSet<String>,
+    settings_failed: &mut HashMap<String, Instant>,
+) -> bool {
+    if settings_ok.contains(symbol) {
+        return true;
     }
+    if settings_failed
+        .get(symbol)
+        .is_some_and(|t| t.elapsed() < Duration::from_secs(SETTINGS_RETRY_SECS))
+    {
+        log::warn!("[{}] Trade skipped: account settings not confirmed (retry pending)", symbol);
+        return false;
+    }
+    match account_setup::prepare_symbol(bybit, symbol).await {
+        Ok(_) => {
+            settings_failed.remove(symbol);
+            settings_ok.insert(symbol.to_string());
+            true
+        }
+        Err(e) => {
+            log::warn!("[{}] Trade skipped: account settings not confirmed: {}", symbol, e);
+            tg.notify_risk_alert(&format!("[{}] Refusing to trade: {}", symbol, e)).await;
+            settings_failed.insert(symbol.to_string(), Instant::now());
+            false
+        }
+    }
+}
+
+/// Lifetime of a resting limit entry: LIMIT_EXPIRY_CANDLES entry-TF candles.
+fn limit_expiry_secs() -> i64 {
+    TF_ENTRY.parse::<i64>().unwrap_or(15) * 60 * LIMIT_EXPIRY_CANDLES
+}
+
+/// Slots in use: open legs plus entry orders still working for a leg that
+/// is not open yet (a partially filled entry already shows up as a position).
+fn used_slots(
+    positions: &HashMap<PositionKey, OpenPosition>,
+    orders: &order_tracker::OrderTracker,
+) -> usize {
+    positions.len()
+        + orders.live_entries().filter(|o| !positions.contains_key(&o.key)).count()
 }
 
-fn calculate_atr(candles: &[types::Candle], period: usize) -> f64 {
-    if candles.len() < period + 1 {
-        return 0.0;
+/// Polls every tracked order and applies the exchange's view of it.
+/// - Entry fills create/grow the position at the confirmed fill price.
+/// - Exit fills realise PnL on the filled qty; the leg is removed once flat.
+/// - Working limit entries are cancelled on expiry or when the FVG is invalidated
+///   (`check_fvg_filled`); the final state is picked up on the next poll.
+/// - Terminal orders are dropped from the tracker after their last transition.
+async fn sync_orders(
+    bybit: &bybit_api::BybitClient,
+    tg: &telegram::TelegramBot,
+    orders: &mut order_tracker::OrderTracker,
+    positions: &mut HashMap<PositionKey, OpenPosition>,
+    all_candles: &HashMap<String, Vec<types::Candle>>,
+    metrics: &mut RiskMetrics,
+) {
+    use order_tracker::{OrderPurpose, OrderState};
+    let now_ts = chrono::Utc::now().timestamp();
+
+    for order_id in orders.order_ids() {
+        let Some(key) = orders.get(&order_id).map(|o| o.key.clone()) else { continue };
+        let snapshot = match bybit.get_order(&key.symbol, &order_id).await {
+            Ok(s) => s,
+            Err(e) => {
+                log::warn!("[{}] Order {} status check failed: {}", key, order_id, e);
+                continue;
+            }
+        };
+        let transition = match orders.apply(&order_id, &snapshot) {
+            Ok(t) => t,
+            Err(e) => {
+                log::warn!("[{}] Ignoring order report: {}", key, e);
+                continue;
+            }
+        };
+        // Fees of whatever just filled, from the order's executions
+        let mut fee_delta = 0.0;
+        if let Some(t) = transition.as_ref().filter(|t| t.filled_delta > 0.0) {
+            let booked = orders.get(&order_id).map(|o| o.fees).unwrap_or(0.0);
+            let total = match bybit.get_order_fees(&key.symbol, &order_id).await {
+                Ok(total) => total,
+                Err(e) => {
+                    log::warn!("[{}] Execution fees unavailable ({}) — estimating taker fee", key, e);
+                    booked + t.filled_delta * t.fill_price * TAKER_FEE_RATE
+                }
+            };
+            fee_delta = orders.record_fees(&order_id, total);
+        }
+        let Some(o) = orders.get(&order_id).cloned() else { continue };
+
+        if let Some(t) = &transition {
+            log::info!(
+                "[{}] Order {} {:?} → {:?} (filled {:.4}/{:.4})",
+                key, order_id, t.from, t.to, o.cum_exec_qty, o.qty
+            );
+            if t.filled_delta > 0.0 {
+                match &o.purpose {
+                    OrderPurpose::Entry { signal, .. } => {
+                        apply_entry_fill(tg, positions, metrics, &o, signal, fee_delta).await;
+                    }
+                    OrderPurpose::Exit { reason } => {
+                        apply_exit_fill(bybit, tg, positions, metrics, &key, t, fee_delta, reason).await;
+                    }
+                }
+            }
+        }
+
+        match o.state {
+            OrderState::New | OrderState::PartiallyFilled => {
+                // Resting limit entry: expiry / zone invalidation
+                let OrderPurpose::Entry { signal, expires_at: Some(expires_at) } = &o.purpose else {
+                    continue;
+                };
+                let current_price = all_candles
+                    .get(&format!("{}_{}", key.symbol, TF_ENTRY))
+                    .and_then(|c| c.last())
+                    .map(|c| c.close);
+                let cancel_reason = if now_ts >= *expires_at {
+                    format!("expired after {} candles", LIMIT_EXPIRY_CANDLES)
+                } else if current_price
+                    .is_some_and(|price| fvg_detector::check_fvg_filled(&signal.fvg_zone, price))
+                {
+                    "FVG invalidated".to_string()
+                } else {
+                    continue;
+                };
+                match bybit.cancel_order(&key.symbol, &order_id).await {
+                    Ok(()) => log::info!("[{}] Limit entry {} cancel requested: {}", key, order_id, cancel_reason),
+                    Err(e) => log::warn!("[{}] Cancel limit entry failed: {} — retrying next cycle", key, e),
+                }
+            }
+            OrderState::Filled => {
+                orders.remove(&order_id);
+            }
+            OrderState::Cancelled | OrderState::Rejected => {
+                let reason = if o.reject_reason.is_empty() || o.reject_reason == "EC_NoError" {
+                    format!("{:?}", o.state)
+                } else {
+                    format!("{:?} ({})", o.state, o.reject_reason)
+                };
+                match &o.purpose {
+                    OrderPurpose::Entry { expires_at: Some(_), .. } if o.cum_exec_qty <= 0.0 => {
+                        log::info!("[{}] Limit entry {} not filled: {}", key, order_id, reason);
+                        tg.notify_limit_cancelled(&key.symbol, &o.side, &reason).await;
+                    }
+                    OrderPurpose::Entry { .. } if o.cum_exec_qty <= 0.0 => {
+                        log::error!("[{}] Entry order {} ended without fill: {}", key, order_id, reason);
+                        tg.notify_risk_alert(&format!("[{}] Entry order not filled: {}", key, reason)).await;
+                    }
+                    OrderPurpose::Exit { .. } if positions.contains_key(&key) => {
+                        // Leg still open: the manage loop retries the close next cycle
+                        log::error!("[{}] Exit order {} ended early: {}", key, order_id, reason);
+                        tg.notify_risk_alert(&format!("[{}] Close order not completed: {}", key, reason)).await;
+                    }
+                    _ => {}
+                }
+                orders.remove(&order_id);
+            }
+        }
     }
-    let start = candles.len() - period - 1;
-    let mut tr_sum = 0.0;
-    for i in (start + 1)..candles.len() {
-        let curr = &candles[i];
-        let prev = &candles[i - 1];
-        let tr = (curr.high - curr.low)
-            .max((curr.high - prev.close).abs())
-            .max((curr.low - prev.close).abs());
-        tr_sum += tr;
+}
+
+/// Creates or grows the leg opened by an entry order. One entry order per leg,
+/// so size and entry price are the order's cumulative fill.
+async fn apply_entry_fill(
+    tg: &telegram::TelegramBot,
+    positions: &mut HashMap<PositionKey, OpenPosition>,
+    metrics: &mut RiskMetrics,
+    o: &order_tracker::TrackedOrder,
+    signal: &TradeSignal,
+    fee_delta: f64,
+) {
+    // Slippage vs the signal price, in bps; positive = filled worse than planned
+    let direction = if o.side == "Buy" { 1.0 } else { -1.0 };
+    let slippage_bps = if signal.entry_price > 0.0 {
+        (o.avg_price - signal.entry_price) / signal.entry_price * 1e4 * direction
+    } else {
+        0.0
+    };
+    if let Some(op) = positions.get_mut(&o.key) {
+        op.data.position_size = o.cum_exec_qty;
+        op.data.actual_entry = Some(o.avg_price);
+        op.data.risk_amount = (o.avg_price - op.data.stop_loss).abs() * o.cum_exec_qty;
+        op.data.fees_paid += fee_delta;
+        op.data.entry_slippage_bps = slippage_bps;
+        return;
     }
-    tr_sum / period as f64
+    let mut data = create_position(signal, &o.order_id);
+    data.actual_entry = Some(o.avg_price);
+    data.entry_time = chrono::Utc::now().timestamp();
+    data.position_size = o.cum_exec_qty;
+    data.risk_amount = (o.avg_price - data.stop_loss).abs() * o.cum_exec_qty;
+    data.fees_paid = fee_delta;
+    data.entry_slippage_bps = slippage_bps;
+    log::info!(
+        "[{}] Entry filled: {} qty={:.4} @ {:.2} (signal {:.2}, slippage {:+.1} bps, fee {:.4}) orderId={}",
+        o.key, o.side, o.cum_exec_qty, o.avg_price, signal.entry_price, slippage_bps, fee_delta, o.order_id
+    );
+    tg.notify_trade_open(
+        &o.key.symbol,
+        &o.side,
+        o.cum_exec_qty,
+        o.avg_price,
+        data.stop_loss,
+        data.take_profit_1,
+        data.take_profit_2,
+    )
+    .await;
+    positions.insert(o.key.clone(), OpenPosition { data, side: o.side.clone() });
+    metrics.trades_today += 1;
 }
 
-fn close_position_local(
-    positions: &mut HashMap<String, OpenPosition>,
-    symbol: &str,
+/// Realises net PnL for the qty an exit order just filled; removes the leg once flat.
+/// Funding is read from the transaction log before the final close.
+#[allow(clippy::too_many_arguments)]
+async fn apply_exit_fill(
+    bybit: &bybit_api::BybitClient,
+    tg: &telegram::TelegramBot,
+    positions: &mut HashMap<PositionKey, OpenPosition>,
     metrics: &mut RiskMetrics,
-    exit_price: f64,
+    key: &PositionKey,
+    t: &order_tracker::Transition,
+    fee_delta: f64,
+    reason: &str,
 ) {
-    if let Some(op) = positions.remove(symbol) {
-        let multiplier = if op.side == "Buy" { 1.0 } else { -1.0 };
-        let entry = op.data.actual_entry.unwrap_or(op.data.entry_price);
-        let exit = op.data.actual_exit.unwrap_or(exit_price);
-        let pnl = (exit - entry) * op.data.position_size * multiplier;
-        metrics.account_balance += pnl;
-        metrics.current_equity = metrics.account_balance;
-        metrics.daily_pnl += pnl;
-        if pnl > 0.0 {
-            metrics.wins_today += 1;
+    let Some(op) = positions.get(key) else { return };
+    let full = t.filled_delta >= op.data.position_size - 1e-9;
+    if full {
+        settle_funding(bybit, positions, key).await;
+    }
+    let Some(closed) = close_position_local(
+        positions, key, metrics, t.filled_delta, t.fill_price, fee_delta, reason,
+    ) else {
+        return;
+    };
+    if full {
+        tg.notify_trade_close(
+            &key.symbol,
+            &closed.side,
+            closed.entry,
+            closed.exit,
+            closed.net_pnl,
+            closed.fees,
+            closed.funding,
+            reason,
+        )
+        .await;
+    }
+}
+
+/// Stores the funding paid by an open leg since its entry (best effort).
+async fn settle_funding(
+    bybit: &bybit_api::BybitClient,
+    positions: &mut HashMap<PositionKey, OpenPosition>,
+    key: &PositionKey,
+) {
+    let Some(op) = positions.get(key) else { return };
+    let since_ms = op.data.entry_time * 1000;
+    match bybit.get_funding_paid(key, &op.side, since_ms).await {
+        Ok(funding) => {
+            if let Some(op) = positions.get_mut(key) {
+                op.data.funding_paid = funding;
+            }
         }
+        Err(e) => log::warn!("[{}] Funding history unavailable: {} — booked as 0", key, e),
+    }
+}
+
+/// Books the close of `qty` at `exit_price` into metrics and the trade journal.
+/// A full close also charges the entry fees and funding carried by the leg and
+/// removes it; a partial close only charges its own exit fee.
+#[allow(clippy::too_many_arguments)]
+fn close_position_local(
+    positions: &mut HashMap<PositionKey, OpenPosition>,
+    key: &PositionKey,
+    metrics: &mut RiskMetrics,
+    qty: f64,
+    exit_price: f64,
+    exit_fee: f64,
+    reason: &str,
+) -> Option<trade_journal::ClosedTrade> {
+    let op = positions.get_mut(key)?;
+    let multiplier = if op.side == "Buy" { 1.0 } else { -1.0 };
+    let entry = op.data.actual_entry.unwrap_or(op.data.entry_price);
+    let exit = op.data.actual_exit.unwrap_or(exit_price);
+    let qty = qty.min(op.data.position_size);
+    let full = qty >= op.data.position_size - 1e-9;
+
+    let gross = (exit - entry) * qty * multiplier;
+    let (fees, funding) = if full {
+        (op.data.fees_paid + exit_fee, op.data.funding_paid)
+    } else {
+        (exit_fee, 0.0)
+    };
+    let net = gross - fees - funding;
+
+    metrics.account_balance += net;
+    metrics.current_equity = metrics.account_balance;
+    metrics.daily_pnl += net;
+    if full && net > 0.0 {
+        metrics.wins_today += 1;
+    }
+
+    let closed = trade_journal::ClosedTrade {
+        key:          key.clone(),
+        side:         op.side.clone(),
+        signal_entry: op.data.entry_price,
+        entry,
+        exit,
+        qty,
+        gross_pnl:    gross,
+        fees,
+        funding,
+        net_pnl:      net,
+        slippage_bps: op.data.entry_slippage_bps,
+        reason:       if full { reason.to_string() } else { format!("{} (partial)", reason) },
+        order_id:     op.data.order_id.clone(),
+    };
+    trade_journal::record(&closed);
+
+    if full {
+        positions.remove(key);
+        log::info!(
+            "[{}] Closed @ {:.2} | PnL: {:+.2} net ({:+.2} gross, fees {:.2}, funding {:+.2}) | Balance: {:.2}",
+            key, exit, net, gross, fees, funding, metrics.account_balance
+        );
+    } else {
+        op.data.position_size -= qty;
         log::info!(
-            "[{}] Closed @ {:.2} | PnL: {:+.2} | Balance: {:.2}",
-            symbol,
-            exit,
-            pnl,
-            metrics.account_balance
+            "[{}] Partial close {:.4} @ {:.2} | PnL: {:+.2} net | remaining {:.4}",
+            key, qty, exit, net, op.data.position_size
         );
     }
+    Some(closed)
 }
 
 fn create_position(signal: &TradeSignal, order_id: &str) -> PositionData {
@@ -839,6 +1479,9 @@ fn create_position(signal: &TradeSignal, order_id: &str) -> PositionData {
         order_id: order_id.to_string(),
         actual_entry: None,
         actual_exit: None,
+        fees_paid: 0.0,
+        funding_paid: 0.0,
+        entry_slippage_bps: 0.0,
     }
 }
 
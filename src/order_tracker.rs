@@ -0,0 +1,299 @@
+//! Lifecycle of every order the bot places.
+//!
+//! New → PartiallyFilled → Filled, or Cancelled / Rejected before Filled.
+//! State only advances from exchange reports (`/v5/order/realtime` polling),
+//! so local positions are built from confirmed fills rather than from the
+//! submission response, and a rejection after acceptance leaves no trace.
+
+use std::collections::HashMap;
+
+use crate::bybit_api::OrderSnapshot;
+use crate::types::{PositionKey, TradeSignal};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderState {
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected,
+}
+
+impl OrderState {
+    /// Maps Bybit `orderStatus`. "Untriggered" counts as New; "PartiallyFilledCanceled"
+    /// and "Deactivated" end as Cancelled (any filled qty is kept in `cum_exec_qty`).
+    pub fn from_exchange(status: &str) -> Option<Self> {
+        match status {
+            "New" | "Created" | "Untriggered" => Some(OrderState::New),
+            "PartiallyFilled" => Some(OrderState::PartiallyFilled),
+            "Filled" => Some(OrderState::Filled),
+            "Cancelled" | "PartiallyFilledCanceled" | "Deactivated" => Some(OrderState::Cancelled),
+            "Rejected" => Some(OrderState::Rejected),
+            _ => None,
+        }
+    }
+
+    pub fn is_terminal(self) -> bool {
+        matches!(self, OrderState::Filled | OrderState::Cancelled | OrderState::Rejected)
+    }
+
+    fn can_become(self, next: OrderState) -> bool {
+        use OrderState::*;
+        match (self, next) {
+            (a, b) if a == b => true,
+            (New, _) => true,
+            (PartiallyFilled, Filled | Cancelled) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum OrderPurpose {
+    /// Opens a position. `expires_at` (Unix s) is set for resting limit entries.
+    Entry { signal: TradeSignal, expires_at: Option<i64> },
+    /// Closes (part of) a position.
+    Exit { reason: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct TrackedOrder {
+    pub order_id:      String,
+    pub key:           PositionKey,
+    pub side:          String,
+    pub qty:           f64,
+    pub purpose:       OrderPurpose,
+    pub state:         OrderState,
+    pub cum_exec_qty:  f64,
+    pub avg_price:     f64,
+    pub fees:          f64, // execFee booked so far (USDT)
+    pub reject_reason: String,
+}
+
+impl TrackedOrder {
+    pub fn is_entry(&self) -> bool {
+        matches!(self.purpose, OrderPurpose::Entry { .. })
+    }
+}
+
+/// Result of applying one exchange report to a tracked order.
+#[derive(Clone, Debug)]
+pub struct Transition {
+    pub from:         OrderState,
+    pub to:           OrderState,
+    pub filled_delta: f64, // qty filled since the previous report
+    pub fill_price:   f64, // average price of that delta
+}
+
+#[derive(Default)]
+pub struct OrderTracker {
+    orders: HashMap<String, TrackedOrder>,
+}
+
+impl OrderTracker {
+    /// Registers an order accepted by `/v5/order/create`. It stays New until
+    /// the exchange reports otherwise.
+    pub fn submit(&mut self, order_id: &str, key: PositionKey, side: &str, qty: f64, purpose: OrderPurpose) {
+        self.orders.insert(order_id.to_string(), TrackedOrder {
+            order_id:      order_id.to_string(),
+            key,
+            side:          side.to_string(),
+            qty,
+            purpose,
+            state:         OrderState::New,
+            cum_exec_qty:  0.0,
+            avg_price:     0.0,
+            fees:          0.0,
+            reject_reason: String::new(),
+        });
+    }
+
+    pub fn get(&self, order_id: &str) -> Option<&TrackedOrder> {
+        self.orders.get(order_id)
+    }
+
+    pub fn order_ids(&self) -> Vec<String> {
+        self.orders.keys().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    /// Entry orders still working on the exchange.
+    pub fn live_entries(&self) -> impl Iterator<Item = &TrackedOrder> {
+        self.orders.values().filter(|o| o.is_entry() && !o.state.is_terminal())
+    }
+
+    pub fn has_live_exit(&self, key: &PositionKey) -> bool {
+        self.orders
+            .values()
+            .any(|o| !o.is_entry() && !o.state.is_terminal() && &o.key == key)
+    }
+
+    /// Applies an exchange report. Returns `Ok(None)` when nothing changed and
+    /// `Err` for reports that would move the order backwards (stale data).
+    pub fn apply(&mut self, order_id: &str, snap: &OrderSnapshot) -> Result<Option<Transition>, String> {
+        let order = self
+            .orders
+            .get_mut(order_id)
+            .ok_or_else(|| format!("order {} is not tracked", order_id))?;
+        let next = OrderState::from_exchange(&snap.status)
+            .ok_or_else(|| format!("unknown order status {:?}", snap.status))?;
+
+        if !order.state.can_become(next) || snap.cum_exec_qty < order.cum_exec_qty - 1e-12 {
+            return Err(format!(
+                "stale report for {}: {:?} ({:.4} filled) → {:?} ({:.4} filled)",
+                order_id, order.state, order.cum_exec_qty, next, snap.cum_exec_qty
+            ));
+        }
+
+        let filled_delta = snap.cum_exec_qty - order.cum_exec_qty;
+        if next == order.state && filled_delta <= 0.0 {
+            return Ok(None);
+        }
+
+        // Price of just this delta, from the cumulative averages
+        let fill_price = if filled_delta > 0.0 {
+            (snap.cum_exec_qty * snap.avg_price - order.cum_exec_qty * order.avg_price) / filled_delta
+        } else {
+            0.0
+        };
+
+        let from = order.state;
+        order.state = next;
+        order.cum_exec_qty = snap.cum_exec_qty;
+        if snap.avg_price > 0.0 {
+            order.avg_price = snap.avg_price;
+        }
+        order.reject_reason = snap.reject_reason.clone();
+
+        Ok(Some(Transition { from, to: next, filled_delta: filled_delta.max(0.0), fill_price }))
+    }
+
+    /// Stores the order's cumulative execution fees and returns the part not
+    /// booked yet.
+    pub fn record_fees(&mut self, order_id: &str, total: f64) -> f64 {
+        match self.orders.get_mut(order_id) {
+            Some(o) => {
+                let delta = total - o.fees;
+                o.fees = total;
+                delta
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Drops a terminal order once its final transition has been handled.
+    pub fn remove(&mut self, order_id: &str) -> Option<TrackedOrder> {
+        self.orders.remove(order_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "order-1";
+
+    fn tracker(qty: f64) -> OrderTracker {
+        let mut t = OrderTracker::default();
+        t.submit(ID, PositionKey::new("BTCUSDT", 0), "Buy", qty, OrderPurpose::Exit { reason: "TP".to_string() });
+        t
+    }
+
+    fn snap(status: &str, cum_exec_qty: f64, avg_price: f64) -> OrderSnapshot {
+        OrderSnapshot { status: status.to_string(), cum_exec_qty, avg_price, reject_reason: String::new() }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn states_only_move_forward() {
+        use OrderState::*;
+        assert!(New.can_become(PartiallyFilled) && New.can_become(Filled) && New.can_become(Rejected));
+        assert!(PartiallyFilled.can_become(Filled) && PartiallyFilled.can_become(Cancelled));
+        assert!(!PartiallyFilled.can_become(New) && !PartiallyFilled.can_become(Rejected));
+        for terminal in [Filled, Cancelled, Rejected] {
+            assert!(terminal.can_become(terminal));
+            assert!(!terminal.can_become(New) && !terminal.can_become(PartiallyFilled));
+        }
+        assert!(!Filled.can_become(Cancelled) && !Cancelled.can_become(Filled));
+    }
+
+    #[test]
+    fn partial_then_full_fill_reports_each_delta_price() {
+        let mut t = tracker(3.0);
+
+        let first = t.apply(ID, &snap("PartiallyFilled", 1.0, 100.0)).unwrap().unwrap();
+        assert_eq!((first.from, first.to), (OrderState::New, OrderState::PartiallyFilled));
+        assert!(close(first.filled_delta, 1.0) && close(first.fill_price, 100.0));
+
+        // Average 102 over 3 → the last 2 filled at 103
+        let second = t.apply(ID, &snap("Filled", 3.0, 102.0)).unwrap().unwrap();
+        assert_eq!((second.from, second.to), (OrderState::PartiallyFilled, OrderState::Filled));
+        assert!(close(second.filled_delta, 2.0) && close(second.fill_price, 103.0));
+
+        let order = t.get(ID).unwrap();
+        assert!(order.state.is_terminal());
+        assert!(close(order.cum_exec_qty, 3.0) && close(order.avg_price, 102.0));
+    }
+
+    #[test]
+    fn repeated_report_is_no_change() {
+        let mut t = tracker(3.0);
+        t.apply(ID, &snap("PartiallyFilled", 1.0, 100.0)).unwrap();
+        assert!(t.apply(ID, &snap("PartiallyFilled", 1.0, 100.0)).unwrap().is_none());
+    }
+
+    #[test]
+    fn report_moving_backwards_is_rejected() {
+        let mut t = tracker(3.0);
+        t.apply(ID, &snap("PartiallyFilled", 1.0, 100.0)).unwrap();
+        assert!(t.apply(ID, &snap("New", 1.0, 100.0)).is_err());
+
+        t.apply(ID, &snap("Filled", 3.0, 100.0)).unwrap();
+        assert!(t.apply(ID, &snap("PartiallyFilled", 3.0, 100.0)).is_err());
+        assert!(t.apply(ID, &snap("Cancelled", 3.0, 100.0)).is_err());
+        assert_eq!(t.get(ID).unwrap().state, OrderState::Filled);
+    }
+
+    #[test]
+    fn shrinking_filled_qty_is_rejected() {
+        let mut t = tracker(3.0);
+        t.apply(ID, &snap("PartiallyFilled", 2.0, 100.0)).unwrap();
+        assert!(t.apply(ID, &snap("PartiallyFilled", 1.0, 100.0)).is_err());
+        assert!(close(t.get(ID).unwrap().cum_exec_qty, 2.0));
+    }
+
+    #[test]
+    fn partially_filled_canceled_keeps_filled_qty() {
+        let mut t = tracker(3.0);
+        t.apply(ID, &snap("PartiallyFilled", 1.0, 100.0)).unwrap();
+
+        let tr = t.apply(ID, &snap("PartiallyFilledCanceled", 1.0, 100.0)).unwrap().unwrap();
+        assert_eq!(tr.to, OrderState::Cancelled);
+        assert!(close(tr.filled_delta, 0.0));
+        let order = t.get(ID).unwrap();
+        assert!(close(order.cum_exec_qty, 1.0) && close(order.avg_price, 100.0));
+    }
+
+    #[test]
+    fn unknown_status_and_untracked_order_are_errors() {
+        let mut t = tracker(1.0);
+        assert!(t.apply(ID, &snap("Exploded", 0.0, 0.0)).is_err());
+        assert!(t.apply("other", &snap("Filled", 1.0, 100.0)).is_err());
+    }
+
+    #[test]
+    fn record_fees_returns_unbooked_delta() {
+        let mut t = tracker(3.0);
+        assert!(close(t.record_fees(ID, 0.4), 0.4));
+        assert!(close(t.record_fees(ID, 1.0), 0.6));
+        assert!(close(t.record_fees(ID, 1.0), 0.0));
+        assert!(close(t.get(ID).unwrap().fees, 1.0));
+        assert!(close(t.record_fees("other", 5.0), 0.0));
+    }
+}
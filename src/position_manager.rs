@@ -1,6 +1,38 @@
-use crate::config::{SymbolParams, MAX_RISK_PER_TRADE_PCT};
+use crate::config::{
+    is_low_cap, EntryMode, SymbolParams, ACCOUNT_BALANCE, ENTRY_MODE, MAX_EFFECTIVE_LEVERAGE, MAX_GROSS_NOTIONAL_MULT,
+    MAX_LOW_CAP_RISK_PCT, MAX_RISK_PER_TRADE_PCT, MAX_SYMBOL_NOTIONAL_PCT,
+};
 use crate::fvg_detector::BollingerBands;
-use crate::types::{FVGType, PositionData, RiskMetrics, TradeSignal};
+use crate::types::{FVGType, FVGZone, PositionData, RiskMetrics, SignalType, TradeSignal};
+
+/// Notional and stop-loss risk already committed on one symbol
+/// (open positions plus orders queued earlier in the same cycle).
+#[derive(Clone, Debug)]
+pub struct Exposure {
+    pub symbol:   String,
+    pub notional: f64,
+    pub risk:     f64,
+}
+
+impl Exposure {
+    pub fn from_position(symbol: &str, pos: &PositionData) -> Self {
+        let entry = pos.actual_entry.unwrap_or(pos.entry_price);
+        Exposure {
+            symbol:   symbol.to_string(),
+            notional: entry * pos.position_size,
+            // Orphans imported on restart carry risk_amount = 0 — derive it from the SL
+            risk:     (entry - pos.stop_loss).abs() * pos.position_size,
+        }
+    }
+
+    pub fn from_signal(symbol: &str, signal: &TradeSignal) -> Self {
+        Exposure {
+            symbol:   symbol.to_string(),
+            notional: signal.entry_price * signal.position_size,
+            risk:     signal.risk_amount,
+        }
+    }
+}
 
 pub fn calculate_position_size(signal: &TradeSignal, metrics: &RiskMetrics, p: &SymbolParams) -> f64 {
     let max_risk = metrics.account_balance * MAX_RISK_PER_TRADE_PCT;
@@ -96,6 +128,124 @@ pub fn validate_trade(signal: &TradeSignal, metrics: &RiskMetrics) -> Result<(),
     Ok(())
 }
 
+/// Checks a new signal against the portfolio-wide caps, taking every open
+/// exposure into account: gross notional, per-symbol notional, effective
+/// leverage and aggregate low-cap risk.
+///
+/// If the signal fits, returns `Ok(None)`. If it only fits at a smaller size,
+/// `signal.position_size` / `risk_amount` are reduced to the largest qty that
+/// satisfies every cap and `Ok(Some(cap))` names the binding cap. If nothing
+/// fits, returns `Err` naming the binding cap.
+pub fn validate_portfolio(
+    signal: &mut TradeSignal,
+    symbol: &str,
+    open: &[Exposure],
+    metrics: &RiskMetrics,
+    p: &SymbolParams,
+) -> Result<Option<String>, String> {
+    let entry = signal.entry_price;
+    let risk_per_unit = (entry - signal.stop_loss).abs();
+    if entry <= 0.0 || risk_per_unit <= 0.0 {
+        return Ok(None);
+    }
+
+    let gross: f64 = open.iter().map(|e| e.notional).sum();
+    let symbol_notional: f64 = open.iter()
+        .filter(|e| e.symbol == symbol)
+        .map(|e| e.notional)
+        .sum();
+
+    // Headroom (in USDT notional) left under each cap
+    let mut caps: Vec<(String, f64)> = vec![
+        (
+            format!("gross notional cap {:.0} USDT ({:.0}× initial balance, open {:.0})",
+                ACCOUNT_BALANCE * MAX_GROSS_NOTIONAL_MULT, MAX_GROSS_NOTIONAL_MULT, gross),
+            ACCOUNT_BALANCE * MAX_GROSS_NOTIONAL_MULT - gross,
+        ),
+        (
+            format!("per-symbol notional cap {:.0} USDT ({:.0}% of initial balance, open {:.0})",
+                ACCOUNT_BALANCE * MAX_SYMBOL_NOTIONAL_PCT, MAX_SYMBOL_NOTIONAL_PCT * 100.0, symbol_notional),
+            ACCOUNT_BALANCE * MAX_SYMBOL_NOTIONAL_PCT - symbol_notional,
+        ),
+        (
+            format!("effective leverage cap {:.1}× (equity {:.2}, open {:.0})",
+                MAX_EFFECTIVE_LEVERAGE, metrics.current_equity, gross),
+            MAX_EFFECTIVE_LEVERAGE * metrics.current_equity - gross,
+        ),
+    ];
+
+    if is_low_cap(symbol) {
+        let low_cap_risk: f64 = open.iter()
+            .filter(|e| is_low_cap(&e.symbol))
+            .map(|e| e.risk)
+            .sum();
+        let risk_headroom = ACCOUNT_BALANCE * MAX_LOW_CAP_RISK_PCT - low_cap_risk;
+        caps.push((
+            format!("low-cap risk cap {:.0} USDT ({:.0}% of initial balance, open risk {:.2})",
+                ACCOUNT_BALANCE * MAX_LOW_CAP_RISK_PCT, MAX_LOW_CAP_RISK_PCT * 100.0, low_cap_risk),
+            risk_headroom / risk_per_unit * entry,
+        ));
+    }
+
+    let (binding, headroom) = caps
+        .into_iter()
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .unwrap();
+
+    let notional = signal.position_size * entry;
+    if notional <= headroom {
+        return Ok(None);
+    }
+
+    let steps = (headroom.max(0.0) / entry / p.qty_step).floor();
+    let qty = steps * p.qty_step;
+    if qty <= 0.0 {
+        return Err(format!("Portfolio {} reached", binding));
+    }
+
+    signal.position_size = qty;
+    signal.risk_amount = risk_per_unit * qty;
+    Ok(Some(binding))
+}
+
+/// Limit price for the configured entry mode, or `None` for market entries.
+/// Rounded to the tick on the passive side (down for longs, up for shorts)
+/// so the post-only order rests instead of being rejected.
+pub fn limit_entry_price(zone: &FVGZone, p: &SymbolParams) -> Option<f64> {
+    let raw = match (ENTRY_MODE, &zone.fvg_type) {
+        (EntryMode::Market, _) => return None,
+        (EntryMode::LimitEdge, FVGType::Bullish) => zone.zone_high,
+        (EntryMode::LimitEdge, FVGType::Bearish) => zone.zone_low,
+        (EntryMode::LimitMid, _) => (zone.zone_high + zone.zone_low) / 2.0,
+    };
+    if p.tick_size <= 0.0 {
+        return Some(raw);
+    }
+    let steps = raw / p.tick_size;
+    let steps = match zone.fvg_type {
+        FVGType::Bullish => (steps + 1e-9).floor(),
+        FVGType::Bearish => (steps - 1e-9).ceil(),
+    };
+    Some(steps * p.tick_size)
+}
+
+/// A signal at `entry_price` with no levels or size yet; see `set_stop_loss`,
+/// `calculate_take_profits` and `calculate_position_size`.
+pub fn build_signal(signal_type: SignalType, fvg_zone: FVGZone, entry_price: f64) -> TradeSignal {
+    TradeSignal {
+        signal_type,
+        fvg_zone,
+        entry_price,
+        stop_loss: 0.0,
+        take_profit_1: 0.0,
+        take_profit_2: 0.0,
+        position_size: 0.0,
+        risk_amount: 0.0,
+        risk_reward_ratio: 0.0,
+        timestamp: chrono::Utc::now().timestamp(),
+    }
+}
+
 pub fn set_stop_loss(signal: &mut TradeSignal, atr: f64, p: &SymbolParams, bb: Option<&BollingerBands>) {
     match signal.fvg_zone.fvg_type {
         FVGType::Bullish => {
@@ -152,11 +302,164 @@ pub fn calculate_take_profits(signal: &mut TradeSignal, p: &SymbolParams, bb: Op
     };
 }
 
-pub fn update_position_pnl(position: &mut PositionData, current_price: f64) {
+/// Rounds SL/TP to the tick, before sizing, so the position qty matches the
+/// SL distance the exchange will actually use.
+pub fn round_to_tick(signal: &mut TradeSignal, tick: f64) {
+    if tick > 0.0 {
+        signal.stop_loss     = (signal.stop_loss / tick).round() * tick;
+        signal.take_profit_1 = (signal.take_profit_1 / tick).round() * tick;
+        signal.take_profit_2 = (signal.take_profit_2 / tick).round() * tick;
+    }
+}
+
+/// TP on the profit side of the entry and SL on the loss side for `side`.
+pub fn levels_consistent(signal: &TradeSignal, side: &str) -> bool {
+    if side == "Buy" {
+        signal.take_profit_1 > signal.entry_price && signal.stop_loss < signal.entry_price
+    } else {
+        signal.take_profit_1 < signal.entry_price && signal.stop_loss > signal.entry_price
+    }
+}
+
+/// Marks the leg to `current_price`. `side` is "Buy" or "Sell"; for shorts the
+/// PnL sign flips and the favorable excursion is the lowest price seen.
+pub fn update_position_pnl(position: &mut PositionData, side: &str, current_price: f64) {
     let entry = position.actual_entry.unwrap_or(position.entry_price);
-    position.unrealized_pnl = (current_price - entry) * position.position_size;
+    let is_long = side == "Buy";
+    let direction = if is_long { 1.0 } else { -1.0 };
+    position.unrealized_pnl = (current_price - entry) * position.position_size * direction;
 
-    if current_price > position.max_favorable_excursion {
+    let favorable = if is_long {
+        current_price > position.max_favorable_excursion
+    } else {
+        current_price < position.max_favorable_excursion
+    };
+    if favorable {
         position.max_favorable_excursion = current_price;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::params;
+
+    const ENTRY: f64 = 100.0;
+
+    fn signal(stop_loss: f64, qty: f64) -> TradeSignal {
+        TradeSignal {
+            signal_type:       SignalType::BuyBreakout,
+            fvg_zone:          FVGZone {
+                fvg_type: FVGType::Bullish, zone_high: 99.5, zone_low: 99.0,
+                impulse_high: 100.0, impulse_low: 98.0, created_timestamp: 0, is_filled: false,
+            },
+            entry_price:       ENTRY,
+            stop_loss,
+            take_profit_1:     ENTRY + 2.0 * (ENTRY - stop_loss),
+            take_profit_2:     ENTRY + 3.0 * (ENTRY - stop_loss),
+            position_size:     qty,
+            risk_amount:       (ENTRY - stop_loss) * qty,
+            risk_reward_ratio: 2.0,
+            timestamp:         0,
+        }
+    }
+
+    fn metrics(equity: f64) -> RiskMetrics {
+        RiskMetrics {
+            account_balance:     equity,
+            current_equity:      equity,
+            daily_pnl:           0.0,
+            max_daily_loss:      0.0,
+            drawdown_percentage: 0.0,
+            max_risk_per_trade:  0.0,
+            trading_enabled:     true,
+            trades_today:        0,
+            wins_today:          0,
+        }
+    }
+
+    fn step(qty_step: f64) -> SymbolParams {
+        params(0.001, 1.0, 8, 1.0, 2.0, 7, qty_step, 0.01)
+    }
+
+    fn exposure(symbol: &str, notional: f64, risk: f64) -> Exposure {
+        Exposure { symbol: symbol.to_string(), notional, risk }
+    }
+
+    // Equity high enough that the leverage cap never binds
+    const RICH: f64 = ACCOUNT_BALANCE * 100.0;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn signal_within_every_cap_is_untouched() {
+        let mut sig = signal(99.0, 1.0);
+        assert_eq!(validate_portfolio(&mut sig, "BTCUSDT", &[], &metrics(RICH), &step(1.0)), Ok(None));
+        assert!(close(sig.position_size, 1.0));
+    }
+
+    #[test]
+    fn gross_cap_binds_and_shrinks_qty() {
+        let open = [exposure("ETHUSDT", ACCOUNT_BALANCE * MAX_GROSS_NOTIONAL_MULT - 1_000.0, 0.0)];
+        let mut sig = signal(99.0, 50.0);
+        let binding = validate_portfolio(&mut sig, "BTCUSDT", &open, &metrics(RICH), &step(1.0)).unwrap().unwrap();
+        assert!(binding.starts_with("gross notional cap"), "{}", binding);
+        assert!(close(sig.position_size, 10.0) && close(sig.risk_amount, 10.0));
+    }
+
+    #[test]
+    fn per_symbol_cap_binds_on_the_same_symbol() {
+        let open = [exposure("BTCUSDT", ACCOUNT_BALANCE * MAX_SYMBOL_NOTIONAL_PCT - 500.0, 0.0)];
+        let mut sig = signal(99.0, 50.0);
+        let binding = validate_portfolio(&mut sig, "BTCUSDT", &open, &metrics(RICH), &step(1.0)).unwrap().unwrap();
+        assert!(binding.starts_with("per-symbol notional cap"), "{}", binding);
+        assert!(close(sig.position_size, 5.0));
+    }
+
+    #[test]
+    fn leverage_cap_binds_on_small_equity() {
+        let equity = 1_000.0 / MAX_EFFECTIVE_LEVERAGE;
+        let mut sig = signal(99.0, 50.0);
+        let binding = validate_portfolio(&mut sig, "BTCUSDT", &[], &metrics(equity), &step(1.0)).unwrap().unwrap();
+        assert!(binding.starts_with("effective leverage cap"), "{}", binding);
+        assert!(close(sig.position_size, 10.0));
+    }
+
+    #[test]
+    fn reduced_qty_is_rounded_down_to_qty_step() {
+        // 1 000 USDT of headroom = 10 units; 10 / 0.3 → 33 steps = 9.9
+        let open = [exposure("ETHUSDT", ACCOUNT_BALANCE * MAX_GROSS_NOTIONAL_MULT - 1_000.0, 0.0)];
+        let mut sig = signal(99.0, 50.0);
+        validate_portfolio(&mut sig, "BTCUSDT", &open, &metrics(RICH), &step(0.3)).unwrap();
+        assert!(close(sig.position_size, 9.9) && close(sig.risk_amount, 9.9));
+    }
+
+    #[test]
+    fn no_headroom_is_an_error() {
+        let full = [exposure("ETHUSDT", ACCOUNT_BALANCE * MAX_GROSS_NOTIONAL_MULT, 0.0)];
+        let mut sig = signal(99.0, 1.0);
+        let err = validate_portfolio(&mut sig, "BTCUSDT", &full, &metrics(RICH), &step(1.0)).unwrap_err();
+        assert!(err.contains("gross notional cap"), "{}", err);
+        assert!(close(sig.position_size, 1.0));
+
+        // Headroom left, but less than one qty step
+        let almost = [exposure("ETHUSDT", ACCOUNT_BALANCE * MAX_GROSS_NOTIONAL_MULT - 50.0, 0.0)];
+        assert!(validate_portfolio(&mut sig, "BTCUSDT", &almost, &metrics(RICH), &step(1.0)).is_err());
+    }
+
+    #[test]
+    fn low_cap_risk_headroom_is_converted_to_notional() {
+        // 20 USDT of risk left at 2 USDT per unit → 10 units → 1 000 USDT notional
+        let open = [exposure("DOGEUSDT", 0.0, ACCOUNT_BALANCE * MAX_LOW_CAP_RISK_PCT - 20.0)];
+        let mut sig = signal(98.0, 50.0);
+        let binding = validate_portfolio(&mut sig, "PEPEUSDT", &open, &metrics(RICH), &step(1.0)).unwrap().unwrap();
+        assert!(binding.starts_with("low-cap risk cap"), "{}", binding);
+        assert!(close(sig.position_size, 10.0) && close(sig.risk_amount, 20.0));
+
+        // Large caps do not count against, nor are limited by, the low-cap budget
+        let mut sig = signal(98.0, 5.0);
+        assert_eq!(validate_portfolio(&mut sig, "BTCUSDT", &open, &metrics(RICH), &step(1.0)), Ok(None));
+    }
+}
@@ -0,0 +1,134 @@
+//! Ranks the entry signals that fired in one cycle when there are more of
+//! them than free position slots.
+//!
+//! Each candidate gets a quality score (R:R, FVG size relative to ATR, volume
+//! surge on the breakout candle, liquidity). Selection is greedy: the best
+//! adjusted score is taken first, and every remaining candidate is penalised
+//! by its direction-adjusted return correlation with open positions and with
+//! the candidates already picked. Two correlated longs are penalised; a long
+//! and a short on correlated pairs (a partial hedge) are not.
+
+use crate::config::{
+    CORRELATION_PENALTY, CORRELATION_WINDOW, LIQUIDITY_REF_USDT, SCORE_W_FVG_ATR, SCORE_W_LIQUIDITY,
+    SCORE_W_RR, SCORE_W_VOLUME,
+};
+use crate::fvg_detector::calculate_atr;
+use crate::types::{Candle, TradeSignal};
+
+/// Inputs needed to score one candidate signal.
+#[derive(Clone, Debug)]
+pub struct CandidateMetrics {
+    pub risk_reward: f64,
+    pub fvg_size:    f64, // zone_high − zone_low
+    pub atr:         f64, // ATR on the entry timeframe
+    pub volume_surge: f64, // breakout volume / average volume
+    pub turnover:    f64, // average USDT turnover per entry-TF candle
+    pub direction:   f64, // +1 long, −1 short
+    pub returns:     Vec<f64>,
+}
+
+impl CandidateMetrics {
+    /// Metrics of a sized-or-not `signal` on `side`: ATR, breakout volume and
+    /// turnover from the entry-TF candles (the last one is the breakout),
+    /// returns from the structure-TF candles.
+    pub fn for_signal(signal: &TradeSignal, side: &str, entry_tf: &[Candle], struct_tf: &[Candle]) -> Self {
+        let last = entry_tf.iter().rev().take(20);
+        let avg_volume = last.clone().map(|c| c.volume).sum::<f64>() / 20.0;
+        let breakout = entry_tf.last().map_or(0.0, |c| c.volume);
+        CandidateMetrics {
+            risk_reward:  signal.risk_reward_ratio,
+            fvg_size:     signal.fvg_zone.zone_high - signal.fvg_zone.zone_low,
+            atr:          calculate_atr(entry_tf, 14),
+            volume_surge: if avg_volume > 0.0 { breakout / avg_volume } else { 0.0 },
+            turnover:     last.map(|c| c.close * c.volume).sum::<f64>() / 20.0,
+            direction:    if side == "Buy" { 1.0 } else { -1.0 },
+            returns:      rolling_returns(struct_tf, CORRELATION_WINDOW),
+        }
+    }
+}
+
+/// An already-open position, reduced to what the correlation penalty needs.
+pub struct OpenLeg {
+    pub direction: f64,
+    pub returns:   Vec<f64>,
+}
+
+/// Quality score in [0, 1] before any correlation penalty.
+pub fn base_score(m: &CandidateMetrics) -> f64 {
+    let rr   = (m.risk_reward / 3.0).clamp(0.0, 1.0);
+    let fvg  = if m.atr > 0.0 { (m.fvg_size / m.atr).clamp(0.0, 1.0) } else { 0.0 };
+    let vol  = ((m.volume_surge - 1.0) / 2.0).clamp(0.0, 1.0);
+    let liq  = if m.turnover > 0.0 {
+        ((1.0 + m.turnover).ln() / (1.0 + LIQUIDITY_REF_USDT).ln()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let w_sum = SCORE_W_RR + SCORE_W_FVG_ATR + SCORE_W_VOLUME + SCORE_W_LIQUIDITY;
+    (SCORE_W_RR * rr + SCORE_W_FVG_ATR * fvg + SCORE_W_VOLUME * vol + SCORE_W_LIQUIDITY * liq)
+        / w_sum
+}
+
+/// Simple close-to-close returns over the last `window` candles.
+pub fn rolling_returns(candles: &[Candle], window: usize) -> Vec<f64> {
+    let start = candles.len().saturating_sub(window + 1);
+    candles[start..]
+        .windows(2)
+        .filter(|w| w[0].close > 0.0)
+        .map(|w| w[1].close / w[0].close - 1.0)
+        .collect()
+}
+
+/// Pearson correlation over the overlapping tail of two return series.
+/// Returns 0.0 when there is not enough data to say anything.
+pub fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    let n = a.len().min(b.len());
+    if n < 10 { return 0.0; }
+    let a = &a[a.len() - n..];
+    let b = &b[b.len() - n..];
+    let mean_a = a.iter().sum::<f64>() / n as f64;
+    let mean_b = b.iter().sum::<f64>() / n as f64;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for i in 0..n {
+        let da = a[i] - mean_a;
+        let db = b[i] - mean_b;
+        cov   += da * db;
+        var_a += da * da;
+        var_b += db * db;
+    }
+    if var_a <= 0.0 || var_b <= 0.0 { return 0.0; }
+    cov / (var_a.sqrt() * var_b.sqrt())
+}
+
+/// Picks up to `slots` candidates. Returns indices into `candidates` in
+/// selection order, each with its penalised score.
+pub fn select(candidates: &[CandidateMetrics], open: &[OpenLeg], slots: usize) -> Vec<(usize, f64)> {
+    let base: Vec<f64> = candidates.iter().map(base_score).collect();
+    let mut picked: Vec<(usize, f64)> = Vec::new();
+
+    while picked.len() < slots.min(candidates.len()) {
+        let mut best: Option<(usize, f64)> = None;
+        for (i, c) in candidates.iter().enumerate() {
+            if picked.iter().any(|(j, _)| *j == i) { continue; }
+
+            // Same-direction correlation with the book so far (open + picked)
+            let max_corr = open.iter()
+                .map(|o| correlation(&c.returns, &o.returns) * c.direction * o.direction)
+                .chain(picked.iter().map(|(j, _)| {
+                    let o = &candidates[*j];
+                    correlation(&c.returns, &o.returns) * c.direction * o.direction
+                }))
+                .fold(0.0_f64, f64::max);
+
+            let adjusted = base[i] * (1.0 - CORRELATION_PENALTY * max_corr);
+            // Ties keep symbol-list order (strict >)
+            if best.is_none_or(|(_, s)| adjusted > s) {
+                best = Some((i, adjusted));
+            }
+        }
+        match best {
+            Some(b) => picked.push(b),
+            None => break,
+        }
+    }
+    picked
+}
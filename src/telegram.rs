@@ -41,6 +41,7 @@ impl TelegramBot {
 
     // ── Convenience helpers ──────────────────────────────────────────────────
 
+    #[allow(dead_code)]
     pub async fn notify_start(&self) {
         self.send(
             "🤖 <b>FVG Trader started</b>\nPair: BTCUSDT | TF: 4H | Capital: $10,000",
@@ -48,6 +49,7 @@ impl TelegramBot {
         .await;
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn notify_trade_open(
         &self,
         symbol: &str,
@@ -70,20 +72,47 @@ impl TelegramBot {
         self.send(&msg).await;
     }
 
+    pub async fn notify_limit_placed(
+        &self,
+        symbol: &str,
+        side: &str,
+        qty: f64,
+        price: f64,
+        expiry_min: i64,
+    ) {
+        let msg = format!(
+            "📌 <b>Limit entry — {side} {symbol}</b>\n\
+             Qty:   <code>{qty:.4}</code>\n\
+             Price: <code>{price:.2}</code>\n\
+             Expira en {expiry_min} min si no se llena",
+        );
+        self.send(&msg).await;
+    }
+
+    pub async fn notify_limit_cancelled(&self, symbol: &str, side: &str, reason: &str) {
+        let msg = format!("🗑 <b>Limit entry cancelled — {side} {symbol}</b>\nReason: {reason}");
+        self.send(&msg).await;
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn notify_trade_close(
         &self,
         symbol: &str,
         side: &str,
         entry: f64,
         exit: f64,
-        pnl: f64,
+        net_pnl: f64,
+        fees: f64,
+        funding: f64,
         reason: &str,
     ) {
-        let emoji = if pnl >= 0.0 { "✅" } else { "❌" };
+        let emoji = if net_pnl >= 0.0 { "✅" } else { "❌" };
+        let gross = net_pnl + fees + funding;
         let msg = format!(
             "{emoji} <b>Trade Closed — {side} {symbol}</b>\n\
              Entry: <code>{entry:.2}</code>  Exit: <code>{exit:.2}</code>\n\
-             PnL:   <code>{pnl:+.2} USDT</code>\n\
+             PnL:   <code>{net_pnl:+.2} USDT</code> neto\n\
+             Bruto <code>{gross:+.2}</code> | Fees <code>{fees:.2}</code> | Funding <code>{funding:+.2}</code>\n\
              Reason: {reason}",
         );
         self.send(&msg).await;
@@ -0,0 +1,58 @@
+//! Live trade journal: one CSV row per realised close (full or partial), with
+//! the gross PnL split into fees, funding and net, plus entry slippage.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use crate::config::TRADE_JOURNAL_PATH;
+use crate::types::PositionKey;
+
+const HEADER: &str = "close_time_utc,symbol,position_idx,side,signal_entry,entry,exit,qty,\
+gross_pnl,fees,funding,net_pnl,slippage_bps,reason,order_id";
+
+/// One realised close, as booked into `RiskMetrics` and the journal.
+#[derive(Clone, Debug)]
+pub struct ClosedTrade {
+    pub key:          PositionKey,
+    pub side:         String,
+    pub signal_entry: f64,
+    pub entry:        f64,
+    pub exit:         f64,
+    pub qty:          f64,
+    pub gross_pnl:    f64,
+    pub fees:         f64,
+    pub funding:      f64,
+    pub net_pnl:      f64,
+    pub slippage_bps: f64,
+    pub reason:       String,
+    pub order_id:     String,
+}
+
+/// Appends the trade to TRADE_JOURNAL_PATH, writing the header on first use.
+/// Failures are logged; the journal must never stop the trading loop.
+pub fn record(t: &ClosedTrade) {
+    if let Err(e) = append(Path::new(TRADE_JOURNAL_PATH), t) {
+        log::warn!("[{}] Trade journal write failed: {}", t.key, e);
+    }
+}
+
+fn append(path: &Path, t: &ClosedTrade) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let is_new = !path.exists();
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    if is_new {
+        writeln!(f, "{}", HEADER)?;
+    }
+    writeln!(
+        f,
+        "{},{},{},{},{:.6},{:.6},{:.6},{:.4},{:.4},{:.4},{:.4},{:.4},{:.2},{},{}",
+        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S"),
+        t.key.symbol, t.key.position_idx, t.side,
+        t.signal_entry, t.entry, t.exit, t.qty,
+        t.gross_pnl, t.fees, t.funding, t.net_pnl, t.slippage_bps,
+        t.reason.replace(',', ";"), t.order_id
+    )
+}
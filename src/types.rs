@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::config::HEDGE_MODE;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Candle {
     pub timestamp: i64,
@@ -8,6 +10,9 @@ pub struct Candle {
     pub low: f64,
     pub close: f64,
     pub volume: f64,
+    /// Bar closed (Bybit kline `confirm`); false while it is still forming.
+    #[serde(default)]
+    pub confirmed: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -15,9 +20,13 @@ pub struct FVGZone {
     pub fvg_type: FVGType,
     pub zone_high: f64,
     pub zone_low: f64,
+    #[allow(dead_code)]
     pub impulse_high: f64,
+    #[allow(dead_code)]
     pub impulse_low: f64,
+    #[allow(dead_code)]
     pub created_timestamp: i64,
+    #[allow(dead_code)]
     pub is_filled: bool,
 }
 
@@ -29,6 +38,7 @@ pub enum FVGType {
 
 #[derive(Clone, Debug)]
 pub struct TradeSignal {
+    #[allow(dead_code)]
     pub signal_type: SignalType,
     pub fvg_zone: FVGZone,
     pub entry_price: f64,
@@ -45,11 +55,47 @@ pub struct TradeSignal {
 pub enum SignalType {
     BuyBreakout,
     SellBreakout,
+    #[allow(dead_code)]
     Exit,
 }
 
+/// Identifies one position leg: the whole symbol in one-way mode
+/// (positionIdx 0), or its long (1) / short (2) leg in hedge mode.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PositionKey {
+    pub symbol: String,
+    pub position_idx: u8,
+}
+
+impl PositionKey {
+    pub fn new(symbol: &str, position_idx: u8) -> Self {
+        PositionKey { symbol: symbol.to_string(), position_idx }
+    }
+
+    /// Key for a new position on `side` ("Buy" | "Sell") under the configured mode.
+    pub fn for_side(symbol: &str, side: &str) -> Self {
+        let position_idx = match (HEDGE_MODE, side) {
+            (false, _)    => 0,
+            (true, "Buy") => 1,
+            (true, _)     => 2,
+        };
+        PositionKey::new(symbol, position_idx)
+    }
+}
+
+impl std::fmt::Display for PositionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.position_idx {
+            1 => write!(f, "{} long", self.symbol),
+            2 => write!(f, "{} short", self.symbol),
+            _ => write!(f, "{}", self.symbol),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PositionData {
+    #[allow(dead_code)]
     pub is_open: bool,
     pub entry_price: f64,
     pub entry_time: i64,
@@ -63,6 +109,9 @@ pub struct PositionData {
     pub order_id: String,
     pub actual_entry: Option<f64>,  // Fill real (de WS privado en producción)
     pub actual_exit: Option<f64>,
+    pub fees_paid: f64,           // execFee de entrada aún no realizado (se imputa al cierre final), USDT
+    pub funding_paid: f64,        // funding neto pagado mientras estuvo abierta, USDT
+    pub entry_slippage_bps: f64,  // fill vs entry_price de la señal; > 0 = en contra
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -78,6 +127,7 @@ pub struct RiskMetrics {
     pub current_equity: f64,
     pub daily_pnl: f64,
     pub max_daily_loss: f64,
+    #[allow(dead_code)]
     pub drawdown_percentage: f64,
     pub max_risk_per_trade: f64,
     pub trading_enabled: bool,
@@ -0,0 +1,181 @@
+//! Tradable symbol universe for `USE_ALL_PAIRS`: active USDT perpetuals that
+//! pass the liquidity filters in config (24h turnover, open interest, listing
+//! age, bid/ask spread), re-evaluated every `UNIVERSE_REFRESH_SECS`.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::bybit_api::{BybitClient, BybitError, LinearInstrument, LinearTicker};
+use crate::config::{
+    UNIVERSE_MAX_SPREAD_PCT, UNIVERSE_MIN_LISTING_DAYS, UNIVERSE_MIN_OPEN_INTEREST,
+    UNIVERSE_MIN_TURNOVER_24H,
+};
+
+/// Symbols that pass every filter, sorted. Instruments without a ticker, or
+/// with an empty or crossed book, are skipped.
+pub fn select(
+    instruments: &[LinearInstrument],
+    tickers: &HashMap<String, LinearTicker>,
+    now_ms: i64,
+) -> Vec<String> {
+    let min_launch = now_ms - UNIVERSE_MIN_LISTING_DAYS * 86_400_000;
+    let mut symbols: Vec<String> = instruments
+        .iter()
+        .filter(|i| i.launch_time_ms <= min_launch)
+        .filter(|i| {
+            let Some(t) = tickers.get(&i.symbol) else { return false };
+            let mid = (t.bid + t.ask) / 2.0;
+            t.turnover_24h >= UNIVERSE_MIN_TURNOVER_24H
+                && t.open_interest_value >= UNIVERSE_MIN_OPEN_INTEREST
+                && t.bid > 0.0
+                && t.ask > 0.0
+                && t.ask >= t.bid
+                && (t.ask - t.bid) / mid <= UNIVERSE_MAX_SPREAD_PCT
+        })
+        .map(|i| i.symbol.clone())
+        .collect();
+    symbols.sort();
+    symbols
+}
+
+/// Fetches instruments-info and 24h tickers and applies `select`.
+pub async fn fetch(bybit: &BybitClient) -> Result<Vec<String>, BybitError> {
+    let instruments = bybit.fetch_linear_instruments().await?;
+    let tickers = bybit.fetch_linear_tickers().await?;
+    Ok(select(&instruments, &tickers, chrono::Utc::now().timestamp_millis()))
+}
+
+/// Subscriptions needed to move from `current` to `target`.
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub added:   Vec<String>,
+    pub removed: Vec<String>,
+    /// Filtered out, but kept because they are held (position or working order).
+    pub kept:    Vec<String>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Symbols in `held` are never removed, and are added if missing (e.g. a
+/// position reconciled on a symbol outside the universe).
+pub fn diff(current: &[String], target: &[String], held: &HashSet<String>) -> Changes {
+    let current_set: HashSet<&String> = current.iter().collect();
+    let target_set: HashSet<&String> = target.iter().collect();
+    let mut changes = Changes::default();
+    for s in target.iter().chain(held.iter()) {
+        if !current_set.contains(s) && !changes.added.contains(s) {
+            changes.added.push(s.clone());
+        }
+    }
+    for s in current {
+        if target_set.contains(s) { continue; }
+        if held.contains(s) {
+            changes.kept.push(s.clone());
+        } else {
+            changes.removed.push(s.clone());
+        }
+    }
+    changes.added.sort();
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 86_400_000;
+    const NOW: i64 = 1_000 * DAY;
+
+    fn instrument(symbol: &str, age_days: i64) -> LinearInstrument {
+        LinearInstrument { symbol: symbol.to_string(), launch_time_ms: NOW - age_days * DAY, tick_size: 0.01 }
+    }
+
+    fn liquid() -> LinearTicker {
+        LinearTicker {
+            turnover_24h:        UNIVERSE_MIN_TURNOVER_24H * 2.0,
+            open_interest_value: UNIVERSE_MIN_OPEN_INTEREST * 2.0,
+            bid:                 100.0,
+            ask:                 100.01,
+        }
+    }
+
+    fn tickers(entries: &[(&str, LinearTicker)]) -> HashMap<String, LinearTicker> {
+        entries.iter().map(|(s, t)| (s.to_string(), t.clone())).collect()
+    }
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn listing_age_cutoff() {
+        let mut young = instrument("YOUNGUSDT", UNIVERSE_MIN_LISTING_DAYS);
+        young.launch_time_ms += 1;
+        let instruments = [instrument("OLDUSDT", UNIVERSE_MIN_LISTING_DAYS), young];
+        let t = tickers(&[("OLDUSDT", liquid()), ("YOUNGUSDT", liquid())]);
+        assert_eq!(select(&instruments, &t, NOW), ["OLDUSDT"]);
+    }
+
+    #[test]
+    fn missing_ticker_is_skipped() {
+        let instruments = [instrument("BTCUSDT", 365), instrument("NEWUSDT", 365)];
+        let t = tickers(&[("BTCUSDT", liquid())]);
+        assert_eq!(select(&instruments, &t, NOW), ["BTCUSDT"]);
+    }
+
+    #[test]
+    fn liquidity_filters() {
+        let thin = LinearTicker { turnover_24h: UNIVERSE_MIN_TURNOVER_24H / 2.0, ..liquid() };
+        let low_oi = LinearTicker { open_interest_value: UNIVERSE_MIN_OPEN_INTEREST / 2.0, ..liquid() };
+        let wide = LinearTicker { ask: 101.0, ..liquid() };
+        let instruments = ["AUSDT", "BUSDT", "CUSDT", "DUSDT"].map(|s| instrument(s, 365));
+        let t = tickers(&[("AUSDT", thin), ("BUSDT", low_oi), ("CUSDT", wide), ("DUSDT", liquid())]);
+        assert_eq!(select(&instruments, &t, NOW), ["DUSDT"]);
+    }
+
+    #[test]
+    fn empty_or_crossed_book_is_skipped() {
+        let no_bid = LinearTicker { bid: 0.0, ..liquid() };
+        let no_ask = LinearTicker { ask: 0.0, ..liquid() };
+        let crossed = LinearTicker { bid: 100.02, ..liquid() };
+        let instruments = ["AUSDT", "BUSDT", "CUSDT"].map(|s| instrument(s, 365));
+        let t = tickers(&[("AUSDT", no_bid), ("BUSDT", no_ask), ("CUSDT", crossed)]);
+        assert!(select(&instruments, &t, NOW).is_empty());
+    }
+
+    #[test]
+    fn select_is_sorted() {
+        let instruments = ["SOLUSDT", "BTCUSDT", "ETHUSDT"].map(|s| instrument(s, 365));
+        let t = tickers(&[("SOLUSDT", liquid()), ("BTCUSDT", liquid()), ("ETHUSDT", liquid())]);
+        assert_eq!(select(&instruments, &t, NOW), ["BTCUSDT", "ETHUSDT", "SOLUSDT"]);
+    }
+
+    #[test]
+    fn diff_adds_and_removes() {
+        let changes = diff(&strings(&["AUSDT", "BUSDT"]), &strings(&["CUSDT", "BUSDT"]), &HashSet::new());
+        assert_eq!(changes.added, ["CUSDT"]);
+        assert_eq!(changes.removed, ["AUSDT"]);
+        assert!(changes.kept.is_empty());
+        assert!(diff(&strings(&["AUSDT"]), &strings(&["AUSDT"]), &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn diff_keeps_held_symbols_that_were_filtered_out() {
+        let held: HashSet<String> = strings(&["AUSDT"]).into_iter().collect();
+        let changes = diff(&strings(&["AUSDT", "BUSDT"]), &strings(&["CUSDT"]), &held);
+        assert_eq!(changes.added, ["CUSDT"]);
+        assert_eq!(changes.removed, ["BUSDT"]);
+        assert_eq!(changes.kept, ["AUSDT"]);
+    }
+
+    #[test]
+    fn diff_adds_held_symbols_outside_the_universe_once() {
+        let held: HashSet<String> = strings(&["XUSDT", "AUSDT"]).into_iter().collect();
+        let changes = diff(&[], &strings(&["AUSDT"]), &held);
+        assert_eq!(changes.added, ["AUSDT", "XUSDT"]);
+        assert!(changes.removed.is_empty() && changes.kept.is_empty());
+    }
+}
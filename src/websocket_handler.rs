@@ -1,30 +1,153 @@
 use futures_util::{SinkExt, StreamExt};
 use serde_json::json;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use tokio::time::{interval, Duration};
+use tokio::sync::Notify;
+use tokio::time::{interval, Duration, Instant};
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 
+use crate::bybit_api::interval_ms;
+use crate::config::BYBIT_WS_URL;
 use crate::types::Candle;
+use crate::ws_recorder::{self, Recorder};
 
 const PING_INTERVAL_SECS: u64 = 20;
+/// Bybit limits 10 args per subscribe message.
+const SUBSCRIBE_CHUNK: usize = 10;
+/// Subscribe requests without an ack after this long are sent again.
+const SUBSCRIBE_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+const SUBSCRIBE_RETRY_SECS: u64 = 5;
+const SUBSCRIBE_MAX_ATTEMPTS: u32 = 5;
+/// A connection that lasted this long resets the reconnect backoff.
+const STABLE_CONNECTION: Duration = Duration::from_secs(5 * 60);
 
-const WS_URL: &str = "wss://stream.bybit.com/v5/public/linear";
-const BUFFER_SIZE: usize = 50;
+pub const BUFFER_SIZE: usize = 50;
 
 /// Shared candle buffers keyed by `"SYMBOL_INTERVAL"` (e.g. `"BTCUSDT_240"`).
 pub type CandleMap = Arc<Mutex<HashMap<String, VecDeque<Candle>>>>;
 
+/// Buffer keys that may be missing bars — a jump in bar time on insert, or a
+/// reconnect — until they are backfilled over REST (see `merge_backfill`).
+/// The value is the sequence number of the latest flag (see `flag_gap`), so a
+/// backfill only clears the flag it started from, not one raised meanwhile.
+pub type GapSet = Arc<Mutex<HashMap<String, u64>>>;
+
+static GAP_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Flags `key` in a locked `GapSet`, replacing any earlier flag.
+pub fn flag_gap(gaps: &mut HashMap<String, u64>, key: String) {
+    gaps.insert(key, GAP_SEQ.fetch_add(1, Ordering::Relaxed) + 1);
+}
+
+/// `"kline.240.BTCUSDT"` → `"BTCUSDT_240"`.
+fn topic_key(topic: &str) -> Option<String> {
+    let mut parts = topic.splitn(3, '.');
+    let (_, interval, symbol) = (parts.next()?, parts.next()?, parts.next()?);
+    Some(format!("{}_{}", symbol, interval))
+}
+
+/// Topic change queued for a live connection by `add_symbols` / `remove_symbols`.
+enum TopicChange {
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+}
+
+/// Subscribe requests of one connection, tracked by `req_id` until Bybit
+/// acknowledges them (`success` / `ret_msg`).
+#[derive(Default)]
+struct Subscriptions {
+    next_id:  u64,
+    pending:  HashMap<String, (Vec<String>, Instant)>, // req_id → (topics, sent at)
+    retry:    Vec<String>,
+    attempts: HashMap<String, u32>,
+}
+
+impl Subscriptions {
+    fn request(&mut self, topics: Vec<String>) -> Message {
+        self.next_id += 1;
+        let req_id = format!("sub-{}", self.next_id);
+        for t in &topics {
+            *self.attempts.entry(t.clone()).or_insert(0) += 1;
+        }
+        let msg = json!({ "req_id": req_id, "op": "subscribe", "args": topics });
+        self.pending.insert(req_id, (topics, Instant::now()));
+        Message::Text(msg.to_string())
+    }
+
+    /// Unsubscribe request; also forgets any retry still owed for `topics`.
+    fn unsubscribe(&mut self, topics: Vec<String>) -> Message {
+        self.next_id += 1;
+        self.retry.retain(|t| !topics.contains(t));
+        for t in &topics { self.attempts.remove(t); }
+        for (pending, _) in self.pending.values_mut() {
+            pending.retain(|t| !topics.contains(t));
+        }
+        self.pending.retain(|_, (pending, _)| !pending.is_empty());
+        let msg = json!({ "req_id": format!("unsub-{}", self.next_id), "op": "unsubscribe", "args": topics });
+        Message::Text(msg.to_string())
+    }
+
+    fn ack(&mut self, shard: usize, data: &serde_json::Value) {
+        let Some((topics, _)) = data["req_id"].as_str().and_then(|id| self.pending.remove(id)) else { return };
+        let ret_msg = data["ret_msg"].as_str().unwrap_or("");
+        if data["success"].as_bool() == Some(true) || ret_msg.contains("already subscribed") {
+            for t in &topics { self.attempts.remove(t); }
+            return;
+        }
+        log::warn!("[ws#{}] Subscribe rejected ({}): {}", shard, ret_msg, topics.join(", "));
+        self.fail(shard, topics);
+    }
+
+    /// Topics to send again: rejected ones plus requests that timed out.
+    fn due_retries(&mut self, shard: usize) -> Vec<String> {
+        let expired: Vec<String> = self.pending.iter()
+            .filter(|(_, (_, sent))| sent.elapsed() >= SUBSCRIBE_ACK_TIMEOUT)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired {
+            let (topics, _) = self.pending.remove(&id).unwrap();
+            log::warn!("[ws#{}] No subscribe ack after {:?}: {}", shard, SUBSCRIBE_ACK_TIMEOUT, topics.join(", "));
+            self.fail(shard, topics);
+        }
+        std::mem::take(&mut self.retry)
+    }
+
+    fn fail(&mut self, shard: usize, topics: Vec<String>) {
+        for t in topics {
+            if self.attempts.get(&t).copied().unwrap_or(0) >= SUBSCRIBE_MAX_ATTEMPTS {
+                log::error!("[ws#{}] Giving up on {} after {} subscribe attempts", shard, t, SUBSCRIBE_MAX_ATTEMPTS);
+                self.attempts.remove(&t);
+            } else {
+                self.retry.push(t);
+            }
+        }
+    }
+}
+
 pub struct BybitWsClient {
-    symbols: Vec<String>,
+    /// Symbol → shard. Authoritative: a (re)connecting shard subscribes to
+    /// exactly the symbols assigned to it here.
+    assignment: Mutex<BTreeMap<String, usize>>,
     intervals: Vec<String>,
+    shards: usize,
+    /// Per shard: changes for its live connection, sent when `wake` fires.
+    changes: Vec<Mutex<Vec<TopicChange>>>,
+    wake: Vec<Notify>,
     pub candle_map: CandleMap,
+    pub gaps: GapSet,
+    connected_before: Vec<AtomicBool>,
+    recorder: Option<Recorder>,
 }
 
 impl BybitWsClient {
     /// Pass all symbols and all timeframe intervals to subscribe to.
     /// Buffer keys are `"SYMBOL_INTERVAL"` (e.g. `"BTCUSDT_240"`).
-    pub fn new(symbols: &[&str], intervals: &[&str]) -> Self {
+    /// Topics are split by symbol over `shards` connections (see `connect`);
+    /// symbols can be added or removed later without reconnecting.
+    pub fn new(symbols: &[&str], intervals: &[&str], shards: usize) -> Self {
+        let shards = shards.max(1);
         let mut map = HashMap::new();
         for &s in symbols {
             for &tf in intervals {
@@ -32,105 +155,220 @@ impl BybitWsClient {
             }
         }
         BybitWsClient {
-            symbols: symbols.iter().map(|s| s.to_string()).collect(),
+            assignment: Mutex::new(symbols.iter().enumerate().map(|(i, s)| (s.to_string(), i % shards)).collect()),
             intervals: intervals.iter().map(|i| i.to_string()).collect(),
+            shards,
+            changes: (0..shards).map(|_| Mutex::new(Vec::new())).collect(),
+            wake: (0..shards).map(|_| Notify::new()).collect(),
             candle_map: Arc::new(Mutex::new(map)),
+            gaps: Arc::new(Mutex::new(HashMap::new())),
+            connected_before: (0..shards).map(|_| AtomicBool::new(false)).collect(),
+            recorder: None,
         }
     }
 
-    pub async fn connect(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let (ws_stream, _) = connect_async(WS_URL).await?;
-        log::info!("WebSocket connected to Bybit ({})", WS_URL);
+    /// Write every received frame to disk (see `ws_recorder`) before parsing it.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
 
-        let (mut write, mut read) = ws_stream.split();
+    /// Number of connections (`connect` shards) the topics are split over.
+    pub fn shard_count(&self) -> usize {
+        self.shards
+    }
 
-        // Subscribe to all symbol × interval combinations.
-        // Bybit limits 10 args per subscribe message — send in chunks.
-        let args: Vec<String> = self
-            .symbols
+    fn symbol_topics<'a>(&'a self, symbol: &'a str) -> impl Iterator<Item = String> + 'a {
+        self.intervals.iter().map(move |tf| format!("kline.{}.{}", tf, symbol))
+    }
+
+    fn symbol_keys<'a>(&'a self, symbol: &'a str) -> impl Iterator<Item = String> + 'a {
+        self.intervals.iter().map(move |tf| format!("{}_{}", symbol, tf))
+    }
+
+    /// Topics of shard `shard`: its assigned symbols × all intervals, so a
+    /// drop only affects the buffers of those symbols.
+    fn shard_topics(&self, assignment: &BTreeMap<String, usize>, shard: usize) -> Vec<String> {
+        assignment
             .iter()
-            .flat_map(|s| self.intervals.iter().map(move |tf| format!("kline.{}.{}", tf, s)))
-            .collect();
+            .filter(|(_, &s)| s == shard)
+            .flat_map(|(symbol, _)| self.symbol_topics(symbol))
+            .collect()
+    }
+
+    /// Starts streaming `symbols` (those not already subscribed), each on the
+    /// least loaded shard. Their buffers start empty and flagged in `gaps`, so
+    /// the REST backfill loads history before they are traded.
+    pub fn add_symbols(&self, symbols: &[String]) {
+        let mut assignment = self.assignment.lock().unwrap();
+        let mut load = vec![0usize; self.shards];
+        for &shard in assignment.values() { load[shard] += 1; }
+        let mut topics: Vec<Vec<String>> = vec![Vec::new(); self.shards];
+        for symbol in symbols {
+            if assignment.contains_key(symbol) { continue; }
+            let shard = (0..self.shards).min_by_key(|&i| load[i]).unwrap();
+            load[shard] += 1;
+            assignment.insert(symbol.clone(), shard);
+            {
+                let mut map = self.candle_map.lock().unwrap();
+                let mut gaps = self.gaps.lock().unwrap();
+                for key in self.symbol_keys(symbol) {
+                    map.entry(key.clone()).or_insert_with(|| VecDeque::with_capacity(BUFFER_SIZE));
+                    flag_gap(&mut gaps, key);
+                }
+            }
+            topics[shard].extend(self.symbol_topics(symbol));
+        }
+        self.queue(topics, TopicChange::Subscribe);
+    }
+
+    /// Stops streaming `symbols` and drops their buffers.
+    pub fn remove_symbols(&self, symbols: &[String]) {
+        let mut assignment = self.assignment.lock().unwrap();
+        let mut topics: Vec<Vec<String>> = vec![Vec::new(); self.shards];
+        for symbol in symbols {
+            let Some(shard) = assignment.remove(symbol) else { continue };
+            {
+                let mut map = self.candle_map.lock().unwrap();
+                let mut gaps = self.gaps.lock().unwrap();
+                for key in self.symbol_keys(symbol) {
+                    map.remove(&key);
+                    gaps.remove(&key);
+                }
+            }
+            topics[shard].extend(self.symbol_topics(symbol));
+        }
+        self.queue(topics, TopicChange::Unsubscribe);
+    }
+
+    /// Hands per-shard topics to the live connections. A shard that is down
+    /// drops its queue on reconnect and subscribes from the assignment instead.
+    fn queue(&self, topics: Vec<Vec<String>>, change: fn(Vec<String>) -> TopicChange) {
+        for (shard, topics) in topics.into_iter().enumerate() {
+            if topics.is_empty() { continue; }
+            self.changes[shard].lock().unwrap().push(change(topics));
+            self.wake[shard].notify_one();
+        }
+    }
+
+    /// Runs one connection carrying the topics of `shard` until it drops.
+    pub async fn connect(&self, shard: usize) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (ws_stream, _) = connect_async(BYBIT_WS_URL).await?;
+        log::info!("[ws#{}] WebSocket connected to Bybit ({})", shard, BYBIT_WS_URL);
+
+        let (mut write, mut read) = ws_stream.split();
 
-        log::info!("Subscribing to {} topics across {} symbols…", args.len(), self.symbols.len());
-        for chunk in args.chunks(10) {
-            let sub_msg = json!({ "op": "subscribe", "args": chunk });
-            write.send(Message::Text(sub_msg.to_string())).await?;
+        let topics = {
+            let assignment = self.assignment.lock().unwrap();
+            self.changes[shard].lock().unwrap().clear();
+            self.shard_topics(&assignment, shard)
+        };
+        log::info!("[ws#{}] Subscribing to {} topics…", shard, topics.len());
+        let mut subs = Subscriptions::default();
+        for chunk in topics.chunks(SUBSCRIBE_CHUNK) {
+            write.send(subs.request(chunk.to_vec())).await?;
+        }
+        log::info!("[ws#{}] Subscriptions sent ({} topics)", shard, topics.len());
+
+        // Bars that closed while the socket was down never arrive over WS:
+        // every buffer of this shard needs a REST backfill before it is trusted again.
+        if self.connected_before[shard].swap(true, Ordering::Relaxed) {
+            let keys: Vec<String> = topics.iter().filter_map(|t| topic_key(t)).collect();
+            let map = self.candle_map.lock().unwrap();
+            let mut gaps = self.gaps.lock().unwrap();
+            let stale: Vec<String> = keys.into_iter().filter(|k| map.get(k).is_some_and(|buf| !buf.is_empty())).collect();
+            log::warn!("[ws#{}] WebSocket reconnected — {} buffers marked for backfill", shard, stale.len());
+            for key in stale { flag_gap(&mut gaps, key); }
         }
-        log::info!("Subscriptions sent ({} topics)", args.len());
 
-        let candle_map = Arc::clone(&self.candle_map);
         let mut ping_timer = interval(Duration::from_secs(PING_INTERVAL_SECS));
         ping_timer.tick().await; // consume the immediate first tick
+        let mut sub_timer = interval(Duration::from_secs(SUBSCRIBE_RETRY_SECS));
+        sub_timer.tick().await;
 
-        let mut drop_reason: Option<String> = None;
+        let drop_reason: String;
 
         loop {
             tokio::select! {
                 _ = ping_timer.tick() => {
                     let ping = json!({"op": "ping"}).to_string();
                     if let Err(e) = write.send(Message::Text(ping)).await {
-                        log::error!("WebSocket ping error: {}", e);
-                        drop_reason = Some(format!("ping failed: {e}"));
+                        log::error!("[ws#{}] WebSocket ping error: {}", shard, e);
+                        drop_reason = format!("ping failed: {e}");
+                        break;
+                    }
+                    log::debug!("[ws#{}] WebSocket ping sent", shard);
+                }
+                _ = sub_timer.tick() => {
+                    // Unacknowledged or rejected topics go out again, one per
+                    // request so a bad topic cannot sink the others.
+                    let mut failed = None;
+                    for topic in subs.due_retries(shard) {
+                        if let Err(e) = write.send(subs.request(vec![topic])).await {
+                            failed = Some(e);
+                            break;
+                        }
+                    }
+                    if let Some(e) = failed {
+                        log::error!("[ws#{}] WebSocket subscribe error: {}", shard, e);
+                        drop_reason = format!("subscribe failed: {e}");
+                        break;
+                    }
+                }
+                _ = self.wake[shard].notified() => {
+                    let changes = std::mem::take(&mut *self.changes[shard].lock().unwrap());
+                    let mut failed = None;
+                    'send: for change in changes {
+                        let (topics, subscribe) = match change {
+                            TopicChange::Subscribe(topics) => (topics, true),
+                            TopicChange::Unsubscribe(topics) => (topics, false),
+                        };
+                        log::info!("[ws#{}] {} {} topics", shard,
+                                   if subscribe { "Subscribing to" } else { "Unsubscribing from" }, topics.len());
+                        for chunk in topics.chunks(SUBSCRIBE_CHUNK) {
+                            let msg = if subscribe { subs.request(chunk.to_vec()) } else { subs.unsubscribe(chunk.to_vec()) };
+                            if let Err(e) = write.send(msg).await {
+                                failed = Some(e);
+                                break 'send;
+                            }
+                        }
+                    }
+                    if let Some(e) = failed {
+                        log::error!("[ws#{}] WebSocket subscribe error: {}", shard, e);
+                        drop_reason = format!("subscribe failed: {e}");
                         break;
                     }
-                    log::debug!("WebSocket ping sent");
                 }
                 msg = read.next() => {
                     match msg {
                         Some(Ok(Message::Text(text))) => {
-                            if let Ok(data) = serde_json::from_str::<serde_json::Value>(&text) {
-                                // Ignore pong / op responses
-                                if data["op"].as_str() == Some("pong") {
-                                    log::debug!("WebSocket pong received");
-                                    continue;
-                                }
-                                // Bybit topic format: "kline.240.BTCUSDT"
-                                // Buffer key = "BTCUSDT_240"
-                                if let Some(topic) = data["topic"].as_str() {
-                                    let parts: Vec<&str> = topic.splitn(3, '.').collect();
-                                    if parts.len() == 3 {
-                                        let interval = parts[1]; // e.g. "240"
-                                        let symbol   = parts[2]; // e.g. "BTCUSDT"
-                                        let key = format!("{}_{}", symbol, interval);
-
-                                        if let Some(kline_arr) = data["data"].as_array() {
-                                            let mut map = candle_map.lock().unwrap();
-                                            if let Some(buf) = map.get_mut(&key) {
-                                                for k in kline_arr {
-                                                    if let Ok(candle) = Self::parse_candle(k) {
-                                                        if candle.timestamp == 0 { continue; }
-                                                        // Deduplicate: replace last candle if same timestamp (live update)
-                                                        if buf.back().map(|c| c.timestamp) == Some(candle.timestamp) {
-                                                            *buf.back_mut().unwrap() = candle;
-                                                        } else {
-                                                            buf.push_back(candle);
-                                                            if buf.len() > BUFFER_SIZE {
-                                                                buf.pop_front();
-                                                            }
-                                                            log::debug!("[{} {}] candles in buffer: {}", symbol, interval, buf.len());
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
+                            if let Some(recorder) = &self.recorder {
+                                recorder.record(&text);
+                            }
+                            let Ok(data) = serde_json::from_str::<serde_json::Value>(&text) else { continue };
+                            if data["op"].as_str() == Some("subscribe") {
+                                subs.ack(shard, &data);
+                            } else if data["op"].as_str() == Some("unsubscribe") {
+                                if data["success"].as_bool() != Some(true) {
+                                    log::warn!("[ws#{}] Unsubscribe rejected: {}", shard, data["ret_msg"].as_str().unwrap_or(""));
                                 }
+                            } else {
+                                self.apply(&data);
                             }
                         }
                         Some(Ok(Message::Close(_))) => {
-                            log::warn!("WebSocket closed by server");
-                            drop_reason = Some("closed by server".into());
+                            log::warn!("[ws#{}] WebSocket closed by server", shard);
+                            drop_reason = "closed by server".into();
                             break;
                         }
                         Some(Err(e)) => {
-                            log::error!("WebSocket error: {}", e);
-                            drop_reason = Some(format!("{e}"));
+                            log::error!("[ws#{}] WebSocket error: {}", shard, e);
+                            drop_reason = format!("{e}");
                             break;
                         }
                         None => {
-                            log::warn!("WebSocket stream ended");
-                            drop_reason = Some("stream ended".into());
+                            log::warn!("[ws#{}] WebSocket stream ended", shard);
+                            drop_reason = "stream ended".into();
                             break;
                         }
                         _ => {}
@@ -140,7 +378,97 @@ impl BybitWsClient {
         }
 
         // Always return Err so reconnect_with_backoff actually reconnects
-        Err(drop_reason.unwrap_or_else(|| "connection dropped".into()).into())
+        Err(drop_reason.into())
+    }
+
+    /// Applies one parsed frame. Shared by `connect` and `replay`, so a
+    /// replayed feed goes through exactly the live code path.
+    fn apply(&self, data: &serde_json::Value) {
+        // Ignore pong / op responses
+        if data["op"].as_str() == Some("pong") {
+            log::debug!("WebSocket pong received");
+            return;
+        }
+        // Bybit topic format: "kline.240.BTCUSDT"
+        // Buffer key = "BTCUSDT_240"
+        let Some(topic) = data["topic"].as_str() else { return };
+        let Some(key) = topic_key(topic) else { return };
+        let (symbol, interval) = key.rsplit_once('_').unwrap();
+
+        let Some(kline_arr) = data["data"].as_array() else { return };
+        let mut map = self.candle_map.lock().unwrap();
+        let Some(buf) = map.get_mut(&key) else { return };
+        for k in kline_arr {
+            if let Ok(candle) = Self::parse_candle(k) {
+                if candle.timestamp == 0 { continue; }
+                // Deduplicate: replace last candle if same timestamp (live update)
+                if buf.back().map(|c| c.timestamp) == Some(candle.timestamp) {
+                    *buf.back_mut().unwrap() = candle;
+                } else {
+                    // A newer bar means the previous one has closed, even if its
+                    // confirm message was missed.
+                    if let Some(prev) = buf.back_mut() {
+                        prev.confirmed = true;
+                        if interval_ms(interval).is_some_and(|ms| candle.timestamp - prev.timestamp > ms) {
+                            log::warn!("[{} {}] bar gap {} → {} — backfill pending",
+                                       symbol, interval, prev.timestamp, candle.timestamp);
+                            flag_gap(&mut self.gaps.lock().unwrap(), key.clone());
+                        }
+                    }
+                    buf.push_back(candle);
+                    if buf.len() > BUFFER_SIZE {
+                        buf.pop_front();
+                    }
+                    log::debug!("[{} {}] candles in buffer: {}", symbol, interval, buf.len());
+                }
+            }
+        }
+    }
+
+    /// Feeds recorded frames (see `ws_recorder`) through `apply`, in order.
+    /// `speed` 1.0 keeps the recorded timing, 10.0 runs ten times faster, 0
+    /// does not wait at all. Stops before the first frame received at or after
+    /// `until_ms`. With `discover`, a kline topic on one of the client's
+    /// intervals gets a buffer the first time it shows up, so the recorded
+    /// symbols need not be known up front (see `buffered_symbols`). Returns
+    /// the number of frames applied.
+    #[allow(dead_code)] // replay bin
+    pub async fn replay(
+        &self, files: &[PathBuf], speed: f64, until_ms: Option<i64>, discover: bool,
+    ) -> std::io::Result<usize> {
+        let mut prev_ms: Option<i64> = None;
+        let mut frames = 0;
+        for file in files {
+            for frame in ws_recorder::read_frames(file)? {
+                if until_ms.is_some_and(|u| frame.recv_ms >= u) { return Ok(frames); }
+                if let (Some(prev), true) = (prev_ms, speed > 0.0) {
+                    let wait = (frame.recv_ms - prev).max(0) as f64 / speed;
+                    if wait >= 1.0 {
+                        tokio::time::sleep(Duration::from_millis(wait as u64)).await;
+                    }
+                }
+                prev_ms = Some(frame.recv_ms);
+                if let Ok(data) = serde_json::from_str::<serde_json::Value>(&frame.text) {
+                    if discover {
+                        self.add_buffer(&data);
+                    }
+                    self.apply(&data);
+                }
+                frames += 1;
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Empty buffer for the frame's kline topic, if it is on one of the
+    /// client's intervals and has none yet.
+    fn add_buffer(&self, data: &serde_json::Value) {
+        let Some(topic) = data["topic"].as_str().filter(|t| t.starts_with("kline.")) else { return };
+        let Some(key) = topic_key(topic) else { return };
+        let interval = key.rsplit_once('_').unwrap().1;
+        if self.intervals.iter().any(|i| i == interval) {
+            self.candle_map.lock().unwrap().entry(key).or_insert_with(|| VecDeque::with_capacity(BUFFER_SIZE));
+        }
     }
 
     fn parse_candle(
@@ -154,11 +482,21 @@ impl BybitWsClient {
             low:    data["low"].as_str().unwrap_or("0").parse().unwrap_or(0.0),
             close:  data["close"].as_str().unwrap_or("0").parse().unwrap_or(0.0),
             volume: data["volume"].as_str().unwrap_or("0").parse().unwrap_or(0.0),
+            confirmed: data["confirm"].as_bool().unwrap_or(false),
         })
     }
 
+    /// Symbols with at least one buffer, sorted.
+    #[allow(dead_code)] // replay bin
+    pub fn buffered_symbols(&self) -> Vec<String> {
+        let map = self.candle_map.lock().unwrap();
+        let symbols: BTreeSet<String> = map.keys().filter_map(|k| k.rsplit_once('_')).map(|(s, _)| s.to_string()).collect();
+        symbols.into_iter().collect()
+    }
+
     /// Snapshot of candles for a specific symbol + interval.
     /// Key format: `"SYMBOL_INTERVAL"` (e.g. `"BTCUSDT_240"`).
+    #[allow(dead_code)] // replay bin
     pub fn get_candles(&self, symbol: &str, interval: &str) -> Vec<Candle> {
         let key = format!("{}_{}", symbol, interval);
         self.candle_map
@@ -170,8 +508,41 @@ impl BybitWsClient {
     }
 }
 
+/// Merges REST candles (oldest-first) into the buffer for `key`: bars are
+/// keyed by open time, fetched bars replace buffered ones except a forming
+/// REST bar over a WS copy of the same bar, and only the newest `BUFFER_SIZE`
+/// are kept. Returns false if the buffer still has a jump in bar time (a gap
+/// on the exchange itself, or a fetch that did not reach back far enough).
+pub fn merge_backfill(candle_map: &CandleMap, key: &str, fetched: Vec<Candle>) -> bool {
+    let mut map = candle_map.lock().unwrap();
+    let Some(buf) = map.get_mut(key) else { return true };
+    let mut bars: BTreeMap<i64, Candle> = buf.drain(..).map(|c| (c.timestamp, c)).collect();
+    for c in fetched {
+        if c.timestamp == 0 { continue; }
+        if !c.confirmed && bars.contains_key(&c.timestamp) { continue; }
+        bars.insert(c.timestamp, c);
+    }
+    let skip = bars.len().saturating_sub(BUFFER_SIZE);
+    buf.extend(bars.into_values().skip(skip));
+
+    let bar_ms = key.rsplit_once('_').and_then(|(_, iv)| interval_ms(iv));
+    let contiguous = bar_ms.is_none_or(|ms| {
+        buf.iter().zip(buf.iter().skip(1)).all(|(a, b)| b.timestamp - a.timestamp == ms)
+    });
+    // Only the newest bar may still be forming.
+    let n = buf.len();
+    for c in buf.iter_mut().take(n.saturating_sub(1)) {
+        c.confirmed = true;
+    }
+    contiguous
+}
+
+/// Keeps shard `shard` connected. The backoff (and retry budget) resets once
+/// a connection has stayed up for `STABLE_CONNECTION`, so only a run of
+/// failed attempts counts towards `max_retries`.
 pub async fn reconnect_with_backoff(
     client: &BybitWsClient,
+    shard: usize,
     max_retries: u32,
     initial_delay_secs: u64,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -179,17 +550,23 @@ pub async fn reconnect_with_backoff(
     let mut delay = initial_delay_secs;
 
     loop {
-        match client.connect().await {
+        let started = Instant::now();
+        match client.connect(shard).await {
             Ok(_) => return Ok(()),
             Err(e) => {
+                if started.elapsed() >= STABLE_CONNECTION {
+                    retries = 0;
+                    delay = initial_delay_secs;
+                }
                 retries += 1;
                 if retries >= max_retries {
                     return Err(
-                        format!("WS failed after {} retries: {}", retries, e).into()
+                        format!("WS shard {} failed after {} retries: {}", shard, retries, e).into()
                     );
                 }
                 log::warn!(
-                    "WS error: {}. Reconnect in {}s… ({}/{})",
+                    "[ws#{}] WS error: {}. Reconnect in {}s… ({}/{})",
+                    shard,
                     e,
                     delay,
                     retries,
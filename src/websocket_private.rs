@@ -1,11 +1,11 @@
-/// Bybit V5 private WebSocket client.
-///
-/// Streams: `order`, `execution`, `position`
-/// Only available on **live** Bybit (NOT demo).
-/// Enable with: `cargo build --release --features private-ws,jemalloc`
-///
-/// Provides real fill prices (actual_entry / actual_exit) which are more
-/// accurate than the candle-close fallback used in demo mode.
+//! Bybit V5 private WebSocket client.
+//!
+//! Streams: `order`, `execution`, `position`
+//! Only available on **live** Bybit (NOT demo).
+//! Enable with: `cargo build --release --features private-ws,jemalloc`
+//!
+//! Provides real fill prices (actual_entry / actual_exit) which are more
+//! accurate than the candle-close fallback used in demo mode.
 
 use futures_util::{SinkExt, StreamExt};
 use hmac::{Hmac, Mac};
@@ -17,15 +17,21 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc;
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 
+use crate::types::PositionKey;
+
 
 type HmacSha256 = Hmac<Sha256>;
 
 const PRIVATE_WS_URL: &str = "wss://stream.bybit.com/v5/private";
 const PING_INTERVAL_SECS: u64 = 20;
 
+/// Latest position push per leg; not read by the bot yet (fills come from
+/// the execution stream).
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct PositionState {
     pub symbol: String,
+    pub position_idx: u8,
     pub side: String,
     pub size: f64,
     pub entry_price: f64,
@@ -39,6 +45,7 @@ pub struct Execution {
     pub symbol: String,
     pub exec_price: f64,
     pub exec_qty: f64,
+    #[allow(dead_code)]
     pub exec_time: i64,
     pub exec_fee: f64,
 }
@@ -46,7 +53,7 @@ pub struct Execution {
 pub struct BybitPrivateWs {
     api_key: String,
     api_secret: String,
-    pub position_state: Arc<Mutex<HashMap<String, PositionState>>>,
+    pub position_state: Arc<Mutex<HashMap<PositionKey, PositionState>>>,
     execution_tx: mpsc::Sender<Execution>,
 }
 
@@ -107,14 +114,14 @@ impl BybitPrivateWs {
         ping_timer.tick().await; // consume immediate first tick
 
         let mut authed = false;
-        let mut drop_reason: Option<String> = None;
+        let drop_reason: String;
 
         loop {
             tokio::select! {
                 _ = ping_timer.tick() => {
                     let ping = json!({"op": "ping"}).to_string();
                     if let Err(e) = write.send(Message::Text(ping)).await {
-                        drop_reason = Some(format!("ping failed: {e}"));
+                        drop_reason = format!("ping failed: {e}");
                         break;
                     }
                 }
@@ -129,7 +136,7 @@ impl BybitPrivateWs {
                                         authed = true;
                                         write.send(Message::Text(sub_msg.to_string())).await?;
                                     } else {
-                                        drop_reason = Some("auth failed".into());
+                                        drop_reason = "auth failed".into();
                                         break;
                                     }
                                     continue;
@@ -166,13 +173,16 @@ impl BybitPrivateWs {
                                         let mut state = position_state.lock().unwrap();
                                         for item in list {
                                             let symbol = item["symbol"].as_str().unwrap_or("").to_string();
+                                            let position_idx = item["positionIdx"].as_u64().unwrap_or(0) as u8;
+                                            let key = PositionKey::new(&symbol, position_idx);
                                             let size: f64 = item["size"].as_str()
                                                 .unwrap_or("0").parse().unwrap_or(0.0);
                                             if size == 0.0 {
-                                                state.remove(&symbol);
+                                                state.remove(&key);
                                             } else {
-                                                state.insert(symbol.clone(), PositionState {
+                                                state.insert(key, PositionState {
                                                     symbol: symbol.clone(),
+                                                    position_idx,
                                                     side: item["side"].as_str().unwrap_or("").to_string(),
                                                     size,
                                                     entry_price: item["entryPrice"].as_str()
@@ -189,15 +199,15 @@ impl BybitPrivateWs {
                             }
                         }
                         Some(Ok(Message::Close(_))) => {
-                            drop_reason = Some("closed by server".into());
+                            drop_reason = "closed by server".into();
                             break;
                         }
                         Some(Err(e)) => {
-                            drop_reason = Some(format!("{e}"));
+                            drop_reason = format!("{e}");
                             break;
                         }
                         None => {
-                            drop_reason = Some("stream ended".into());
+                            drop_reason = "stream ended".into();
                             break;
                         }
                         _ => {}
@@ -207,6 +217,6 @@ impl BybitPrivateWs {
         }
 
         // Always return Err so reconnect logic can restart
-        Err(drop_reason.unwrap_or_else(|| "connection dropped".into()).into())
+        Err(drop_reason.into())
     }
 }
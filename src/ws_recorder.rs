@@ -0,0 +1,133 @@
+//! Raw WebSocket feed recording and the reader used to replay it.
+//!
+//! Every text frame is written as `recv_ms<TAB>payload` (local receive time in
+//! ms) to gzip files `{dir}/ws_{YYYYMMDD_HHMMSS}.log.gz`, rotated every
+//! `rotate_secs`. Writing happens on a dedicated thread so the socket loop
+//! never waits on disk; the encoder is sync-flushed about once a second, so a
+//! crash loses at most the last second of the current file. If the disk falls
+//! behind, frames beyond `QUEUE_CAP` are dropped (and counted) rather than
+//! buffered without bound.
+
+use chrono::{DateTime, Utc};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, TrySendError};
+use std::time::{Duration, Instant};
+
+const FLUSH_EVERY: Duration = Duration::from_secs(1);
+const QUEUE_CAP: usize = 50_000; // frames waiting for the writer thread
+const DROP_LOG_EVERY: u64 = 1_000;
+
+/// Handle to the writer thread; cheap to call from the socket loop.
+pub struct Recorder {
+    tx:      mpsc::SyncSender<(i64, String)>,
+    dropped: AtomicU64,
+}
+
+impl Recorder {
+    pub fn start(dir: impl Into<PathBuf>, rotate_secs: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        log::info!("Recording WebSocket feed to {:?}", dir);
+        let (tx, rx) = mpsc::sync_channel(QUEUE_CAP);
+        std::thread::Builder::new()
+            .name("ws-recorder".into())
+            .spawn(move || writer_loop(&dir, rotate_secs as i64 * 1000, rx))?;
+        Ok(Recorder { tx, dropped: AtomicU64::new(0) })
+    }
+
+    /// Queues `text` without blocking; drops it if the writer is behind.
+    pub fn record(&self, text: &str) {
+        if let Err(TrySendError::Full(_)) = self.tx.try_send((Utc::now().timestamp_millis(), text.to_string())) {
+            let n = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if n == 1 || n.is_multiple_of(DROP_LOG_EVERY) {
+                log::warn!("WS recorder: queue full ({} frames), {} frames dropped so far", QUEUE_CAP, n);
+            }
+        }
+    }
+}
+
+type Sink = GzEncoder<BufWriter<File>>;
+
+fn open_file(dir: &Path, ts_ms: i64) -> io::Result<Sink> {
+    let stamp = DateTime::from_timestamp_millis(ts_ms).unwrap_or_default().format("%Y%m%d_%H%M%S");
+    let file = File::create(dir.join(format!("ws_{}.log.gz", stamp)))?;
+    Ok(GzEncoder::new(BufWriter::new(file), Compression::default()))
+}
+
+fn writer_loop(dir: &Path, rotate_ms: i64, rx: mpsc::Receiver<(i64, String)>) {
+    let mut current: Option<(Sink, i64)> = None; // (file, opened at)
+    let mut last_flush = Instant::now();
+    loop {
+        match rx.recv_timeout(FLUSH_EVERY) {
+            Ok((ts, text)) => {
+                if current.as_ref().is_some_and(|(_, opened)| ts - opened >= rotate_ms) {
+                    let (sink, _) = current.take().unwrap();
+                    if let Err(e) = sink.finish().and_then(|mut w| w.flush()) {
+                        log::error!("WS recorder: closing file failed: {}", e);
+                    }
+                }
+                if current.is_none() {
+                    match open_file(dir, ts) {
+                        Ok(sink) => current = Some((sink, ts)),
+                        Err(e) => { log::error!("WS recorder: cannot create file: {}", e); continue; }
+                    }
+                }
+                let (sink, _) = current.as_mut().unwrap();
+                if let Err(e) = writeln!(sink, "{}\t{}", ts, text) {
+                    log::error!("WS recorder: write failed: {}", e);
+                    current = None;
+                    continue;
+                }
+                if last_flush.elapsed() >= FLUSH_EVERY {
+                    let _ = sink.flush();
+                    last_flush = Instant::now();
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some((sink, _)) = current.as_mut() { let _ = sink.flush(); }
+                last_flush = Instant::now();
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                if let Some((sink, _)) = current.take() { let _ = sink.finish(); }
+                return;
+            }
+        }
+    }
+}
+
+// ── Replay ────────────────────────────────────────────────────────────────────
+
+/// One recorded frame.
+pub struct Frame {
+    pub recv_ms: i64,
+    pub text:    String,
+}
+
+/// `path` itself if it is a file, otherwise its `ws_*.log.gz` files in time order.
+#[allow(dead_code)] // replay bin
+pub fn list_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if path.is_file() { return Ok(vec![path.to_path_buf()]); }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("ws_") && n.ends_with(".log.gz")))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Frames of one file in recorded order. A file cut short by a crash yields
+/// everything up to its last flush.
+#[allow(dead_code)] // replay bin
+pub fn read_frames(path: &Path) -> io::Result<impl Iterator<Item = Frame>> {
+    let reader = BufReader::new(MultiGzDecoder::new(File::open(path)?));
+    Ok(reader.lines().map_while(|l| l.ok()).filter_map(|line| {
+        let (ts, text) = line.split_once('\t')?;
+        Some(Frame { recv_ms: ts.parse().ok()?, text: text.to_string() })
+    }))
+}